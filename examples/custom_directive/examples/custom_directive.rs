@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use sqllogictest::{ColumnType, DBOutput, Directive};
+
+/// A database whose inserts are only visible after they are flushed.
+#[derive(Default)]
+pub struct FakeDB {
+    pending: usize,
+    visible: usize,
+}
+
+#[derive(Debug)]
+pub struct FakeDBError;
+
+impl std::fmt::Display for FakeDBError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for FakeDBError {}
+
+impl sqllogictest::DB for FakeDB {
+    type Error = FakeDBError;
+
+    fn run(&mut self, sql: &str) -> Result<DBOutput, FakeDBError> {
+        if sql.starts_with("insert") {
+            self.pending += 1;
+            return Ok(DBOutput::StatementComplete(Some(1)));
+        }
+        if sql == "select count(*) from t" {
+            return Ok(DBOutput::Rows {
+                names: vec![],
+                types: vec![ColumnType::Integer],
+                rows: vec![vec![self.visible.to_string()]],
+            });
+        }
+        Err(FakeDBError)
+    }
+}
+
+/// Makes the pending inserts visible.
+struct Flush;
+
+#[async_trait]
+impl Directive<FakeDB> for Flush {
+    async fn run(
+        &mut self,
+        db: &mut FakeDB,
+        _text: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        db.visible += std::mem::take(&mut db.pending);
+        Ok(())
+    }
+}
+
+fn main() {
+    let mut tester = sqllogictest::Runner::new(FakeDB::default());
+    tester.register_directive("flush", Flush);
+
+    let mut filename = PathBuf::from(file!());
+    filename.pop();
+    filename.pop();
+    filename.push("custom_directive.slt");
+
+    tester.run_file(filename).unwrap();
+}
@@ -0,0 +1,88 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use sqllogictest::{ColumnType, DBOutput, Hook, Record, TestError};
+
+pub struct FakeDB;
+
+#[derive(Debug)]
+pub struct FakeDBError;
+
+impl std::fmt::Display for FakeDBError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for FakeDBError {}
+
+impl sqllogictest::DB for FakeDB {
+    type Error = FakeDBError;
+
+    fn run(&mut self, sql: &str) -> Result<DBOutput, FakeDBError> {
+        if sql.starts_with("create") || sql.starts_with("insert") {
+            return Ok(DBOutput::StatementComplete(Some(0)));
+        }
+        if sql == "select * from t" {
+            return Ok(DBOutput::Rows {
+                names: vec![],
+                types: vec![ColumnType::Integer],
+                rows: vec![vec!["1".to_string()]],
+            });
+        }
+        Err(FakeDBError)
+    }
+}
+
+/// Logs the files and records run, e.g. to collect metrics.
+struct Log(Arc<Mutex<Vec<String>>>);
+
+#[async_trait]
+impl Hook for Log {
+    async fn before_file(&mut self, filename: &Path) {
+        let name = filename.file_name().unwrap().to_string_lossy();
+        self.0.lock().unwrap().push(format!("begin {name}"));
+    }
+
+    async fn after_file(&mut self, filename: &Path, result: &Result<(), TestError>) {
+        let name = filename.file_name().unwrap().to_string_lossy();
+        let outcome = if result.is_ok() { "ok" } else { "failed" };
+        self.0.lock().unwrap().push(format!("end {name} {outcome}"));
+    }
+
+    async fn after_record(&mut self, record: &Record, result: &Result<(), TestError>) {
+        let kind = match record {
+            Record::Statement { .. } => "statement",
+            Record::Query { .. } => "query",
+            _ => return,
+        };
+        let outcome = if result.is_ok() { "ok" } else { "failed" };
+        self.0.lock().unwrap().push(format!("{kind} {outcome}"));
+    }
+}
+
+fn main() {
+    let log = Arc::new(Mutex::new(vec![]));
+    let mut tester = sqllogictest::Runner::new(FakeDB);
+    tester.set_hook(Log(log.clone()));
+
+    let mut filename = PathBuf::from(file!());
+    filename.pop();
+    filename.pop();
+    filename.push("hook.slt");
+
+    tester.run_file(filename).unwrap();
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            "begin hook.slt",
+            "statement ok",
+            "statement ok",
+            "statement ok",
+            "query ok",
+            "end hook.slt ok",
+        ]
+    );
+}
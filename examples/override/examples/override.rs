@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use sqllogictest::{ColumnType, DBOutput};
+
+pub struct FakeDB;
+
+#[derive(Debug)]
+pub struct FakeDBError;
+
+impl std::fmt::Display for FakeDBError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for FakeDBError {}
+
+impl sqllogictest::DB for FakeDB {
+    type Error = FakeDBError;
+
+    fn run(&mut self, sql: &str) -> Result<DBOutput, FakeDBError> {
+        let rows = match sql {
+            "select * from t" => vec![vec!["3".to_string()], vec!["2".to_string()]],
+            "select count(*) from t" => vec![vec!["2".to_string()]],
+            _ => return Ok(DBOutput::StatementComplete(Some(0))),
+        };
+        Ok(DBOutput::Rows {
+            names: vec![],
+            types: vec![ColumnType::Integer],
+            rows,
+        })
+    }
+}
+
+fn main() {
+    let mut filename = PathBuf::from(file!());
+    filename.pop();
+    filename.pop();
+    filename.push("override.slt");
+
+    // Rewrite a copy, to keep the stale results of the example.
+    let copy = std::env::temp_dir().join("sqllogictest-override.slt");
+    std::fs::copy(&filename, &copy).unwrap();
+
+    let mut tester = sqllogictest::Runner::new(FakeDB);
+    tester.enable_override();
+    tester.run_file(&copy).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&copy).unwrap(),
+        "\
+# The expected results are stale, and rewritten by the example.
+statement ok
+create table t(v int)
+
+query I rowsort
+select * from t
+----
+2
+3
+
+query I
+select count(*) from t
+----
+2
+
+statement ok
+insert into t values (1)
+"
+    );
+
+    // The results match now.
+    let mut tester = sqllogictest::Runner::new(FakeDB);
+    tester.run_file(&copy).unwrap();
+
+    // Only the missing results are filled in in record mode.
+    filename.set_file_name("record.slt");
+    std::fs::copy(&filename, &copy).unwrap();
+    let mut tester = sqllogictest::Runner::new(FakeDB);
+    tester.enable_record();
+    tester.run_file(&copy).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&copy).unwrap(),
+        "\
+# The SQL of a new test, whose results are filled in by the example.
+statement ok
+create table t(v int)
+
+query I rowsort
+select * from t
+----
+2
+3
+
+query I
+select count(*) from t
+----
+2
+
+statement ok
+insert into t values (1)
+"
+    );
+
+    // Stale results still fail.
+    filename.set_file_name("override.slt");
+    std::fs::copy(&filename, &copy).unwrap();
+    let mut tester = sqllogictest::Runner::new(FakeDB);
+    tester.enable_record();
+    tester.run_file(&copy).unwrap_err();
+    assert_eq!(
+        std::fs::read_to_string(&copy).unwrap(),
+        std::fs::read_to_string(&filename).unwrap()
+    );
+    std::fs::remove_file(copy).unwrap();
+}
@@ -0,0 +1,397 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use itertools::Itertools;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqllogictest::DBOutput;
+use thiserror::Error;
+
+use crate::{DBConfig, Result};
+
+const SCOPE: &str = "https://www.googleapis.com/auth/bigquery";
+const API_URL: &str = "https://bigquery.googleapis.com/bigquery/v2";
+/// Access tokens are refreshed this long before they expire.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Google BigQuery engine using the REST `jobs.query` API, authenticated with a service account.
+///
+/// The `db` option is interpreted as `[project.]dataset`, where the project defaults to the one
+/// of the service account.
+pub struct BigQuery {
+    client: reqwest::Client,
+    key: ServiceAccountKey,
+    encoding_key: EncodingKey,
+    project: String,
+    dataset: String,
+    token: Option<(String, Instant)>,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+}
+
+#[derive(Debug, Error)]
+pub enum BigQueryError {
+    #[error("http request failed")]
+    Http(#[from] reqwest::Error),
+    #[error("jwt encoding failed")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+    #[error("sql failed {0}")]
+    Sql(String),
+    #[error("row has {values} values, but there are {columns} columns")]
+    ColumnCount { columns: usize, values: usize },
+}
+
+/// The JSON key file of a service account.
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+    project_id: String,
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryResponse {
+    schema: Option<Schema>,
+    #[serde(default)]
+    rows: Vec<Value>,
+    job_reference: Option<JobReference>,
+    #[serde(default)]
+    job_complete: bool,
+    page_token: Option<String>,
+    error: Option<ErrorProto>,
+    /// The number of rows affected by a DML statement, as an int64 string.
+    num_dml_affected_rows: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Schema {
+    fields: Vec<Field>,
+}
+
+#[derive(Deserialize)]
+struct Field {
+    #[serde(rename = "type")]
+    ty: String,
+    mode: Option<String>,
+    #[serde(default)]
+    fields: Vec<Field>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JobReference {
+    job_id: String,
+    location: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorProto {
+    message: String,
+}
+
+impl BigQuery {
+    pub(super) async fn connect(config: &DBConfig, credentials_path: &str) -> Result<Self> {
+        let key = std::fs::read_to_string(credentials_path)
+            .context(format!("failed to read credentials {credentials_path}"))?;
+        let key: ServiceAccountKey =
+            serde_json::from_str(&key).context("failed to parse service account key")?;
+        let (project, dataset) = match config.db.split_once('.') {
+            Some((project, dataset)) => (project.to_string(), dataset.to_string()),
+            None => (key.project_id.clone(), config.db.clone()),
+        };
+
+        let mut bigquery = Self {
+            client: reqwest::Client::new(),
+            encoding_key: EncodingKey::from_rsa_pem(key.private_key.as_bytes())?,
+            key,
+            project,
+            dataset,
+            token: None,
+            null_string: config.null_string.clone(),
+        };
+        bigquery
+            .access_token()
+            .await
+            .context("failed to authenticate with the service account")?;
+        Ok(bigquery)
+    }
+
+    /// Returns the cached OAuth 2.0 access token, or exchanges a signed JWT for a new one.
+    async fn access_token(&mut self) -> Result<String, BigQueryError> {
+        if let Some((token, expires_at)) = &self.token {
+            if Instant::now() + TOKEN_REFRESH_MARGIN < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let claims = Claims {
+            iss: &self.key.client_email,
+            scope: SCOPE,
+            aud: &self.key.token_uri,
+            iat: now,
+            exp: now + 3600,
+        };
+        let assertion =
+            jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &self.encoding_key)?;
+        let resp: TokenResponse = self
+            .client
+            .post(&self.key.token_uri)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", &assertion),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let expires_at = Instant::now() + Duration::from_secs(resp.expires_in);
+        self.token = Some((resp.access_token.clone(), expires_at));
+        Ok(resp.access_token)
+    }
+
+    async fn send(
+        &mut self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<QueryResponse, BigQueryError> {
+        let token = self.access_token().await?;
+        let resp = request.bearer_auth(token).send().await?;
+        let status = resp.status();
+        let resp: QueryResponse = resp.json().await?;
+        match resp.error {
+            Some(error) => Err(BigQueryError::Sql(error.message)),
+            None if !status.is_success() => Err(BigQueryError::Sql(status.to_string())),
+            None => Ok(resp),
+        }
+    }
+}
+
+/// Formats a cell (`{"v": ...}`) according to its field.
+fn value_to_str(field: &Field, value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Array(values) if field.mode.as_deref() == Some("REPEATED") => format!(
+            "{{{}}}",
+            values
+                .iter()
+                .map(|v| element_to_str(field, &v["v"]))
+                .join(",")
+        ),
+        _ => element_to_str(field, value),
+    }
+}
+
+/// Formats a single value, or an element of an `ARRAY`.
+fn element_to_str(field: &Field, value: &Value) -> String {
+    if !field.fields.is_empty() {
+        return record_to_str(&field.fields, value);
+    }
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::String(v) => match field.ty.as_str() {
+            "BOOLEAN" | "BOOL" => match v.as_str() {
+                "true" => "t".to_string(),
+                "false" => "f".to_string(),
+                _ => v.clone(),
+            },
+            // Timestamps are requested as microseconds since the epoch.
+            "TIMESTAMP" => v
+                .parse::<i64>()
+                .ok()
+                .and_then(micros_to_datetime)
+                .map(|t| t.to_string())
+                .unwrap_or_else(|| v.clone()),
+            "DATETIME" => v.replacen('T', " ", 1),
+            _ if v.is_empty() => "(empty)".to_string(),
+            // Integers, NUMERIC and BIGNUMERIC are encoded as strings, and already formatted.
+            _ => v.clone(),
+        },
+        _ => value.to_string(),
+    }
+}
+
+/// Formats a record (`{"f": [...]}`) in the postgres composite style, e.g. `(1,a)`.
+fn record_to_str(fields: &[Field], value: &Value) -> String {
+    let cells = value["f"].as_array().map(Vec::as_slice).unwrap_or_default();
+    format!(
+        "({})",
+        fields
+            .iter()
+            .zip(cells)
+            .map(|(field, cell)| value_to_str(field, &cell["v"]))
+            .join(",")
+    )
+}
+
+fn micros_to_datetime(micros: i64) -> Option<NaiveDateTime> {
+    NaiveDate::from_ymd_opt(1970, 1, 1)?
+        .and_hms_opt(0, 0, 0)?
+        .checked_add_signed(chrono::Duration::microseconds(micros))
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for BigQuery {
+    type Error = BigQueryError;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let url = format!("{}/projects/{}/queries", API_URL, self.project);
+        let body = json!({
+            "query": sql,
+            "useLegacySql": false,
+            "defaultDataset": {
+                "projectId": self.project,
+                "datasetId": self.dataset,
+            },
+            "formatOptions": { "useInt64Timestamp": true },
+        });
+        let request = self.client.post(&url).json(&body);
+        let mut resp = self.send(request).await?;
+
+        let mut output = vec![];
+        let mut fields = vec![];
+        let mut affected_rows = None;
+        loop {
+            if let Some(schema) = resp.schema.take() {
+                fields = schema.fields;
+            }
+            if let Some(count) = resp.num_dml_affected_rows.take() {
+                affected_rows = count.parse().ok();
+            }
+            for row in &resp.rows {
+                let cells = row["f"].as_array().map(Vec::as_slice).unwrap_or_default();
+                if cells.len() != fields.len() {
+                    return Err(BigQueryError::ColumnCount {
+                        columns: fields.len(),
+                        values: cells.len(),
+                    });
+                }
+                let values = fields
+                    .iter()
+                    .zip(cells)
+                    .map(|(field, cell)| match &cell["v"] {
+                        Value::Null => self.null_string.clone(),
+                        value => value_to_str(field, value),
+                    })
+                    .collect();
+                output.push(values);
+            }
+            // Keep polling until the job is complete and all pages are fetched.
+            if resp.job_complete && resp.page_token.is_none() {
+                break;
+            }
+            let job = match &resp.job_reference {
+                Some(job) => job,
+                None => return Err(BigQueryError::Sql("missing job reference".to_string())),
+            };
+            let mut query = vec![("formatOptions.useInt64Timestamp", "true".to_string())];
+            if let Some(location) = &job.location {
+                query.push(("location", location.clone()));
+            }
+            if let Some(page_token) = &resp.page_token {
+                query.push(("pageToken", page_token.clone()));
+            }
+            let request = self
+                .client
+                .get(format!("{}/{}", url, job.job_id))
+                .query(&query);
+            resp = self.send(request).await?;
+        }
+        // DDL statements return no schema.
+        match affected_rows {
+            Some(count) => Ok(DBOutput::StatementComplete(Some(count))),
+            None if fields.is_empty() => Ok(DBOutput::StatementComplete(Some(0))),
+            None => Ok(DBOutput::Rows {
+                names: vec![],
+                types: vec![],
+                rows: output,
+            }),
+        }
+    }
+
+    fn engine_name(&self) -> &str {
+        "bigquery"
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "arrays" | "decimals" | "transactions")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn field(field: Value) -> Field {
+        serde_json::from_value(field).unwrap()
+    }
+
+    #[test]
+    fn test_value_to_str() {
+        let bool_field = field(json!({"name": "b", "type": "BOOLEAN"}));
+        assert_eq!(value_to_str(&bool_field, &json!("true")), "t");
+        assert_eq!(value_to_str(&bool_field, &Value::Null), "NULL");
+        let timestamp = field(json!({"name": "t", "type": "TIMESTAMP"}));
+        assert_eq!(
+            value_to_str(&timestamp, &json!("1672531200500000")),
+            "2023-01-01 00:00:00.500"
+        );
+        let datetime = field(json!({"name": "d", "type": "DATETIME"}));
+        assert_eq!(
+            value_to_str(&datetime, &json!("2023-01-01T01:02:03")),
+            "2023-01-01 01:02:03"
+        );
+        let string = field(json!({"name": "s", "type": "STRING"}));
+        assert_eq!(value_to_str(&string, &json!("")), "(empty)");
+        let array = field(json!({"name": "a", "type": "INTEGER", "mode": "REPEATED"}));
+        assert_eq!(
+            value_to_str(&array, &json!([{"v": "1"}, {"v": "2"}])),
+            "{1,2}"
+        );
+    }
+
+    #[test]
+    fn test_record_to_str() {
+        let record = field(json!({
+            "name": "r",
+            "type": "RECORD",
+            "fields": [
+                {"name": "a", "type": "INTEGER"},
+                {"name": "b", "type": "STRING", "mode": "REPEATED"}
+            ]
+        }));
+        assert_eq!(
+            value_to_str(
+                &record,
+                &json!({"f": [{"v": "1"}, {"v": [{"v": "x"}, {"v": "y"}]}]})
+            ),
+            "(1,{x,y})"
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::io;
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use regex::Regex;
+use sqllogictest::DBOutput;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+/// The line printed by [`MARKER_SQL`], after the output of each record.
+const MARKER: &str = "__sqllogictest_end__";
+const MARKER_SQL: &str = "SELECT '__sqllogictest_end__';";
+
+/// Engine that pipes SQL to a command line client, e.g. `psql` or `mysql`.
+///
+/// The client is spawned once, and keeps its session for all the records of a file. Each record
+/// is written to its stdin, followed by `SELECT '__sqllogictest_end__';`, and the lines printed
+/// before that marker are the output of the record: one row per line, with columns split by
+/// `separator`. Errors are printed to stderr, which is merged into stdout, and are recognized by
+/// `error_pattern`, e.g. `psql:<stdin>:1: ERROR:  ...`. The client should be configured to print
+/// neither headers nor footers, to print `NULL` for null values, to keep running on error, and to
+/// flush its output after each statement, e.g. `psql -X -q -A -t -F '|' -P null=NULL` or `mysql
+/// --unbuffered -N -B`. A client which exits is spawned again for the next record.
+pub struct CliDriver {
+    cmd: String,
+    separator: String,
+    error_pattern: Regex,
+    session: Option<Session>,
+}
+
+/// A running client, see [`CliDriver`].
+struct Session {
+    // Killed on drop.
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+#[derive(Debug, Error)]
+pub enum CliDriverError {
+    #[error("io failed")]
+    Io(#[from] io::Error),
+    #[error("sql failed {0}")]
+    Sql(String),
+}
+
+type Result<T> = std::result::Result<T, CliDriverError>;
+
+impl CliDriver {
+    pub fn new(cmd: String, separator: String, error_pattern: Regex) -> Self {
+        Self {
+            cmd,
+            separator,
+            error_pattern,
+            session: None,
+        }
+    }
+
+    fn spawn(&self) -> Result<Session> {
+        let mut child = Command::new("bash")
+            .args(["-c", &format!("{{ {}\n}} 2>&1", self.cmd)])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        Ok(Session {
+            stdin: child.stdin.take().unwrap(),
+            stdout: BufReader::new(child.stdout.take().unwrap()).lines(),
+            _child: child,
+        })
+    }
+
+    /// Runs the SQL in the session, and returns the lines printed before the marker.
+    async fn run_in_session(&mut self, sql: &str) -> Result<Vec<String>> {
+        if self.session.is_none() {
+            self.session = Some(self.spawn()?);
+        }
+        let session = self.session.as_mut().unwrap();
+        // The statement must be terminated, so that the marker is not a part of it.
+        let sql = sql.trim_end();
+        let terminator = if sql.ends_with(';') { "" } else { ";" };
+        let input = format!("{sql}{terminator}\n{MARKER_SQL}\n");
+        let mut lines = vec![];
+        let result = async {
+            session.stdin.write_all(input.as_bytes()).await?;
+            session.stdin.flush().await?;
+            while let Some(line) = session.stdout.next_line().await? {
+                if line.trim() == MARKER {
+                    return Ok(true);
+                }
+                lines.push(line);
+            }
+            Ok::<_, io::Error>(false)
+        }
+        .await;
+        match result {
+            Ok(true) => Ok(lines),
+            // The client exited, e.g. on error, so the next record spawns it again.
+            Ok(false) | Err(_) => {
+                self.session = None;
+                match result {
+                    Err(e) if lines.is_empty() => Err(e.into()),
+                    _ => Err(CliDriverError::Sql(lines.join("\n"))),
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for CliDriver {
+    type Error = CliDriverError;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput> {
+        let lines = self.run_in_session(sql).await?;
+        // The error message may be followed by more lines, e.g. the context of the error.
+        if let Some(i) = lines
+            .iter()
+            .position(|line| self.error_pattern.is_match(line))
+        {
+            return Err(CliDriverError::Sql(lines[i..].join("\n")));
+        }
+
+        let rows: Vec<Vec<String>> = lines
+            .iter()
+            .map(|line| {
+                line.split(self.separator.as_str())
+                    .map(|v| if v.is_empty() { "(empty)" } else { v }.to_string())
+                    .collect()
+            })
+            .collect();
+        // The client prints nothing for statements, and the number of rows they affect is not
+        // known.
+        if rows.is_empty() {
+            return Ok(DBOutput::StatementComplete(None));
+        }
+        Ok(DBOutput::Rows {
+            names: vec![],
+            types: vec![],
+            rows,
+        })
+    }
+
+    fn engine_name(&self) -> &str {
+        "cli"
+    }
+}
@@ -0,0 +1,220 @@
+use async_trait::async_trait;
+use itertools::Itertools;
+use serde_json::Value;
+use sqllogictest::DBOutput;
+use thiserror::Error;
+
+use crate::{DBConfig, Result};
+
+/// ClickHouse engine using the HTTP interface.
+///
+/// Results are requested in `JSONCompactEachRowWithNamesAndTypes` format, so that values can be
+/// formatted according to their column type.
+pub struct ClickHouse {
+    client: reqwest::Client,
+    url: String,
+    db: String,
+    user: String,
+    pass: String,
+    /// Settings made by `SET` are only kept within a session.
+    session_id: String,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ClickHouseError {
+    #[error("http request failed")]
+    Http(#[from] reqwest::Error),
+    #[error("ser/de failed")]
+    Json(#[from] serde_json::Error),
+    #[error("sql failed {0}")]
+    Sql(String),
+    #[error("row has {values} values, but there are {columns} columns")]
+    ColumnCount { columns: usize, values: usize },
+}
+
+impl ClickHouse {
+    pub(super) async fn connect(config: &DBConfig) -> Result<Self> {
+        let (host, port) = config.addr();
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: format!("http://{host}:{port}/"),
+            db: config.db.clone(),
+            user: config.user.clone(),
+            pass: config.pass.clone(),
+            session_id: format!("sqllogictest-{}", rand::random::<u64>()),
+            null_string: config.null_string.clone(),
+        })
+    }
+}
+
+/// Strips wrappers which do not affect the formatting, e.g. `Nullable(String)` -> `String`.
+fn strip_type_wrapper(ty: &str) -> &str {
+    for wrapper in ["Nullable(", "LowCardinality("] {
+        if let Some(inner) = ty.strip_prefix(wrapper).and_then(|t| t.strip_suffix(')')) {
+            return strip_type_wrapper(inner);
+        }
+    }
+    ty
+}
+
+fn value_to_str(ty: &str, value: &Value) -> String {
+    let ty = strip_type_wrapper(ty);
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(true) => "t".to_string(),
+        Value::Bool(false) => "f".to_string(),
+        Value::Array(values) => match ty.strip_prefix("Array(").and_then(|t| t.strip_suffix(')')) {
+            Some(elem_ty) => format!(
+                "{{{}}}",
+                values.iter().map(|v| value_to_str(elem_ty, v)).join(",")
+            ),
+            // Tuples are also encoded as JSON arrays.
+            None => format!("({})", values.iter().map(|v| value_to_str("", v)).join(",")),
+        },
+        Value::String(v) if v.is_empty() => "(empty)".to_string(),
+        // 64-bit integers, decimals, dates and `DateTime64` are quoted by ClickHouse.
+        Value::String(v) => v.clone(),
+        Value::Number(v) => v.to_string(),
+        Value::Object(_) => value.to_string(),
+    }
+}
+
+/// Parses a response in `JSONCompactEachRowWithNamesAndTypes` format, with `NULL`s written as
+/// `null_string`.
+fn parse_output(body: &str, null_string: &str) -> Result<DBOutput, ClickHouseError> {
+    let mut output = vec![];
+    let mut lines = body.lines();
+    // The first line is column names, and the second line is column types. Statements return
+    // nothing, and the number of rows they affect is not known.
+    let (names, types): (Vec<String>, Vec<String>) = match (lines.next(), lines.next()) {
+        (Some(names), Some(types)) => (serde_json::from_str(names)?, serde_json::from_str(types)?),
+        _ => return Ok(DBOutput::StatementComplete(None)),
+    };
+    for line in lines {
+        let row: Vec<Value> = serde_json::from_str(line)?;
+        if row.len() != types.len() {
+            return Err(ClickHouseError::ColumnCount {
+                columns: types.len(),
+                values: row.len(),
+            });
+        }
+        let values = types
+            .iter()
+            .zip(&row)
+            .map(|(ty, value)| match value {
+                Value::Null => null_string.to_string(),
+                value => value_to_str(ty, value),
+            })
+            .collect();
+        output.push(values);
+    }
+    Ok(DBOutput::Rows {
+        names,
+        types: vec![],
+        rows: output,
+    })
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for ClickHouse {
+    type Error = ClickHouseError;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let resp = self
+            .client
+            .post(&self.url)
+            .query(&[
+                ("database", self.db.as_str()),
+                ("session_id", self.session_id.as_str()),
+                ("default_format", "JSONCompactEachRowWithNamesAndTypes"),
+                ("output_format_json_quote_denormals", "1"),
+                ("output_format_json_quote_decimals", "1"),
+            ])
+            .header("X-ClickHouse-User", &self.user)
+            .header("X-ClickHouse-Key", &self.pass)
+            .body(sql.to_string())
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(ClickHouseError::Sql(resp.text().await?));
+        }
+        parse_output(&resp.text().await?, &self.null_string)
+    }
+
+    fn engine_name(&self) -> &str {
+        "clickhouse"
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "arrays" | "decimals")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_strip_type_wrapper() {
+        assert_eq!(
+            strip_type_wrapper("LowCardinality(Nullable(String))"),
+            "String"
+        );
+        assert_eq!(
+            strip_type_wrapper("Array(Nullable(Int32))"),
+            "Array(Nullable(Int32))"
+        );
+    }
+
+    #[test]
+    fn test_value_to_str() {
+        assert_eq!(value_to_str("Bool", &json!(true)), "t");
+        assert_eq!(value_to_str("String", &json!("")), "(empty)");
+        assert_eq!(
+            value_to_str("Int64", &json!("9007199254740993")),
+            "9007199254740993"
+        );
+        assert_eq!(value_to_str("Float64", &json!(0.5)), "0.5");
+        assert_eq!(
+            value_to_str("Nullable(Array(Nullable(Int32)))", &json!([1, null])),
+            "{1,NULL}"
+        );
+        assert_eq!(
+            value_to_str("Tuple(String, UInt8)", &json!(["a", 1])),
+            "(a,1)"
+        );
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let body = "[\"a\",\"b\"]\n[\"Nullable(String)\",\"UInt8\"]\n[null,1]\n[\"x\",2]\n";
+        assert_eq!(
+            parse_output(body, "null").unwrap(),
+            DBOutput::Rows {
+                names: vec!["a".into(), "b".into()],
+                types: vec![],
+                rows: vec![
+                    vec!["null".into(), "1".into()],
+                    vec!["x".into(), "2".into()],
+                ],
+            }
+        );
+        assert_eq!(
+            parse_output("", "NULL").unwrap(),
+            DBOutput::StatementComplete(None)
+        );
+        let body = "[\"a\"]\n[\"UInt8\"]\n[1,2]\n";
+        assert!(matches!(
+            parse_output(body, "NULL"),
+            Err(ClickHouseError::ColumnCount {
+                columns: 1,
+                values: 2
+            })
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+use sqllogictest::{AsyncDB, DBOutput};
+use tokio_postgres::error::SqlState;
+
+use super::postgres::{transaction_change, Postgres};
+use crate::{DBConfig, Result};
+
+/// CockroachDB engine, which is the postgres engine with automatic retries.
+///
+/// CockroachDB runs transactions at `SERIALIZABLE` isolation, and may abort them with a
+/// serialization failure (SQLSTATE `40001`) that is expected to be retried by the client.
+/// Such records are run again, up to `max_retries` times. Inside an explicit transaction, the
+/// failure aborts the whole transaction, which cannot be retried by running the record again,
+/// so the error is returned.
+pub struct Cockroach {
+    inner: Postgres,
+    max_retries: usize,
+    /// Whether an explicit transaction is open, in which the records are not retried.
+    in_transaction: bool,
+}
+
+impl Cockroach {
+    pub(super) async fn connect(config: &DBConfig, max_retries: usize) -> Result<Self> {
+        Ok(Self {
+            inner: Postgres::connect(config).await?,
+            max_retries,
+            in_transaction: false,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncDB for Cockroach {
+    type Error = tokio_postgres::error::Error;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let mut retries = 0;
+        let result = loop {
+            match self.inner.run(sql).await {
+                Err(e)
+                    if e.code() == Some(&SqlState::T_R_SERIALIZATION_FAILURE)
+                        && !self.in_transaction
+                        && retries < self.max_retries =>
+                {
+                    retries += 1;
+                    log::warn!("retrying ({retries}/{}): {}", self.max_retries, e);
+                }
+                Err(e)
+                    if e.code() == Some(&SqlState::T_R_SERIALIZATION_FAILURE)
+                        && self.in_transaction =>
+                {
+                    log::warn!("not retrying inside an explicit transaction: {e}");
+                    break Err(e);
+                }
+                result => break result,
+            }
+        };
+        match transaction_change(sql) {
+            Some(true) if result.is_ok() => self.in_transaction = true,
+            Some(false) => self.in_transaction = false,
+            _ => {}
+        }
+        result
+    }
+
+    fn engine_name(&self) -> &str {
+        "cockroach"
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "arrays" | "decimals" | "transactions" | "mvcc")
+    }
+}
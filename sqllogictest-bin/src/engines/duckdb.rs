@@ -0,0 +1,293 @@
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use duckdb::types::{TimeUnit, Value};
+use duckdb::Connection;
+use itertools::Itertools;
+use sqllogictest::DBOutput;
+
+use crate::{DBConfig, Result};
+
+/// In-process DuckDB engine. The `db` option is used as the path of the database file, and
+/// `:memory:` opens a fresh in-memory database.
+pub struct DuckDB {
+    conn: Connection,
+    /// The path of the database file, or `None` for an in-memory database, reopened by
+    /// `restart`.
+    path: Option<String>,
+    readonly: bool,
+    /// Whether another database than the configured one is opened by `load`.
+    loaded: bool,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+}
+
+impl DuckDB {
+    pub(super) fn connect(config: &DBConfig) -> Result<Self> {
+        let path = (config.db != ":memory:").then(|| config.db.clone());
+        let conn = open(path.as_deref(), false)
+            .context(format!("failed to open duckdb database {}", config.db))?;
+
+        Ok(Self {
+            conn,
+            path,
+            readonly: false,
+            loaded: false,
+            null_string: config.null_string.clone(),
+        })
+    }
+
+    /// Opens the database file at `path`, or a new in-memory database, instead of the current
+    /// one, for `load` in the DuckDB dialect.
+    pub(super) fn load(&mut self, path: Option<&str>, readonly: bool) -> Result<(), duckdb::Error> {
+        self.conn = open(path, readonly)?;
+        self.path = path.map(str::to_string);
+        self.readonly = readonly;
+        self.loaded = true;
+        Ok(())
+    }
+
+    /// Closes and reopens the database, for `restart` in the DuckDB dialect. An in-memory
+    /// database is lost.
+    pub(super) fn restart(&mut self) -> Result<(), duckdb::Error> {
+        // The database file is locked until the old connection is closed.
+        self.conn = Connection::open_in_memory()?;
+        self.conn = open(self.path.as_deref(), self.readonly)?;
+        Ok(())
+    }
+
+    pub(super) fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+}
+
+fn open(path: Option<&str>, readonly: bool) -> Result<Connection, duckdb::Error> {
+    let mut config = duckdb::Config::default();
+    if readonly {
+        config = config.access_mode(duckdb::AccessMode::ReadOnly)?;
+    }
+    match path {
+        Some(path) => Connection::open_with_flags(path, config),
+        None => Connection::open_in_memory_with_flags(config),
+    }
+}
+
+fn to_micros(unit: TimeUnit, value: i64) -> i64 {
+    match unit {
+        TimeUnit::Second => value * 1_000_000,
+        TimeUnit::Millisecond => value * 1_000,
+        TimeUnit::Microsecond => value,
+        TimeUnit::Nanosecond => value / 1_000,
+    }
+}
+
+fn time_to_str(micros: i64) -> String {
+    let secs = micros.div_euclid(1_000_000);
+    let nanos = micros.rem_euclid(1_000_000) * 1_000;
+    NaiveTime::from_num_seconds_from_midnight_opt(secs as u32, nanos as u32)
+        .map(|t| t.to_string())
+        .unwrap_or_else(|| format!("{micros}us"))
+}
+
+fn interval_to_str(months: i32, days: i32, nanos: i64) -> String {
+    let mut parts = vec![];
+    let (years, months) = (months / 12, months % 12);
+    for (value, unit) in [(years, "year"), (months, "month"), (days, "day")] {
+        match value {
+            0 => {}
+            1 | -1 => parts.push(format!("{value} {unit}")),
+            _ => parts.push(format!("{value} {unit}s")),
+        }
+    }
+    if nanos != 0 || parts.is_empty() {
+        let sign = if nanos < 0 { "-" } else { "" };
+        parts.push(format!("{sign}{}", time_to_str(nanos.abs() / 1_000)));
+    }
+    parts.join(" ")
+}
+
+/// Formats a value the way DuckDB's own sqllogictest runner does. Nested values are rendered
+/// with `value_to_sql_str` so that strings inside lists and structs are quoted.
+fn value_to_str(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Boolean(v) => v.to_string(),
+        Value::TinyInt(v) => v.to_string(),
+        Value::SmallInt(v) => v.to_string(),
+        Value::Int(v) => v.to_string(),
+        Value::BigInt(v) => v.to_string(),
+        Value::HugeInt(v) => v.to_string(),
+        Value::UTinyInt(v) => v.to_string(),
+        Value::USmallInt(v) => v.to_string(),
+        Value::UInt(v) => v.to_string(),
+        Value::UBigInt(v) => v.to_string(),
+        Value::Float(v) => v.to_string(),
+        Value::Double(v) => v.to_string(),
+        Value::Decimal(v) => v.to_string(),
+        Value::Text(v) if v.is_empty() => "(empty)".to_string(),
+        Value::Text(v) | Value::Enum(v) => v.clone(),
+        Value::Blob(v) => v.iter().map(|b| format!("\\x{:02X}", b)).collect(),
+        Value::Date32(days) => NaiveDate::from_ymd_opt(1970, 1, 1)
+            .and_then(|epoch| epoch.checked_add_signed(chrono::Duration::days(*days as i64)))
+            .map(|d| d.to_string())
+            .unwrap_or_else(|| format!("{days}")),
+        Value::Time64(unit, v) => time_to_str(to_micros(*unit, *v)),
+        Value::Timestamp(unit, v) => {
+            let micros = to_micros(*unit, *v);
+            DateTime::from_timestamp(
+                micros.div_euclid(1_000_000),
+                (micros.rem_euclid(1_000_000) * 1_000) as u32,
+            )
+            .map(|t| t.naive_utc().to_string())
+            .unwrap_or_else(|| format!("{micros}us"))
+        }
+        Value::Interval {
+            months,
+            days,
+            nanos,
+        } => interval_to_str(*months, *days, *nanos),
+        Value::List(values) | Value::Array(values) => {
+            format!("[{}]", values.iter().map(value_to_sql_str).join(", "))
+        }
+        Value::Struct(fields) => format!(
+            "{{{}}}",
+            fields
+                .iter()
+                .map(|(k, v)| format!("'{}': {}", k, value_to_sql_str(v)))
+                .join(", ")
+        ),
+        Value::Map(entries) => format!(
+            "{{{}}}",
+            entries
+                .iter()
+                .map(|(k, v)| format!("{}={}", value_to_sql_str(k), value_to_sql_str(v)))
+                .join(", ")
+        ),
+        Value::Union(v) => value_to_str(v),
+    }
+}
+
+/// Formats a value nested inside a list or struct, where strings are quoted.
+fn value_to_sql_str(value: &Value) -> String {
+    match value {
+        Value::Text(v) | Value::Enum(v) => format!("'{}'", v),
+        _ => value_to_str(value),
+    }
+}
+
+/// DuckDB is synchronous, so we implement the blocking [`sqllogictest::DB`] trait. The caller is
+/// responsible for not blocking the async runtime.
+impl sqllogictest::DB for DuckDB {
+    type Error = duckdb::Error;
+
+    fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let mut output = vec![];
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+        // The column count is only known after the statement is executed.
+        let column_count = rows.as_ref().map(|s| s.column_count()).unwrap_or(0);
+        let names = rows.as_ref().map(|s| s.column_names()).unwrap_or_default();
+        while let Some(row) = rows.next()? {
+            let mut values = Vec::with_capacity(column_count);
+            for idx in 0..column_count {
+                values.push(match row.get::<_, Value>(idx)? {
+                    Value::Null => self.null_string.clone(),
+                    value => value_to_str(&value),
+                });
+            }
+            output.push(values);
+        }
+        // Statements return the number of rows they affect as a row of a `Count` column.
+        Ok(DBOutput::Rows {
+            names,
+            types: vec![],
+            rows: output,
+        })
+    }
+
+    fn engine_name(&self) -> &str {
+        "duckdb"
+    }
+
+    /// Besides the common capabilities, the extensions which are loaded, e.g. `parquet` for
+    /// `require parquet`.
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "arrays" | "decimals" | "transactions" | "mvcc")
+            || self
+                .conn
+                .query_row(
+                    "SELECT count(*) > 0 FROM duckdb_extensions() \
+                     WHERE extension_name = ? AND loaded",
+                    [capability],
+                    |row| row.get(0),
+                )
+                .unwrap_or(false)
+    }
+
+    fn load(&mut self, path: Option<&str>, readonly: bool) -> Option<Result<(), Self::Error>> {
+        Some(DuckDB::load(self, path, readonly))
+    }
+
+    fn restart(&mut self) -> Option<Result<(), Self::Error>> {
+        Some(DuckDB::restart(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqllogictest::DB;
+
+    use super::*;
+
+    #[test]
+    fn test_value_to_str() {
+        assert_eq!(value_to_str(&Value::Text(String::new())), "(empty)");
+        assert_eq!(value_to_str(&Value::Blob(vec![0x0a, 0xff])), "\\x0A\\xFF");
+        assert_eq!(value_to_str(&Value::Date32(19358)), "2023-01-01");
+        assert_eq!(
+            value_to_str(&Value::Time64(TimeUnit::Microsecond, 3_723_500_000)),
+            "01:02:03.500"
+        );
+        assert_eq!(
+            value_to_str(&Value::Timestamp(TimeUnit::Second, -1)),
+            "1969-12-31 23:59:59"
+        );
+        let interval = |months, days, nanos| Value::Interval {
+            months,
+            days,
+            nanos,
+        };
+        assert_eq!(
+            value_to_str(&interval(14, -1, 3_600_000_000_000)),
+            "1 year 2 months -1 day 01:00:00"
+        );
+        assert_eq!(value_to_str(&interval(0, 0, 0)), "00:00:00");
+        assert_eq!(
+            value_to_str(&Value::List(vec![Value::Text("a".into()), Value::Int(1)])),
+            "['a', 1]"
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let mut db = DuckDB {
+            conn: Connection::open_in_memory().unwrap(),
+            path: None,
+            readonly: false,
+            loaded: false,
+            null_string: "null".into(),
+        };
+        assert_eq!(
+            db.run("select {'a': 'x', 'b': [1, 2]} as s, null as n, '' as e")
+                .unwrap(),
+            DBOutput::Rows {
+                names: vec!["s".into(), "n".into(), "e".into()],
+                types: vec![],
+                rows: vec![vec![
+                    "{'a': 'x', 'b': [1, 2]}".into(),
+                    "null".into(),
+                    "(empty)".into(),
+                ]],
+            }
+        );
+    }
+}
@@ -7,12 +7,14 @@ use async_trait::async_trait;
 use bytes::{Buf, BytesMut};
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
-use sqllogictest::AsyncDB;
+use sqllogictest::{AsyncDB, DBOutput};
 use thiserror::Error;
 use tokio::io::AsyncWriteExt;
 use tokio::process::{Child, ChildStdin, ChildStdout, Command};
 use tokio_util::codec::{Decoder, FramedRead};
 
+use super::parse_text_output;
+
 pub struct ExternalDriver {
     child: Child,
     stdin: ChildStdin,
@@ -71,7 +73,7 @@ impl Drop for ExternalDriver {
 impl AsyncDB for ExternalDriver {
     type Error = ExternalDriverError;
 
-    async fn run(&mut self, sql: &str) -> Result<String> {
+    async fn run(&mut self, sql: &str) -> Result<DBOutput> {
         let input = Input {
             sql: sql.to_string(),
         };
@@ -83,7 +85,7 @@ impl AsyncDB for ExternalDriver {
             None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
         };
         match output {
-            Output::Success { result } => Ok(result),
+            Output::Success { result } => Ok(parse_text_output(&result)),
             Output::Failed { err } => Err(ExternalDriverError::Sql(err)),
         }
     }
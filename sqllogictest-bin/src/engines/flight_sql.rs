@@ -0,0 +1,153 @@
+use anyhow::Context;
+use arrow::array::{
+    as_boolean_array, as_large_list_array, as_list_array, Array, ArrayRef, LargeStringArray,
+    StringArray,
+};
+use arrow::datatypes::DataType;
+use arrow::error::ArrowError;
+use arrow::util::display::array_value_to_string;
+use arrow_flight::error::FlightError;
+use arrow_flight::sql::client::FlightSqlServiceClient;
+use async_trait::async_trait;
+use futures::StreamExt;
+use itertools::Itertools;
+use sqllogictest::DBOutput;
+use thiserror::Error;
+use tonic::transport::{Channel, Endpoint};
+
+use crate::{DBConfig, Result};
+
+/// Engine for databases speaking Arrow Flight SQL, e.g. Dremio, InfluxDB IOx and DataFusion
+/// based servers.
+///
+/// Every record is executed as a query, and the returned endpoints are fetched from the same
+/// server, ignoring their locations.
+pub struct FlightSql {
+    client: FlightSqlServiceClient<Channel>,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+}
+
+#[derive(Debug, Error)]
+pub enum FlightSqlError {
+    #[error("arrow failed")]
+    Arrow(#[from] ArrowError),
+    #[error("flight failed")]
+    Flight(#[from] FlightError),
+}
+
+impl FlightSql {
+    pub(super) async fn connect(config: &DBConfig) -> Result<Self> {
+        let (host, port) = config.addr();
+
+        let channel = Endpoint::from_shared(format!("http://{host}:{port}"))?
+            .connect()
+            .await
+            .context(format!(
+                "failed to connect to flight sql server at {host}:{port}"
+            ))?;
+        let mut client = FlightSqlServiceClient::new(channel);
+
+        // Not every server implements authentication, so continue without a token on failure.
+        if let Err(e) = client.handshake(&config.user, &config.pass).await {
+            log::warn!(
+                "flight sql handshake failed, continuing unauthenticated: {}",
+                e
+            );
+        }
+
+        Ok(Self {
+            client,
+            null_string: config.null_string.clone(),
+        })
+    }
+}
+
+/// Formats the value at `row` in the canonical text format.
+fn value_to_str(array: &ArrayRef, row: usize) -> Result<String, ArrowError> {
+    if array.is_null(row) {
+        return Ok("NULL".to_string());
+    }
+    let s = match array.data_type() {
+        DataType::Boolean => match as_boolean_array(array).value(row) {
+            true => "t".to_string(),
+            false => "f".to_string(),
+        },
+        DataType::Utf8 => {
+            let v = array
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .value(row);
+            match v {
+                "" => "(empty)".to_string(),
+                v => v.to_string(),
+            }
+        }
+        DataType::LargeUtf8 => {
+            let v = array
+                .as_any()
+                .downcast_ref::<LargeStringArray>()
+                .unwrap()
+                .value(row);
+            match v {
+                "" => "(empty)".to_string(),
+                v => v.to_string(),
+            }
+        }
+        DataType::List(_) => list_to_str(&as_list_array(array).value(row))?,
+        DataType::LargeList(_) => list_to_str(&as_large_list_array(array).value(row))?,
+        // Arrow formats timestamps in RFC 3339, e.g. `2023-01-01T00:00:00`.
+        DataType::Timestamp(_, _) => array_value_to_string(array, row)?.replacen('T', " ", 1),
+        _ => array_value_to_string(array, row)?,
+    };
+    Ok(s)
+}
+
+/// Formats the elements of a list in the postgres array style, e.g. `{1,2,NULL}`.
+fn list_to_str(values: &ArrayRef) -> Result<String, ArrowError> {
+    let values: Vec<_> = (0..values.len())
+        .map(|i| value_to_str(values, i))
+        .try_collect()?;
+    Ok(format!("{{{}}}", values.join(",")))
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for FlightSql {
+    type Error = FlightSqlError;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let mut output = vec![];
+        let info = self.client.execute(sql.to_string(), None).await?;
+        for endpoint in info.endpoint {
+            let ticket = match endpoint.ticket {
+                Some(ticket) => ticket,
+                None => continue,
+            };
+            let mut stream = self.client.do_get(ticket).await?;
+            while let Some(batch) = stream.next().await {
+                let batch = batch?;
+                for row in 0..batch.num_rows() {
+                    let mut values = Vec::with_capacity(batch.num_columns());
+                    for column in batch.columns() {
+                        if column.is_null(row) {
+                            values.push(self.null_string.clone());
+                        } else {
+                            values.push(value_to_str(column, row)?);
+                        }
+                    }
+                    output.push(values);
+                }
+            }
+        }
+        Ok(DBOutput::Rows {
+            names: vec![],
+            types: vec![],
+            rows: output,
+        })
+    }
+
+    fn engine_name(&self) -> &str {
+        "flight-sql"
+    }
+}
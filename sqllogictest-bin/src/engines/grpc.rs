@@ -0,0 +1,147 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use sqllogictest::DBOutput;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Status};
+
+use crate::{DBConfig, Result};
+
+/// The request message of `sqllogictest.Query/Run`. See `proto/query.proto`.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct QueryRequest {
+    #[prost(string, tag = "1")]
+    pub sql: String,
+}
+
+/// The response message of `sqllogictest.Query/Run`. See `proto/query.proto`.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct QueryResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub columns: Vec<Column>,
+    #[prost(message, repeated, tag = "2")]
+    pub rows: Vec<Row>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Column {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub r#type: String,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Row {
+    #[prost(message, repeated, tag = "1")]
+    pub values: Vec<Value>,
+}
+
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Value {
+    /// Text representation of the value. `None` for NULL.
+    #[prost(string, optional, tag = "1")]
+    pub text: Option<String>,
+}
+
+/// A client of a gRPC service which runs SQL.
+///
+/// [`DefaultQueryClient`] talks to the service defined in `proto/query.proto`. To test a database
+/// with a different service, implement this trait on top of the generated client, converting the
+/// response into a [`QueryResponse`], and construct it in [`Grpc::connect`].
+#[async_trait]
+pub trait QueryClient: Send {
+    /// Run a single SQL statement or query.
+    async fn run(&mut self, sql: &str) -> Result<QueryResponse, Status>;
+}
+
+/// Client of the `sqllogictest.Query` service, equivalent to the one generated by `tonic-build`.
+pub struct DefaultQueryClient {
+    inner: tonic::client::Grpc<Channel>,
+}
+
+impl DefaultQueryClient {
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            inner: tonic::client::Grpc::new(channel),
+        }
+    }
+}
+
+#[async_trait]
+impl QueryClient for DefaultQueryClient {
+    async fn run(&mut self, sql: &str) -> Result<QueryResponse, Status> {
+        self.inner
+            .ready()
+            .await
+            .map_err(|e| Status::new(Code::Unknown, format!("service was not ready: {}", e)))?;
+        let codec = tonic::codec::ProstCodec::default();
+        let path = PathAndQuery::from_static("/sqllogictest.Query/Run");
+        let request = Request::new(QueryRequest {
+            sql: sql.to_string(),
+        });
+        let response = self.inner.unary(request, path, codec).await?;
+        Ok(response.into_inner())
+    }
+}
+
+/// Engine for databases exposing a gRPC query service.
+pub struct Grpc {
+    client: Box<dyn QueryClient>,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+}
+
+impl Grpc {
+    pub(super) async fn connect(config: &DBConfig) -> Result<Self> {
+        let (host, port) = config.addr();
+
+        let channel = Endpoint::from_shared(format!("http://{host}:{port}"))?
+            .connect()
+            .await
+            .context(format!(
+                "failed to connect to grpc service at {host}:{port}"
+            ))?;
+
+        Ok(Self {
+            client: Box::new(DefaultQueryClient::new(channel)),
+            null_string: config.null_string.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for Grpc {
+    type Error = Status;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let response = self.client.run(sql).await?;
+        // Statements return neither columns nor rows.
+        if response.columns.is_empty() && response.rows.is_empty() {
+            return Ok(DBOutput::StatementComplete(None));
+        }
+        let rows = response
+            .rows
+            .into_iter()
+            .map(|row| {
+                row.values
+                    .into_iter()
+                    .map(|value| match value.text {
+                        Some(v) if v.is_empty() => "(empty)".to_string(),
+                        Some(v) => v,
+                        None => self.null_string.clone(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(DBOutput::Rows {
+            names: vec![],
+            types: vec![],
+            rows,
+        })
+    }
+
+    fn engine_name(&self) -> &str {
+        "grpc"
+    }
+}
@@ -0,0 +1,232 @@
+use async_trait::async_trait;
+use itertools::Itertools;
+use serde_json::Value;
+use sqllogictest::DBOutput;
+use thiserror::Error;
+
+/// Engine for services which run SQL sent in an HTTP request, and return rows in JSON.
+///
+/// The SQL is sent in a POST request to `url`, with `body` as the body, where `{sql}` is replaced
+/// by the SQL as a JSON string. Rows are found in the response at `rows_path`, a JSONPath like
+/// `$.data.rows`, and each row is either an array of values, or an object whose values are taken
+/// in order.
+pub struct HttpEngine {
+    client: reqwest::Client,
+    url: String,
+    body: String,
+    headers: Vec<(String, String)>,
+    rows_path: Vec<PathSegment>,
+    /// The string which `NULL` values are written as.
+    null_string: String,
+}
+
+#[derive(Debug, Error)]
+pub enum HttpEngineError {
+    #[error("http request failed")]
+    Http(#[from] reqwest::Error),
+    #[error("ser/de failed")]
+    Json(#[from] serde_json::Error),
+    #[error("sql failed {0}")]
+    Sql(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Parses a JSONPath consisting of keys and indices, e.g. `$.data[0].rows` or `$['data']`.
+fn parse_path(path: &str) -> anyhow::Result<Vec<PathSegment>> {
+    let mut segments = vec![];
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            segments.push(PathSegment::Key(r[..end].to_string()));
+            rest = &r[end..];
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r
+                .find(']')
+                .ok_or_else(|| anyhow::anyhow!("unclosed `[` in JSONPath {path}"))?;
+            let inner = &r[..end];
+            match inner.strip_prefix('\'').and_then(|k| k.strip_suffix('\'')) {
+                Some(key) => segments.push(PathSegment::Key(key.to_string())),
+                None => {
+                    segments.push(PathSegment::Index(inner.parse().map_err(|_| {
+                        anyhow::anyhow!("invalid index `{inner}` in JSONPath {path}")
+                    })?))
+                }
+            }
+            rest = &r[end + 1..];
+        } else {
+            anyhow::bail!("invalid JSONPath {path}");
+        }
+    }
+    Ok(segments)
+}
+
+impl HttpEngine {
+    pub fn new(
+        url: String,
+        body: String,
+        headers: &[String],
+        rows_path: &str,
+        null_string: String,
+    ) -> anyhow::Result<Self> {
+        let headers = headers
+            .iter()
+            .map(|h| match h.split_once(':') {
+                Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+                None => Err(anyhow::anyhow!(
+                    "invalid header `{h}`, expected `Name: value`"
+                )),
+            })
+            .try_collect()?;
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url,
+            body,
+            headers,
+            rows_path: parse_path(rows_path)?,
+            null_string,
+        })
+    }
+}
+
+fn value_to_str(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(true) => "t".to_string(),
+        Value::Bool(false) => "f".to_string(),
+        Value::String(v) if v.is_empty() => "(empty)".to_string(),
+        Value::String(v) => v.clone(),
+        Value::Number(v) => v.to_string(),
+        Value::Array(values) => format!("{{{}}}", values.iter().map(value_to_str).join(",")),
+        Value::Object(_) => value.to_string(),
+    }
+}
+
+/// Parses the rows at `rows_path` in a response, writing `NULL` values as `null_string`.
+fn parse_output(resp: &Value, rows_path: &[PathSegment], null_string: &str) -> DBOutput {
+    let mut rows = resp;
+    for segment in rows_path {
+        rows = match segment {
+            PathSegment::Key(key) => &rows[key.as_str()],
+            PathSegment::Index(idx) => &rows[*idx],
+        };
+    }
+
+    // Statements usually return no rows at all, and the number of rows they affect is not
+    // known.
+    let rows = match rows {
+        Value::Array(rows) => rows.as_slice(),
+        _ => return DBOutput::StatementComplete(None),
+    };
+    let rows = rows
+        .iter()
+        .map(|row| {
+            let values = match row {
+                Value::Array(values) => values.iter().collect_vec(),
+                Value::Object(values) => values.values().collect_vec(),
+                value => vec![value],
+            };
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::Null => null_string.to_string(),
+                    value => value_to_str(value),
+                })
+                .collect()
+        })
+        .collect();
+    DBOutput::Rows {
+        names: vec![],
+        types: vec![],
+        rows,
+    }
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for HttpEngine {
+    type Error = HttpEngineError;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let body = self.body.replace("{sql}", &serde_json::to_string(sql)?);
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let resp = request.send().await?;
+        if !resp.status().is_success() {
+            return Err(HttpEngineError::Sql(resp.text().await?));
+        }
+        let resp: Value = resp.json().await?;
+        Ok(parse_output(&resp, &self.rows_path, &self.null_string))
+    }
+
+    fn engine_name(&self) -> &str {
+        "http"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("$.data[0]['rows']").unwrap(),
+            vec![
+                PathSegment::Key("data".into()),
+                PathSegment::Index(0),
+                PathSegment::Key("rows".into()),
+            ]
+        );
+        assert_eq!(parse_path("$").unwrap(), vec![]);
+        assert!(parse_path("$.data[0").is_err());
+        assert!(parse_path("$.data[x]").is_err());
+        assert!(parse_path("$data").is_err());
+    }
+
+    #[test]
+    fn test_value_to_str() {
+        assert_eq!(value_to_str(&json!(null)), "NULL");
+        assert_eq!(value_to_str(&json!(true)), "t");
+        assert_eq!(value_to_str(&json!("")), "(empty)");
+        assert_eq!(value_to_str(&json!("a b")), "a b");
+        assert_eq!(value_to_str(&json!(1.5)), "1.5");
+        assert_eq!(value_to_str(&json!([1, null, "x"])), "{1,NULL,x}");
+        assert_eq!(value_to_str(&json!({"a": 1})), r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let path = parse_path("$.data.rows").unwrap();
+        let resp = json!({"data": {"rows": [[1, null], {"a": "x", "b": [null]}, 2]}});
+        assert_eq!(
+            parse_output(&resp, &path, "null"),
+            DBOutput::Rows {
+                names: vec![],
+                types: vec![],
+                rows: vec![
+                    vec!["1".into(), "null".into()],
+                    vec!["x".into(), "{NULL}".into()],
+                    vec!["2".into()],
+                ],
+            }
+        );
+        assert_eq!(
+            parse_output(&json!({"ok": true}), &path, "NULL"),
+            DBOutput::StatementComplete(None)
+        );
+    }
+}
@@ -1,61 +1,776 @@
 mod postgres;
+use self::postgres::Postgres;
+use anyhow::Context;
 use clap::ArgEnum;
-use postgres::Postgres;
 use tokio::process::Command;
 mod postgres_extended;
 use std::fmt::Display;
+use std::future::Future;
+use std::time::{Duration, Instant};
+#[cfg(feature = "bigquery")]
+mod bigquery;
+mod cli;
+#[cfg(feature = "clickhouse")]
+mod clickhouse;
+mod cockroach;
+#[cfg(feature = "duckdb")]
+mod duckdb;
 mod external;
+#[cfg(feature = "flight-sql")]
+mod flight_sql;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "mysql")]
+mod mysql;
+#[cfg(feature = "odbc")]
+mod odbc;
+#[cfg(feature = "plugin")]
+mod plugin;
+#[cfg(feature = "postgres-sync")]
+mod postgres_sync;
+#[cfg(feature = "snowflake")]
+mod snowflake;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "mysql")]
+mod tidb;
+#[cfg(feature = "trino")]
+mod trino;
+#[cfg(feature = "wasm")]
+mod wasm;
 
 use async_trait::async_trait;
 use postgres_extended::PostgresExtended;
-use sqllogictest::AsyncDB;
+use sqllogictest::{AsyncDB, DBOutput, Protocol};
 
+#[cfg(feature = "bigquery")]
+use self::bigquery::BigQuery;
+use self::cli::CliDriver;
+#[cfg(feature = "clickhouse")]
+use self::clickhouse::ClickHouse;
+use self::cockroach::Cockroach;
+#[cfg(feature = "duckdb")]
+use self::duckdb::DuckDB;
 use self::external::ExternalDriver;
-use super::{DBConfig, Result};
+#[cfg(feature = "flight-sql")]
+use self::flight_sql::FlightSql;
+#[cfg(feature = "grpc")]
+use self::grpc::Grpc;
+#[cfg(feature = "http")]
+use self::http::HttpEngine;
+#[cfg(feature = "mysql")]
+use self::mysql::Mysql;
+#[cfg(feature = "odbc")]
+use self::odbc::Odbc;
+#[cfg(feature = "plugin")]
+use self::plugin::Plugin;
+#[cfg(feature = "postgres-sync")]
+use self::postgres_sync::PostgresSync;
+#[cfg(feature = "snowflake")]
+use self::snowflake::Snowflake;
+#[cfg(feature = "sqlite")]
+use self::sqlite::Sqlite;
+#[cfg(feature = "mysql")]
+use self::tidb::Tidb;
+#[cfg(feature = "trino")]
+use self::trino::Trino;
+#[cfg(feature = "wasm")]
+use self::wasm::Wasm;
+use super::{ArrayQuoting, CharPadding, DBConfig, Result};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, ArgEnum)]
 pub enum EngineType {
     Postgres,
     PostgresExtended,
+    PostgresSync,
+    Redshift,
+    Cockroach,
     External,
+    Cli,
+    Mysql,
+    Tidb,
+    Sqlite,
+    Duckdb,
+    Clickhouse,
+    Trino,
+    Odbc,
+    Grpc,
+    FlightSql,
+    Snowflake,
+    Bigquery,
+    Plugin,
+    Wasm,
+    Http,
 }
 
 #[derive(Clone, Debug)]
 pub enum EngineConfig {
     Postgres,
-    PostgresExtended,
+    PostgresExtended {
+        max_bytea_len: Option<usize>,
+        char_padding: CharPadding,
+        array_quoting: ArrayQuoting,
+        strict_types: bool,
+        statement_cache_size: usize,
+        max_rows: Option<usize>,
+    },
+    PostgresSync,
+    Redshift {
+        statement_cache_size: usize,
+    },
+    Cockroach {
+        max_retries: usize,
+    },
     External(String),
+    Cli {
+        cmd_tmpl: String,
+        separator: String,
+        error_pattern: regex::Regex,
+    },
+    Mysql,
+    #[cfg_attr(not(feature = "mysql"), allow(dead_code))]
+    Tidb {
+        max_retries: usize,
+    },
+    Sqlite,
+    Duckdb,
+    Clickhouse,
+    Trino,
+    #[cfg_attr(not(feature = "odbc"), allow(dead_code))]
+    Odbc(String),
+    Grpc,
+    FlightSql,
+    #[cfg_attr(not(feature = "snowflake"), allow(dead_code))]
+    Snowflake(String),
+    #[cfg_attr(not(feature = "bigquery"), allow(dead_code))]
+    Bigquery(String),
+    #[cfg_attr(not(feature = "plugin"), allow(dead_code))]
+    Plugin(String),
+    #[cfg_attr(not(feature = "wasm"), allow(dead_code))]
+    Wasm(String),
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    Http {
+        url: String,
+        body: String,
+        headers: Vec<String>,
+        rows_path: String,
+    },
+    /// Runs each record against both engines, the second one connected to the URL if given.
+    /// See `--compare` and `--compare-url`.
+    Compare(Box<EngineConfig>, Box<EngineConfig>, Option<String>),
 }
 
-enum Engines {
+impl EngineConfig {
+    /// Returns the SQL which resets the session state of a connection, used by
+    /// `--reset-connections` unless `--reset-sql` is given.
+    pub(super) fn reset_sql(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Self::Postgres
+            | Self::PostgresExtended { .. }
+            | Self::PostgresSync
+            | Self::Cockroach { .. } => Some(&["ROLLBACK", "DISCARD ALL"]),
+            Self::Mysql | Self::Tidb { .. } => Some(&["ROLLBACK"]),
+            Self::Compare(a, b, _) => a.reset_sql().filter(|&sql| b.reset_sql() == Some(sql)),
+            _ => None,
+        }
+    }
+}
+
+pub(super) enum Engines {
     Postgres(Postgres),
     PostgresExtended(PostgresExtended),
+    #[cfg(feature = "postgres-sync")]
+    PostgresSync(PostgresSync),
+    Cockroach(Cockroach),
     External(ExternalDriver),
+    Cli(CliDriver),
+    #[cfg(feature = "mysql")]
+    Mysql(Mysql),
+    #[cfg(feature = "mysql")]
+    Tidb(Tidb),
+    #[cfg(feature = "sqlite")]
+    Sqlite(Sqlite),
+    #[cfg(feature = "duckdb")]
+    Duckdb(DuckDB),
+    #[cfg(feature = "clickhouse")]
+    Clickhouse(ClickHouse),
+    #[cfg(feature = "trino")]
+    Trino(Trino),
+    #[cfg(feature = "odbc")]
+    Odbc(Odbc),
+    #[cfg(feature = "grpc")]
+    Grpc(Grpc),
+    #[cfg(feature = "flight-sql")]
+    FlightSql(FlightSql),
+    #[cfg(feature = "snowflake")]
+    Snowflake(Snowflake),
+    #[cfg(feature = "bigquery")]
+    Bigquery(BigQuery),
+    #[cfg(feature = "plugin")]
+    Plugin(Plugin),
+    #[cfg(feature = "wasm")]
+    Wasm(Wasm),
+    #[cfg(feature = "http")]
+    Http(HttpEngine),
+    Compare(Box<Engines>, Box<Engines>),
 }
 
+/// Connects to the database, failing over to the other addresses if connecting fails or the
+/// server is unhealthy.
 pub(super) async fn connect(engine: &EngineConfig, config: &DBConfig) -> Result<impl AsyncDB> {
+    Ok(Timeout {
+        db: connect_engines(engine, config).await?,
+        timeout: config.statement_timeout,
+    })
+}
+
+/// Connections shared by test files, created on demand up to the size of the pool. See
+/// `--pool-size`.
+pub(super) type Pool = deadpool::managed::Pool<Manager>;
+
+pub(super) struct Manager {
+    engine: EngineConfig,
+    config: DBConfig,
+    /// The statements run before reusing a connection. See `--reset-connections`.
+    reset_sql: Vec<String>,
+}
+
+#[async_trait]
+impl deadpool::managed::Manager for Manager {
+    type Type = Engines;
+    type Error = AnyhowError;
+
+    async fn create(&self) -> Result<Engines, AnyhowError> {
+        connect_engines(&self.engine, &self.config)
+            .await
+            .map_err(AnyhowError)
+    }
+
+    async fn recycle(
+        &self,
+        engines: &mut Engines,
+    ) -> deadpool::managed::RecycleResult<AnyhowError> {
+        // A connection which fails to reset is dropped, and a new one is created instead.
+        for sql in &self.reset_sql {
+            engines.run(sql).await.map_err(AnyhowError)?;
+        }
+        // Resetting, e.g. with `DISCARD ALL`, may also undo the settings made on connect.
+        if !self.reset_sql.is_empty() {
+            set_up_session(engines, &self.config)
+                .await
+                .map_err(AnyhowError)?;
+        }
+        if let Some(sql) = &self.config.health_check_sql {
+            engines.run(sql).await.map_err(AnyhowError)?;
+        }
+        // The next test file must run on the configured database, rather than one opened by
+        // `load` in the previous one.
+        if engines.is_loaded() {
+            return Err(AnyhowError(anyhow::anyhow!("another database is loaded")).into());
+        }
+        // Undo `control float_precision` and `control protocol` of the previous test file.
+        engines.set_float_precision(self.config.float_precision);
+        engines.set_protocol(self.config.protocol);
+        Ok(())
+    }
+}
+
+pub(super) fn pool(
+    engine: &EngineConfig,
+    config: &DBConfig,
+    size: usize,
+    reset_sql: Vec<String>,
+) -> Result<Pool> {
+    let manager = Manager {
+        engine: engine.clone(),
+        config: config.clone(),
+        reset_sql,
+    };
+    Ok(Pool::builder(manager).max_size(size).build()?)
+}
+
+/// A connection taken from a [`Pool`], which is returned to the pool when dropped, or one made
+/// apart from the pool for a `connection` record.
+pub(super) enum PooledEngines {
+    Pooled(deadpool::managed::Object<Manager>),
+    Unpooled(Engines),
+}
+
+impl std::ops::Deref for PooledEngines {
+    type Target = Engines;
+
+    fn deref(&self) -> &Engines {
+        match self {
+            PooledEngines::Pooled(engines) => engines,
+            PooledEngines::Unpooled(engines) => engines,
+        }
+    }
+}
+
+impl std::ops::DerefMut for PooledEngines {
+    fn deref_mut(&mut self) -> &mut Engines {
+        match self {
+            PooledEngines::Pooled(engines) => engines,
+            PooledEngines::Unpooled(engines) => engines,
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncDB for PooledEngines {
+    type Error = AnyhowError;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        Engines::run(self.tested(), sql).await.map_err(AnyhowError)
+    }
+
+    async fn run_batch(&mut self, sqls: &[String]) -> Vec<Result<DBOutput, Self::Error>> {
+        let results = Engines::run_batch(self.tested(), sqls).await;
+        results
+            .into_iter()
+            .map(|r| r.map_err(AnyhowError))
+            .collect()
+    }
+
+    async fn run_reference(&mut self, sql: &str) -> Option<Result<DBOutput, Self::Error>> {
+        let result = Engines::run_reference(self, sql).await?;
+        Some(result.map_err(AnyhowError))
+    }
+
+    fn engine_name(&self) -> &str {
+        Engines::engine_name(self)
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        Engines::has_capability(self, capability)
+    }
+
+    fn set_float_precision(&mut self, precision: Option<usize>) {
+        Engines::set_float_precision(self, precision)
+    }
+
+    fn set_protocol(&mut self, protocol: Protocol) {
+        Engines::set_protocol(self, protocol)
+    }
+
+    fn error_code(&self, err: &AnyhowError) -> Option<String> {
+        error_code(&err.0)
+    }
+
+    async fn load(
+        &mut self,
+        path: Option<&str>,
+        readonly: bool,
+    ) -> Option<Result<(), AnyhowError>> {
+        Engines::load(self, path, readonly).map(|r| r.map_err(AnyhowError))
+    }
+
+    async fn restart(&mut self) -> Option<Result<(), AnyhowError>> {
+        Engines::restart(self).map(|r| r.map_err(AnyhowError))
+    }
+
+    async fn sleep(dur: Duration) {
+        tokio::time::sleep(dur).await
+    }
+}
+
+/// Returns the config which the connections of the pool are made with.
+pub(super) fn pool_config(pool: &Pool) -> &DBConfig {
+    &pool.manager().config
+}
+
+/// Takes a connection from the pool, waiting for one to be returned if the pool is full.
+pub(super) async fn get(pool: &Pool) -> Result<Timeout<PooledEngines>> {
+    let engines = pool.get().await.map_err(|e| match e {
+        deadpool::managed::PoolError::Backend(e) => e.0,
+        e => anyhow::anyhow!("failed to get a connection from the pool: {e}"),
+    })?;
+    Ok(Timeout {
+        timeout: pool.manager().config.statement_timeout,
+        db: PooledEngines::Pooled(engines),
+    })
+}
+
+/// Connects like the pool, but apart from it, for `connection` records, which must not wait for
+/// the connections of other test files.
+pub(super) async fn connect_unpooled(pool: &Pool) -> Result<Timeout<PooledEngines>> {
+    let Manager { engine, config, .. } = pool.manager();
+    Ok(Timeout {
+        timeout: config.statement_timeout,
+        db: PooledEngines::Unpooled(connect_engines(engine, config).await?),
+    })
+}
+
+/// How much longer than `--statement-timeout` SQL is waited for, so that the server cancels it
+/// first if it supports `statement_timeout`.
+const SERVER_TIMEOUT_GRACE: Duration = Duration::from_secs(1);
+
+/// Fails SQL which runs longer than `--statement-timeout`.
+pub(super) struct Timeout<D> {
+    db: D,
+    timeout: Option<Duration>,
+}
+
+#[async_trait]
+impl<D: AsyncDB<Error = AnyhowError>> AsyncDB for Timeout<D> {
+    type Error = AnyhowError;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        match self.timeout {
+            Some(timeout) => {
+                with_timeout(timeout, self.db.run(sql), || Err(timed_out(timeout))).await
+            }
+            None => self.db.run(sql).await,
+        }
+    }
+
+    async fn run_batch(&mut self, sqls: &[String]) -> Vec<Result<DBOutput, Self::Error>> {
+        match self.timeout {
+            // The statements of a batch may run at the same time, so the batch is given the
+            // total time of its statements.
+            Some(timeout) => {
+                let batch_timeout = timeout * sqls.len() as u32;
+                with_timeout(batch_timeout, self.db.run_batch(sqls), || {
+                    sqls.iter().map(|_| Err(timed_out(batch_timeout))).collect()
+                })
+                .await
+            }
+            None => self.db.run_batch(sqls).await,
+        }
+    }
+
+    async fn run_reference(&mut self, sql: &str) -> Option<Result<DBOutput, Self::Error>> {
+        match self.timeout {
+            Some(timeout) => {
+                with_timeout(timeout, self.db.run_reference(sql), || {
+                    Some(Err(timed_out(timeout)))
+                })
+                .await
+            }
+            None => self.db.run_reference(sql).await,
+        }
+    }
+
+    fn engine_name(&self) -> &str {
+        self.db.engine_name()
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        self.db.has_capability(capability)
+    }
+
+    fn set_float_precision(&mut self, precision: Option<usize>) {
+        self.db.set_float_precision(precision)
+    }
+
+    fn set_protocol(&mut self, protocol: Protocol) {
+        self.db.set_protocol(protocol)
+    }
+
+    fn error_code(&self, err: &AnyhowError) -> Option<String> {
+        self.db.error_code(err)
+    }
+
+    async fn load(
+        &mut self,
+        path: Option<&str>,
+        readonly: bool,
+    ) -> Option<Result<(), AnyhowError>> {
+        self.db.load(path, readonly).await
+    }
+
+    async fn restart(&mut self) -> Option<Result<(), AnyhowError>> {
+        self.db.restart().await
+    }
+
+    async fn sleep(dur: Duration) {
+        D::sleep(dur).await
+    }
+}
+
+/// Waits for the SQL until the timeout has passed, and the grace period after it. Engines which
+/// block, e.g. `--engine=sqlite`, are not stopped by the timeout of tokio, as they return only
+/// once they finish, so their results are replaced by `timed_out` if they took too long.
+async fn with_timeout<T>(
+    timeout: Duration,
+    future: impl Future<Output = T>,
+    timed_out: impl FnOnce() -> T,
+) -> T {
+    let start = Instant::now();
+    match tokio::time::timeout(timeout + SERVER_TIMEOUT_GRACE, future).await {
+        Ok(output) if start.elapsed() <= timeout => output,
+        _ => timed_out(),
+    }
+}
+
+/// Calls `interrupt` once the timeout has passed, unless dropped before, to stop the SQL of
+/// engines which block. See [`Timeout`].
+#[cfg(any(feature = "sqlite", feature = "wasm"))]
+pub(super) struct Watchdog {
+    _done: std::sync::mpsc::Sender<()>,
+}
+
+#[cfg(any(feature = "sqlite", feature = "wasm"))]
+impl Watchdog {
+    pub(super) fn start(timeout: Duration, interrupt: impl FnOnce() + Send + 'static) -> Self {
+        let (done, wait) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            // The sender is dropped when the SQL finishes in time.
+            if wait.recv_timeout(timeout) == Err(std::sync::mpsc::RecvTimeoutError::Timeout) {
+                interrupt();
+            }
+        });
+        Self { _done: done }
+    }
+}
+
+fn timed_out(timeout: Duration) -> AnyhowError {
+    AnyhowError(anyhow::anyhow!(
+        "timed out after {}",
+        humantime::format_duration(timeout)
+    ))
+}
+
+async fn connect_engines(engine: &EngineConfig, config: &DBConfig) -> Result<Engines> {
+    let mut configs = config.addrs_to_try().into_iter().peekable();
+    while let Some(config) = configs.next() {
+        match connect_addr(engine, &config).await {
+            Ok(engines) => return Ok(engines),
+            Err(e) if configs.peek().is_some() => {
+                let (host, port) = config.addr();
+                log::warn!("failed to connect to {host}:{port}, trying the next address: {e:#}");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("no address to connect")
+}
+
+async fn connect_addr(engine: &EngineConfig, config: &DBConfig) -> Result<Engines> {
+    let mut engines = match engine {
+        EngineConfig::Compare(a, b, url) => {
+            // The parts not given in the URL, e.g. the database of `--isolation`, are kept.
+            let b_config = match url {
+                Some(url) => config.clone().with_url(url)?.1,
+                None => config.clone(),
+            };
+            Engines::Compare(
+                Box::new(connect_one(a, config).await?),
+                Box::new(connect_one(b, &b_config).await?),
+            )
+        }
+        _ => connect_one(engine, config).await?,
+    };
+    set_up_session(&mut engines, config).await?;
+    if let Some(sql) = &config.health_check_sql {
+        engines
+            .run(sql)
+            .await
+            .context(format!("health check `{sql}` failed"))?;
+    }
+    Ok(engines)
+}
+
+/// Sets up the session of a connection, when connected and again when reset for another test
+/// file: the `statement_timeout` of the postgres engines, then `--connect-sql`.
+async fn set_up_session(engines: &mut Engines, config: &DBConfig) -> Result<()> {
+    if let Some(sql) = postgres::statement_timeout_sql(config) {
+        let each = match &mut *engines {
+            Engines::Compare(a, b) => vec![&mut **a, &mut **b],
+            engine => vec![engine],
+        };
+        for engine in each.into_iter().filter(|e| e.has_server_timeout()) {
+            engine.run(&sql).await?;
+        }
+    }
+    for sql in &config.connect_sql {
+        engines
+            .run(sql)
+            .await
+            .context(format!("failed to run connect sql `{sql}`"))?;
+    }
+    Ok(())
+}
+
+async fn connect_one(engine: &EngineConfig, config: &DBConfig) -> Result<Engines> {
     Ok(match engine {
         EngineConfig::Postgres => Engines::Postgres(Postgres::connect(config).await?),
-        EngineConfig::PostgresExtended => {
-            Engines::PostgresExtended(PostgresExtended::connect(config).await?)
+        EngineConfig::PostgresExtended {
+            max_bytea_len,
+            char_padding,
+            array_quoting,
+            strict_types,
+            statement_cache_size,
+            max_rows,
+        } => Engines::PostgresExtended(
+            PostgresExtended::connect(
+                config,
+                *max_bytea_len,
+                *char_padding,
+                *array_quoting,
+                *strict_types,
+                *statement_cache_size,
+                *max_rows,
+            )
+            .await?,
+        ),
+        #[cfg(feature = "postgres-sync")]
+        EngineConfig::PostgresSync => Engines::PostgresSync(tokio::task::block_in_place(|| {
+            PostgresSync::connect(config)
+        })?),
+        #[cfg(not(feature = "postgres-sync"))]
+        EngineConfig::PostgresSync => {
+            anyhow::bail!("sqllogictest-bin is built without the `postgres-sync` feature")
+        }
+        EngineConfig::Redshift {
+            statement_cache_size,
+        } => Engines::PostgresExtended(
+            PostgresExtended::connect_redshift(config, *statement_cache_size).await?,
+        ),
+        EngineConfig::Cockroach { max_retries } => {
+            Engines::Cockroach(Cockroach::connect(config, *max_retries).await?)
         }
         EngineConfig::External(cmd_tmpl) => {
-            let (host, port) = config.random_addr();
-            let cmd_str = cmd_tmpl
-                .replace("{db}", &config.db)
-                .replace("{host}", host)
-                .replace("{port}", &port.to_string())
-                .replace("{user}", &config.user)
-                .replace("{pass}", &config.pass);
+            let cmd_str = render_command_template(cmd_tmpl, config);
             let mut cmd = Command::new("bash");
             let cmd = cmd.args(["-c", &cmd_str]);
             Engines::External(ExternalDriver::connect(cmd).await?)
         }
+        EngineConfig::Cli {
+            cmd_tmpl,
+            separator,
+            error_pattern,
+        } => Engines::Cli(CliDriver::new(
+            render_command_template(cmd_tmpl, config),
+            separator.clone(),
+            error_pattern.clone(),
+        )),
+        #[cfg(feature = "mysql")]
+        EngineConfig::Mysql => Engines::Mysql(Mysql::connect(config).await?),
+        #[cfg(not(feature = "mysql"))]
+        EngineConfig::Mysql => {
+            anyhow::bail!("sqllogictest-bin is built without the `mysql` feature")
+        }
+        #[cfg(feature = "mysql")]
+        EngineConfig::Tidb { max_retries } => {
+            Engines::Tidb(Tidb::connect(config, *max_retries).await?)
+        }
+        #[cfg(not(feature = "mysql"))]
+        EngineConfig::Tidb { .. } => {
+            anyhow::bail!("sqllogictest-bin is built without the `mysql` feature")
+        }
+        #[cfg(feature = "sqlite")]
+        EngineConfig::Sqlite => Engines::Sqlite(Sqlite::connect(config)?),
+        #[cfg(not(feature = "sqlite"))]
+        EngineConfig::Sqlite => {
+            anyhow::bail!("sqllogictest-bin is built without the `sqlite` feature")
+        }
+        #[cfg(feature = "duckdb")]
+        EngineConfig::Duckdb => Engines::Duckdb(DuckDB::connect(config)?),
+        #[cfg(not(feature = "duckdb"))]
+        EngineConfig::Duckdb => {
+            anyhow::bail!("sqllogictest-bin is built without the `duckdb` feature")
+        }
+        #[cfg(feature = "clickhouse")]
+        EngineConfig::Clickhouse => Engines::Clickhouse(ClickHouse::connect(config).await?),
+        #[cfg(not(feature = "clickhouse"))]
+        EngineConfig::Clickhouse => {
+            anyhow::bail!("sqllogictest-bin is built without the `clickhouse` feature")
+        }
+        #[cfg(feature = "trino")]
+        EngineConfig::Trino => Engines::Trino(Trino::connect(config).await?),
+        #[cfg(not(feature = "trino"))]
+        EngineConfig::Trino => {
+            anyhow::bail!("sqllogictest-bin is built without the `trino` feature")
+        }
+        #[cfg(feature = "odbc")]
+        EngineConfig::Odbc(dsn) => Engines::Odbc(Odbc::connect(dsn, config).await?),
+        #[cfg(not(feature = "odbc"))]
+        EngineConfig::Odbc(_) => {
+            anyhow::bail!("sqllogictest-bin is built without the `odbc` feature")
+        }
+        #[cfg(feature = "grpc")]
+        EngineConfig::Grpc => Engines::Grpc(Grpc::connect(config).await?),
+        #[cfg(not(feature = "grpc"))]
+        EngineConfig::Grpc => {
+            anyhow::bail!("sqllogictest-bin is built without the `grpc` feature")
+        }
+        #[cfg(feature = "flight-sql")]
+        EngineConfig::FlightSql => Engines::FlightSql(FlightSql::connect(config).await?),
+        #[cfg(not(feature = "flight-sql"))]
+        EngineConfig::FlightSql => {
+            anyhow::bail!("sqllogictest-bin is built without the `flight-sql` feature")
+        }
+        #[cfg(feature = "snowflake")]
+        EngineConfig::Snowflake(private_key_path) => {
+            Engines::Snowflake(Snowflake::connect(config, private_key_path).await?)
+        }
+        #[cfg(not(feature = "snowflake"))]
+        EngineConfig::Snowflake(_) => {
+            anyhow::bail!("sqllogictest-bin is built without the `snowflake` feature")
+        }
+        #[cfg(feature = "bigquery")]
+        EngineConfig::Bigquery(credentials_path) => {
+            Engines::Bigquery(BigQuery::connect(config, credentials_path).await?)
+        }
+        #[cfg(not(feature = "bigquery"))]
+        EngineConfig::Bigquery(_) => {
+            anyhow::bail!("sqllogictest-bin is built without the `bigquery` feature")
+        }
+        #[cfg(feature = "plugin")]
+        EngineConfig::Plugin(path) => Engines::Plugin(Plugin::connect(path, config)?),
+        #[cfg(not(feature = "plugin"))]
+        EngineConfig::Plugin(_) => {
+            anyhow::bail!("sqllogictest-bin is built without the `plugin` feature")
+        }
+        #[cfg(feature = "wasm")]
+        EngineConfig::Wasm(path) => Engines::Wasm(Wasm::connect(path, config)?),
+        #[cfg(not(feature = "wasm"))]
+        EngineConfig::Wasm(_) => {
+            anyhow::bail!("sqllogictest-bin is built without the `wasm` feature")
+        }
+        #[cfg(feature = "http")]
+        EngineConfig::Http {
+            url,
+            body,
+            headers,
+            rows_path,
+        } => {
+            let headers = headers
+                .iter()
+                .map(|h| render_command_template(h, config))
+                .collect::<Vec<_>>();
+            Engines::Http(HttpEngine::new(
+                render_command_template(url, config),
+                render_command_template(body, config),
+                &headers,
+                rows_path,
+                config.null_string.clone(),
+            )?)
+        }
+        #[cfg(not(feature = "http"))]
+        EngineConfig::Http { .. } => {
+            anyhow::bail!("sqllogictest-bin is built without the `http` feature")
+        }
+        EngineConfig::Compare(..) => anyhow::bail!("can not compare more than two engines"),
     })
 }
 
+/// Replace the items in `{}` by [`DBConfig`].
+fn render_command_template(cmd_tmpl: &str, config: &DBConfig) -> String {
+    let (host, port) = config.addr();
+    cmd_tmpl
+        .replace("{db}", &config.db)
+        .replace("{host}", host)
+        .replace("{port}", &port.to_string())
+        .replace("{user}", &config.user)
+        .replace("{pass}", &config.pass)
+}
+
 #[derive(Debug)]
-struct AnyhowError(anyhow::Error);
+pub(super) struct AnyhowError(anyhow::Error);
 
 impl Display for AnyhowError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -69,21 +784,342 @@ impl std::error::Error for AnyhowError {
     }
 }
 
+/// Returns the SQLSTATE of an error of postgres or mysql, found among the error and its causes.
+fn error_code(err: &anyhow::Error) -> Option<String> {
+    err.chain().find_map(|e| {
+        if let Some(e) = e.downcast_ref::<tokio_postgres::Error>() {
+            return e.code().map(|code| code.code().to_string());
+        }
+        #[cfg(feature = "mysql")]
+        if let Some(mysql_async::Error::Server(e)) = e.downcast_ref::<mysql_async::Error>() {
+            return Some(e.state.clone());
+        }
+        None
+    })
+}
+
 impl Engines {
-    async fn run(&mut self, sql: &str) -> Result<String, anyhow::Error> {
+    /// Returns the name of the engine, which `onlyif` and `skipif` records are matched against.
+    /// Compared engines have no name, so records with `onlyif` are skipped and ones with `skipif`
+    /// are run.
+    fn engine_name(&self) -> &str {
+        match self {
+            Engines::Postgres(e) => e.engine_name(),
+            Engines::PostgresExtended(e) => e.engine_name(),
+            #[cfg(feature = "postgres-sync")]
+            Engines::PostgresSync(e) => e.engine_name(),
+            Engines::Cockroach(e) => e.engine_name(),
+            Engines::External(e) => e.engine_name(),
+            Engines::Cli(e) => e.engine_name(),
+            #[cfg(feature = "mysql")]
+            Engines::Mysql(e) => e.engine_name(),
+            #[cfg(feature = "mysql")]
+            Engines::Tidb(e) => e.engine_name(),
+            #[cfg(feature = "sqlite")]
+            Engines::Sqlite(e) => e.engine_name(),
+            #[cfg(feature = "duckdb")]
+            Engines::Duckdb(e) => e.engine_name(),
+            #[cfg(feature = "clickhouse")]
+            Engines::Clickhouse(e) => e.engine_name(),
+            #[cfg(feature = "trino")]
+            Engines::Trino(e) => e.engine_name(),
+            #[cfg(feature = "odbc")]
+            Engines::Odbc(e) => e.engine_name(),
+            #[cfg(feature = "grpc")]
+            Engines::Grpc(e) => e.engine_name(),
+            #[cfg(feature = "flight-sql")]
+            Engines::FlightSql(e) => e.engine_name(),
+            #[cfg(feature = "snowflake")]
+            Engines::Snowflake(e) => e.engine_name(),
+            #[cfg(feature = "bigquery")]
+            Engines::Bigquery(e) => e.engine_name(),
+            #[cfg(feature = "plugin")]
+            Engines::Plugin(e) => e.engine_name(),
+            #[cfg(feature = "wasm")]
+            Engines::Wasm(e) => e.engine_name(),
+            #[cfg(feature = "http")]
+            Engines::Http(e) => e.engine_name(),
+            Engines::Compare(..) => "",
+        }
+    }
+
+    /// Returns whether the engine has the capability. Compared engines must both have it.
+    fn has_capability(&self, capability: &str) -> bool {
+        match self {
+            Engines::Postgres(e) => e.has_capability(capability),
+            Engines::PostgresExtended(e) => e.has_capability(capability),
+            #[cfg(feature = "postgres-sync")]
+            Engines::PostgresSync(e) => e.has_capability(capability),
+            Engines::Cockroach(e) => e.has_capability(capability),
+            Engines::External(e) => e.has_capability(capability),
+            Engines::Cli(e) => e.has_capability(capability),
+            #[cfg(feature = "mysql")]
+            Engines::Mysql(e) => e.has_capability(capability),
+            #[cfg(feature = "mysql")]
+            Engines::Tidb(e) => e.has_capability(capability),
+            #[cfg(feature = "sqlite")]
+            Engines::Sqlite(e) => e.has_capability(capability),
+            #[cfg(feature = "duckdb")]
+            Engines::Duckdb(e) => e.has_capability(capability),
+            #[cfg(feature = "clickhouse")]
+            Engines::Clickhouse(e) => e.has_capability(capability),
+            #[cfg(feature = "trino")]
+            Engines::Trino(e) => e.has_capability(capability),
+            #[cfg(feature = "odbc")]
+            Engines::Odbc(e) => e.has_capability(capability),
+            #[cfg(feature = "grpc")]
+            Engines::Grpc(e) => e.has_capability(capability),
+            #[cfg(feature = "flight-sql")]
+            Engines::FlightSql(e) => e.has_capability(capability),
+            #[cfg(feature = "snowflake")]
+            Engines::Snowflake(e) => e.has_capability(capability),
+            #[cfg(feature = "bigquery")]
+            Engines::Bigquery(e) => e.has_capability(capability),
+            #[cfg(feature = "plugin")]
+            Engines::Plugin(e) => e.has_capability(capability),
+            #[cfg(feature = "wasm")]
+            Engines::Wasm(e) => e.has_capability(capability),
+            #[cfg(feature = "http")]
+            Engines::Http(e) => e.has_capability(capability),
+            Engines::Compare(a, b) => a.has_capability(capability) && b.has_capability(capability),
+        }
+    }
+
+    /// Runs the statements pipelined for the postgres engines, or one by one otherwise.
+    async fn run_batch(&mut self, sqls: &[String]) -> Vec<Result<DBOutput, anyhow::Error>> {
+        fn into_anyhow<E: Into<anyhow::Error>>(
+            results: Vec<Result<DBOutput, E>>,
+        ) -> Vec<Result<DBOutput, anyhow::Error>> {
+            results.into_iter().map(|r| r.map_err(Into::into)).collect()
+        }
+
+        match self {
+            Engines::Postgres(e) => into_anyhow(e.run_batch(sqls).await),
+            Engines::PostgresExtended(e) => into_anyhow(e.run_batch(sqls).await),
+            _ => {
+                let mut results = Vec::with_capacity(sqls.len());
+                for sql in sqls {
+                    results.push(self.run(sql).await);
+                }
+                results
+            }
+        }
+    }
+
+    /// Sets the precision of floats in the output, or restores the full precision with `None`.
+    /// Ignored by engines which do not decode floats themselves.
+    fn set_float_precision(&mut self, precision: Option<usize>) {
+        match self {
+            Engines::PostgresExtended(e) => e.set_float_precision(precision),
+            Engines::Compare(a, b) => {
+                a.set_float_precision(precision);
+                b.set_float_precision(precision);
+            }
+            _ => {}
+        }
+    }
+
+    /// Opens another database, if supported by the engine. See [`AsyncDB::load`].
+    #[cfg_attr(not(feature = "duckdb"), allow(unused_variables))]
+    fn load(&mut self, path: Option<&str>, readonly: bool) -> Option<Result<(), anyhow::Error>> {
+        match self {
+            #[cfg(feature = "duckdb")]
+            Engines::Duckdb(e) => {
+                Some(tokio::task::block_in_place(|| e.load(path, readonly)).map_err(Into::into))
+            }
+            _ => None,
+        }
+    }
+
+    /// Closes and reopens the database, if supported by the engine. See [`AsyncDB::restart`].
+    fn restart(&mut self) -> Option<Result<(), anyhow::Error>> {
+        match self {
+            #[cfg(feature = "duckdb")]
+            Engines::Duckdb(e) => {
+                Some(tokio::task::block_in_place(|| e.restart()).map_err(Into::into))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether another database than the configured one is opened by [`Engines::load`].
+    fn is_loaded(&self) -> bool {
+        match self {
+            #[cfg(feature = "duckdb")]
+            Engines::Duckdb(e) => e.is_loaded(),
+            _ => false,
+        }
+    }
+
+    /// Whether the engine supports the `statement_timeout` of postgres, which cancels SQL on the
+    /// server. See [`set_up_session`].
+    fn has_server_timeout(&self) -> bool {
+        match self {
+            Engines::Postgres(_) | Engines::PostgresExtended(_) | Engines::Cockroach(_) => true,
+            #[cfg(feature = "postgres-sync")]
+            Engines::PostgresSync(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Sets the protocol to run SQL with. Ignored by engines which support only one protocol.
+    fn set_protocol(&mut self, protocol: Protocol) {
+        match self {
+            Engines::PostgresExtended(e) => e.set_protocol(protocol),
+            Engines::Compare(a, b) => {
+                a.set_protocol(protocol);
+                b.set_protocol(protocol);
+            }
+            _ => {}
+        }
+    }
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, anyhow::Error> {
         Ok(match self {
             Engines::Postgres(e) => e.run(sql).await?,
             Engines::PostgresExtended(e) => e.run(sql).await?,
+            #[cfg(feature = "postgres-sync")]
+            Engines::PostgresSync(e) => {
+                tokio::task::block_in_place(|| sqllogictest::DB::run(e, sql))?
+            }
+            Engines::Cockroach(e) => e.run(sql).await?,
             Engines::External(e) => e.run(sql).await?,
+            Engines::Cli(e) => e.run(sql).await?,
+            #[cfg(feature = "mysql")]
+            Engines::Mysql(e) => e.run(sql).await?,
+            #[cfg(feature = "mysql")]
+            Engines::Tidb(e) => e.run(sql).await?,
+            #[cfg(feature = "sqlite")]
+            Engines::Sqlite(e) => tokio::task::block_in_place(|| sqllogictest::DB::run(e, sql))?,
+            #[cfg(feature = "duckdb")]
+            Engines::Duckdb(e) => tokio::task::block_in_place(|| sqllogictest::DB::run(e, sql))?,
+            #[cfg(feature = "clickhouse")]
+            Engines::Clickhouse(e) => e.run(sql).await?,
+            #[cfg(feature = "trino")]
+            Engines::Trino(e) => e.run(sql).await?,
+            #[cfg(feature = "odbc")]
+            Engines::Odbc(e) => e.run(sql).await?,
+            #[cfg(feature = "grpc")]
+            Engines::Grpc(e) => e.run(sql).await?,
+            #[cfg(feature = "flight-sql")]
+            Engines::FlightSql(e) => e.run(sql).await?,
+            #[cfg(feature = "snowflake")]
+            Engines::Snowflake(e) => e.run(sql).await?,
+            #[cfg(feature = "bigquery")]
+            Engines::Bigquery(e) => e.run(sql).await?,
+            #[cfg(feature = "plugin")]
+            Engines::Plugin(e) => tokio::task::block_in_place(|| sqllogictest::DB::run(e, sql))?,
+            #[cfg(feature = "wasm")]
+            Engines::Wasm(e) => tokio::task::block_in_place(|| sqllogictest::DB::run(e, sql))?,
+            #[cfg(feature = "http")]
+            Engines::Http(e) => e.run(sql).await?,
+            // The SQL which sets up connections, e.g. `--connect-sql`, runs on both engines. The
+            // records are run on each of them by the runner, see `Engines::run_reference`.
+            Engines::Compare(a, b) => {
+                // Call through `AsyncDB`, whose boxed future allows the recursion.
+                let (a, b) = (
+                    AsyncDB::run(&mut **a, sql).await,
+                    AsyncDB::run(&mut **b, sql).await,
+                );
+                let a = a.map_err(|e| e.0)?;
+                b.map_err(|e| e.0)?;
+                a
+            }
         })
     }
+
+    /// Returns the engine whose results are checked, i.e. the first one of compared engines.
+    fn tested(&mut self) -> &mut Engines {
+        match self {
+            Engines::Compare(a, _) => a,
+            engines => engines,
+        }
+    }
+
+    /// Runs the SQL on the second one of compared engines, whose results the runner compares
+    /// those of the first one with. See [`AsyncDB::run_reference`].
+    async fn run_reference(&mut self, sql: &str) -> Option<Result<DBOutput, anyhow::Error>> {
+        match self {
+            Engines::Compare(_, b) => Some(AsyncDB::run(&mut **b, sql).await.map_err(|e| e.0)),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the output of engines which print their results as text, one row per line with values
+/// separated by whitespace. No output is taken as a statement, whose count is not known.
+pub(super) fn parse_text_output(output: &str) -> DBOutput {
+    let rows: Vec<Vec<String>> = output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.split_whitespace().map(String::from).collect())
+        .collect();
+    if rows.is_empty() {
+        DBOutput::StatementComplete(None)
+    } else {
+        DBOutput::Rows {
+            names: vec![],
+            types: vec![],
+            rows,
+        }
+    }
 }
 
 #[async_trait]
 impl AsyncDB for Engines {
     type Error = AnyhowError;
 
-    async fn run(&mut self, sql: &str) -> Result<String, Self::Error> {
-        self.run(sql).await.map_err(AnyhowError)
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        self.tested().run(sql).await.map_err(AnyhowError)
+    }
+
+    async fn run_batch(&mut self, sqls: &[String]) -> Vec<Result<DBOutput, Self::Error>> {
+        let results = Engines::run_batch(self.tested(), sqls).await;
+        results
+            .into_iter()
+            .map(|r| r.map_err(AnyhowError))
+            .collect()
+    }
+
+    async fn run_reference(&mut self, sql: &str) -> Option<Result<DBOutput, Self::Error>> {
+        let result = Engines::run_reference(self, sql).await?;
+        Some(result.map_err(AnyhowError))
+    }
+
+    fn engine_name(&self) -> &str {
+        Engines::engine_name(self)
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        Engines::has_capability(self, capability)
+    }
+
+    fn set_float_precision(&mut self, precision: Option<usize>) {
+        Engines::set_float_precision(self, precision)
+    }
+
+    fn set_protocol(&mut self, protocol: Protocol) {
+        Engines::set_protocol(self, protocol)
+    }
+
+    fn error_code(&self, err: &AnyhowError) -> Option<String> {
+        error_code(&err.0)
+    }
+
+    async fn load(
+        &mut self,
+        path: Option<&str>,
+        readonly: bool,
+    ) -> Option<Result<(), AnyhowError>> {
+        Engines::load(self, path, readonly).map(|r| r.map_err(AnyhowError))
+    }
+
+    async fn restart(&mut self) -> Option<Result<(), AnyhowError>> {
+        Engines::restart(self).map(|r| r.map_err(AnyhowError))
+    }
+
+    // `sleep` records must not block the other test files run in parallel.
+    async fn sleep(dur: Duration) {
+        tokio::time::sleep(dur).await
     }
 }
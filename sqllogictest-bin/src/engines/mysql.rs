@@ -0,0 +1,152 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use mysql_async::consts::{ColumnFlags, ColumnType};
+use mysql_async::prelude::Queryable;
+use mysql_async::{Column, Conn, OptsBuilder, Row, Value};
+use sqllogictest::DBOutput;
+
+use crate::{DBConfig, Result};
+
+pub struct Mysql {
+    conn: Conn,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+}
+
+impl Mysql {
+    pub(super) async fn connect(config: &DBConfig) -> Result<Self> {
+        let (host, port) = config.addr();
+
+        let opts = OptsBuilder::default()
+            .ip_or_hostname(host)
+            .tcp_port(port)
+            .db_name(Some(&config.db))
+            .user(Some(&config.user))
+            .pass(Some(&config.pass));
+
+        let conn = Conn::new(opts)
+            .await
+            .context(format!("failed to connect to mysql at {host}:{port}"))?;
+
+        Ok(Self {
+            conn,
+            null_string: config.null_string.clone(),
+        })
+    }
+}
+
+/// Returns whether the column is a `BOOL`, which MySQL represents as a signed `TINYINT(1)`.
+fn is_bool_column(column: &Column) -> bool {
+    column.column_type() == ColumnType::MYSQL_TYPE_TINY
+        && column.column_length() == 1
+        && !column.flags().contains(ColumnFlags::UNSIGNED_FLAG)
+}
+
+/// Formats a value which is not `NULL`, which is written by the caller.
+pub(super) fn value_to_str(value: &Value, column: &Column) -> String {
+    match value {
+        Value::Bytes(bytes) if bytes.is_empty() => "(empty)".to_string(),
+        // A `TINYINT(1)` may hold other integers than booleans, which are printed as is.
+        Value::Bytes(bytes) if is_bool_column(column) => match bytes.as_slice() {
+            b"0" => "f".to_string(),
+            b"1" => "t".to_string(),
+            _ => String::from_utf8_lossy(bytes).into_owned(),
+        },
+        Value::Bytes(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+        // Other variants are only produced by the binary protocol. Reuse the SQL literal
+        // formatting and strip the quotes.
+        other => other.as_sql(true).trim_matches('\'').to_string(),
+    }
+}
+
+impl Mysql {
+    pub(super) fn conn(&mut self) -> &mut Conn {
+        &mut self.conn
+    }
+
+    /// Runs the SQL, formatting each value with `value_to_str`.
+    pub(super) async fn run_with(
+        &mut self,
+        sql: &str,
+        value_to_str: impl Fn(&Value, &Column) -> String,
+    ) -> Result<DBOutput, mysql_async::Error> {
+        let mut output = vec![];
+        let mut has_rows = false;
+        let mut affected_rows = 0;
+        // NOTE:
+        // We use the text protocol, so values are returned as strings formatted by the server.
+        // Only NULL, empty strings and booleans are rewritten to follow the conventions of the
+        // postgres engines.
+        let mut result = self.conn.query_iter(sql).await?;
+        while !result.is_empty() {
+            has_rows |= !result.columns_ref().is_empty();
+            let rows: Vec<Row> = result.collect().await?;
+            affected_rows = result.affected_rows();
+            for row in rows {
+                let values = row
+                    .columns_ref()
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, column)| match row.as_ref(idx) {
+                        Some(Value::NULL) | None => self.null_string.clone(),
+                        Some(value) => value_to_str(value, column),
+                    })
+                    .collect();
+                output.push(values);
+            }
+        }
+        if has_rows {
+            Ok(DBOutput::Rows {
+                names: vec![],
+                types: vec![],
+                rows: output,
+            })
+        } else {
+            Ok(DBOutput::StatementComplete(Some(affected_rows)))
+        }
+    }
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for Mysql {
+    type Error = mysql_async::Error;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        self.run_with(sql, value_to_str).await
+    }
+
+    fn engine_name(&self) -> &str {
+        "mysql"
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "decimals" | "transactions" | "mvcc")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(column: Column, text: &str) -> String {
+        value_to_str(&Value::Bytes(text.as_bytes().to_vec()), &column)
+    }
+
+    #[test]
+    fn test_value_to_str() {
+        let tiny = |length| Column::new(ColumnType::MYSQL_TYPE_TINY).with_column_length(length);
+        assert_eq!(format(tiny(1), "0"), "f");
+        assert_eq!(format(tiny(1), "1"), "t");
+        assert_eq!(format(tiny(1), "2"), "2");
+        assert_eq!(format(tiny(1), "-1"), "-1");
+        assert_eq!(format(tiny(4), "1"), "1");
+        let unsigned = tiny(1).with_flags(ColumnFlags::UNSIGNED_FLAG);
+        assert_eq!(format(unsigned, "1"), "1");
+        let decimal = Column::new(ColumnType::MYSQL_TYPE_NEWDECIMAL);
+        assert_eq!(format(decimal.clone(), "1.50"), "1.50");
+        assert_eq!(format(decimal, ""), "(empty)");
+        // Values of the binary protocol.
+        let long = Column::new(ColumnType::MYSQL_TYPE_LONGLONG);
+        assert_eq!(value_to_str(&Value::Int(-3), &long), "-3");
+    }
+}
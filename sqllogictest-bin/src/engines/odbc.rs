@@ -0,0 +1,127 @@
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use odbc_api::buffers::TextRowSet;
+use odbc_api::{Connection, Cursor, Environment};
+use sqllogictest::DBOutput;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+use crate::{DBConfig, Result};
+
+/// Maximum number of rows fetched at once.
+const BATCH_SIZE: usize = 1024;
+/// Maximum length of a text value. Longer values are truncated.
+const MAX_STR_LEN: usize = 4096;
+
+type Request = (String, oneshot::Sender<Result<DBOutput, OdbcError>>);
+
+/// Generic engine for any database with an ODBC driver.
+///
+/// ODBC connections can not be shared between threads, so the connection lives in a dedicated
+/// thread, and SQL is sent to it through a channel.
+pub struct Odbc {
+    tx: mpsc::Sender<Request>,
+}
+
+#[derive(Debug, Error)]
+pub enum OdbcError {
+    #[error("odbc failed")]
+    Odbc(#[from] odbc_api::Error),
+    #[error("odbc connection is closed")]
+    Disconnected,
+}
+
+impl Odbc {
+    /// Connect to `dsn`, which is either the name of a data source, or a connection string like
+    /// `Driver={PostgreSQL Unicode};Server=localhost;...`.
+    pub(super) async fn connect(dsn: &str, config: &DBConfig) -> Result<Self> {
+        let (tx, rx) = mpsc::channel::<Request>();
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let (dsn_str, user, pass) = (dsn.to_string(), config.user.clone(), config.pass.clone());
+        let null_string = config.null_string.clone();
+
+        thread::spawn(move || {
+            let env = match Environment::new() {
+                Ok(env) => env,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+            let conn = if dsn_str.contains('=') {
+                env.connect_with_connection_string(&dsn_str)
+            } else {
+                env.connect(&dsn_str, &user, &pass)
+            };
+            let conn = match conn {
+                Ok(conn) => conn,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+            let _ = ready_tx.send(Ok(()));
+
+            // The thread exits once the engine is dropped.
+            for (sql, resp_tx) in rx {
+                let _ = resp_tx.send(run(&conn, &sql, &null_string).map_err(OdbcError::Odbc));
+            }
+        });
+
+        ready_rx
+            .await
+            .map_err(|_| anyhow!("odbc thread exited unexpectedly"))?
+            .context(format!("failed to connect to odbc data source {dsn}"))?;
+
+        Ok(Self { tx })
+    }
+}
+
+/// Runs the SQL and converts every value to text, with `NULL`s written as `null_string`.
+fn run(conn: &Connection<'_>, sql: &str, null_string: &str) -> Result<DBOutput, odbc_api::Error> {
+    let mut output = vec![];
+    // Statements have no cursor, and the number of rows they affect is not known.
+    let mut cursor = match conn.execute(sql, ())? {
+        Some(cursor) => cursor,
+        None => return Ok(DBOutput::StatementComplete(None)),
+    };
+    let buffer = TextRowSet::for_cursor(BATCH_SIZE, &mut cursor, Some(MAX_STR_LEN))?;
+    let mut row_set_cursor = cursor.bind_buffer(buffer)?;
+    while let Some(batch) = row_set_cursor.fetch()? {
+        for row in 0..batch.num_rows() {
+            let values = (0..batch.num_cols())
+                .map(|col| match batch.at(col, row) {
+                    Some([]) => "(empty)".to_string(),
+                    Some(v) => String::from_utf8_lossy(v).into_owned(),
+                    None => null_string.to_string(),
+                })
+                .collect();
+            output.push(values);
+        }
+    }
+    Ok(DBOutput::Rows {
+        names: vec![],
+        types: vec![],
+        rows: output,
+    })
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for Odbc {
+    type Error = OdbcError;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.tx
+            .send((sql.to_string(), resp_tx))
+            .map_err(|_| OdbcError::Disconnected)?;
+        resp_rx.await.map_err(|_| OdbcError::Disconnected)?
+    }
+
+    fn engine_name(&self) -> &str {
+        "odbc"
+    }
+}
@@ -0,0 +1,130 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+
+use anyhow::{anyhow, Context};
+use libloading::Library;
+use sqllogictest::DBOutput;
+use thiserror::Error;
+
+use super::parse_text_output;
+use crate::{DBConfig, Result};
+
+type ConnectFn = unsafe extern "C" fn(config: *const c_char) -> *mut c_void;
+type RunFn =
+    unsafe extern "C" fn(conn: *mut c_void, sql: *const c_char, output: *mut *mut c_char) -> i32;
+type FreeStringFn = unsafe extern "C" fn(s: *mut c_char);
+type CloseFn = unsafe extern "C" fn(conn: *mut c_void);
+
+/// Engine loaded from a dynamic library, so that engines can be provided without forking
+/// sqllogictest-bin.
+///
+/// The library must export the following functions with the C ABI. All strings are
+/// null-terminated UTF-8.
+///
+/// ```c
+/// // Connects to the database. `config` is a JSON object with the keys `host`, `port`, `db`,
+/// // `user` and `pass`. Returns NULL on failure.
+/// void *slt_connect(const char *config);
+/// // Runs `sql`. On success, returns 0 and sets `*output` to the formatted rows: one row per
+/// // line, with values separated by spaces. On failure, returns non-zero and sets `*output` to
+/// // the error message.
+/// int32_t slt_run(void *conn, const char *sql, char **output);
+/// // Frees a string returned by `slt_run`.
+/// void slt_free_string(char *s);
+/// // Closes the connection.
+/// void slt_close(void *conn);
+/// ```
+///
+/// A connection is only used by one thread at a time, but may be moved between threads.
+pub struct Plugin {
+    conn: *mut c_void,
+    run: RunFn,
+    free_string: FreeStringFn,
+    close: CloseFn,
+    /// Keeps the functions above valid. Dropped after the connection is closed.
+    _library: Library,
+}
+
+// SAFETY: plugins are required to support moving connections between threads.
+unsafe impl Send for Plugin {}
+
+#[derive(Debug, Error)]
+pub enum PluginError {
+    #[error("invalid sql: {0}")]
+    InvalidSql(#[from] std::ffi::NulError),
+    #[error("sql failed {0}")]
+    Sql(String),
+}
+
+impl Plugin {
+    pub(super) fn connect(path: &str, config: &DBConfig) -> Result<Self> {
+        // SAFETY: the library is trusted to follow the ABI above.
+        unsafe {
+            let library =
+                Library::new(path).context(format!("failed to load engine plugin {path}"))?;
+            let connect = *library.get::<ConnectFn>(b"slt_connect\0")?;
+            let run = *library.get::<RunFn>(b"slt_run\0")?;
+            let free_string = *library.get::<FreeStringFn>(b"slt_free_string\0")?;
+            let close = *library.get::<CloseFn>(b"slt_close\0")?;
+
+            let (host, port) = config.addr();
+            let config = serde_json::json!({
+                "host": host,
+                "port": port,
+                "db": config.db,
+                "user": config.user,
+                "pass": config.pass,
+            });
+            let config = CString::new(config.to_string())?;
+            let conn = connect(config.as_ptr());
+            if conn.is_null() {
+                return Err(anyhow!("engine plugin {path} failed to connect"));
+            }
+
+            Ok(Self {
+                conn,
+                run,
+                free_string,
+                close,
+                _library: library,
+            })
+        }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        // SAFETY: the connection is not used after being closed.
+        unsafe { (self.close)(self.conn) }
+    }
+}
+
+impl sqllogictest::DB for Plugin {
+    type Error = PluginError;
+
+    fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let sql = CString::new(sql)?;
+        let mut output = std::ptr::null_mut();
+        // SAFETY: `output` is either left null, or set to a string owned by the plugin, which is
+        // copied and then freed by the plugin.
+        let (ret, output) = unsafe {
+            let ret = (self.run)(self.conn, sql.as_ptr(), &mut output);
+            let s = if output.is_null() {
+                String::new()
+            } else {
+                let s = CStr::from_ptr(output).to_string_lossy().into_owned();
+                (self.free_string)(output);
+                s
+            };
+            (ret, s)
+        };
+        match ret {
+            0 => Ok(parse_text_output(&output)),
+            _ => Err(PluginError::Sql(output)),
+        }
+    }
+
+    fn engine_name(&self) -> &str {
+        "plugin"
+    }
+}
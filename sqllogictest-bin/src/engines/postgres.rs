@@ -1,43 +1,167 @@
+use std::future::Future;
 use std::sync::Arc;
 
 use anyhow::Context;
 use async_trait::async_trait;
+use sqllogictest::DBOutput;
 use tokio::task::JoinHandle;
 
-use crate::{DBConfig, Result};
+use crate::{ChannelBinding, DBConfig, Result, SslMode};
 
+/// Postgres engine using the simple query protocol. Values are rendered as text by the server,
+/// like in `psql`, so every type is supported without decoding it in Rust. See
+/// [`PostgresExtended`](super::postgres_extended::PostgresExtended) for the extended protocol.
 pub struct Postgres {
     client: Arc<tokio_postgres::Client>,
     join_handle: JoinHandle<()>,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
 }
 
 impl Postgres {
     pub(super) async fn connect(config: &DBConfig) -> Result<Self> {
-        let (host, port) = config.random_addr();
-
-        let (client, connection) = tokio_postgres::Config::new()
-            .host(host)
-            .port(port)
-            .dbname(&config.db)
-            .user(&config.user)
-            .password(&config.pass)
-            .connect(tokio_postgres::NoTls)
-            .await
-            .context(format!("failed to connect to postgres at {host}:{port}"))?;
-
-        let join_handle = tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                log::error!("Postgres connection error: {:?}", e);
-            }
-        });
+        let (client, join_handle) = connect_pg(config, "Postgres").await?;
 
         Ok(Self {
             client: Arc::new(client),
             join_handle,
+            null_string: config.null_string.clone(),
         })
     }
 }
 
+/// Connects to postgres with TLS according to [`DBConfig::ssl_mode`], and spawns the connection.
+/// `name` is used in the log of connection errors.
+pub(super) async fn connect_pg(
+    config: &DBConfig,
+    name: &'static str,
+) -> Result<(tokio_postgres::Client, JoinHandle<()>)> {
+    let (pg_config, addr) = pg_config(config)?;
+    let context = || format!("failed to connect to postgres at {addr}");
+    let (client, join_handle) = match config.ssl_mode {
+        SslMode::Disable => {
+            let (client, connection) = pg_config
+                .connect(tokio_postgres::NoTls)
+                .await
+                .with_context(context)?;
+            (client, spawn_connection(connection, name))
+        }
+        #[cfg(feature = "postgres-tls")]
+        _ => {
+            let (client, connection) = pg_config
+                .connect(make_tls(config)?)
+                .await
+                .with_context(context)?;
+            (client, spawn_connection(connection, name))
+        }
+        #[cfg(not(feature = "postgres-tls"))]
+        _ => anyhow::bail!("sqllogictest-bin is built without the `postgres-tls` feature"),
+    };
+    Ok((client, join_handle))
+}
+
+/// Returns the statement which sets the `statement_timeout` of the server to
+/// [`DBConfig::statement_timeout`], so that the server cancels SQL running longer than it.
+pub(super) fn statement_timeout_sql(config: &DBConfig) -> Option<String> {
+    config
+        .statement_timeout
+        .map(|timeout| format!("SET statement_timeout = {}", timeout.as_millis()))
+}
+
+fn spawn_connection(
+    connection: impl Future<Output = Result<(), tokio_postgres::Error>> + Send + 'static,
+    name: &'static str,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::error!("{} connection error: {:?}", name, e);
+        }
+    })
+}
+
+/// Builds the TLS connector. Like libpq, the server certificate is only verified for
+/// `verify-ca` and `verify-full`, or `require` with a root certificate, and the host name is
+/// only verified for `verify-full`.
+#[cfg(feature = "postgres-tls")]
+pub(super) fn make_tls(config: &DBConfig) -> Result<postgres_native_tls::MakeTlsConnector> {
+    use native_tls::{Certificate, Identity, TlsConnector};
+
+    let mut builder = TlsConnector::builder();
+    match config.ssl_mode {
+        SslMode::Require if config.ssl_root_cert.is_some() => {
+            builder.danger_accept_invalid_hostnames(true);
+        }
+        SslMode::Prefer | SslMode::Require => {
+            builder.danger_accept_invalid_certs(true);
+        }
+        SslMode::VerifyCa => {
+            builder.danger_accept_invalid_hostnames(true);
+        }
+        SslMode::Disable | SslMode::VerifyFull => {}
+    }
+    if let Some(path) = &config.ssl_root_cert {
+        let cert = std::fs::read(path).context(format!(
+            "failed to read root certificate {}",
+            path.display()
+        ))?;
+        builder.add_root_certificate(Certificate::from_pem(&cert)?);
+    }
+    match (&config.ssl_cert, &config.ssl_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert = std::fs::read(cert_path).context(format!(
+                "failed to read client certificate {}",
+                cert_path.display()
+            ))?;
+            let key = std::fs::read(key_path)
+                .context(format!("failed to read client key {}", key_path.display()))?;
+            builder.identity(
+                Identity::from_pkcs8(&cert, &key)
+                    .context("failed to load client certificate and key")?,
+            );
+        }
+        (None, None) => {}
+        _ => anyhow::bail!("`--ssl-cert` and `--ssl-key` must be given together"),
+    }
+    Ok(postgres_native_tls::MakeTlsConnector::new(builder.build()?))
+}
+
+/// Returns the connection config of the postgres engines, and the address for error messages.
+/// Connects through the Unix domain socket in [`DBConfig::socket_dir`] if given.
+pub(super) fn pg_config(config: &DBConfig) -> Result<(tokio_postgres::Config, String)> {
+    let (host, port) = config.addr();
+
+    let mut pg_config = tokio_postgres::Config::new();
+    pg_config
+        .port(port)
+        .dbname(&config.db)
+        .user(&config.user)
+        .password(&config.pass)
+        .ssl_mode(match config.ssl_mode {
+            SslMode::Disable => tokio_postgres::config::SslMode::Disable,
+            SslMode::Prefer => tokio_postgres::config::SslMode::Prefer,
+            _ => tokio_postgres::config::SslMode::Require,
+        })
+        .channel_binding(match config.channel_binding {
+            ChannelBinding::Disable => tokio_postgres::config::ChannelBinding::Disable,
+            ChannelBinding::Prefer => tokio_postgres::config::ChannelBinding::Prefer,
+            ChannelBinding::Require => tokio_postgres::config::ChannelBinding::Require,
+        });
+    let addr = match &config.socket_dir {
+        #[cfg(unix)]
+        Some(dir) => {
+            pg_config.host_path(dir);
+            format!("{}/.s.PGSQL.{port}", dir.display())
+        }
+        #[cfg(not(unix))]
+        Some(_) => anyhow::bail!("unix domain sockets are not supported on this platform"),
+        None => {
+            pg_config.host(host);
+            format!("{host}:{port}")
+        }
+    };
+    Ok((pg_config, addr))
+}
+
 impl Drop for Postgres {
     fn drop(&mut self) {
         self.join_handle.abort()
@@ -48,45 +172,122 @@ impl Drop for Postgres {
 impl sqllogictest::AsyncDB for Postgres {
     type Error = tokio_postgres::error::Error;
 
-    async fn run(&mut self, sql: &str) -> Result<String, Self::Error> {
-        use std::fmt::Write;
-
-        let mut output = String::new();
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
         // NOTE:
         // We use `simple_query` API which returns the query results as strings.
         // This means that we can not reformat values based on their type,
         // and we have to follow the format given by the specific database (pg).
         // For example, postgres will output `t` as true and `f` as false,
         // thus we have to write `t`/`f` in the expected results.
-        let rows = self.client.simple_query(sql).await?;
-        for row in rows {
-            match row {
-                tokio_postgres::SimpleQueryMessage::Row(row) => {
-                    for i in 0..row.len() {
-                        if i != 0 {
-                            write!(output, " ").unwrap();
-                        }
-                        match row.get(i) {
-                            Some(v) => {
-                                if v.is_empty() {
-                                    write!(output, "(empty)").unwrap()
-                                } else {
-                                    write!(output, "{}", v).unwrap()
-                                }
-                            }
-                            None => write!(output, "NULL").unwrap(),
-                        }
-                    }
-                }
-                tokio_postgres::SimpleQueryMessage::CommandComplete(_) => {}
-                _ => unreachable!(),
-            }
-            writeln!(output).unwrap();
-        }
-        Ok(output)
+        let messages = self.client.simple_query(sql).await?;
+        Ok(format_simple_query(messages, &self.null_string))
+    }
+
+    async fn run_batch(&mut self, sqls: &[String]) -> Vec<Result<DBOutput, Self::Error>> {
+        run_pipelined(&self.client, sqls)
+            .await
+            .into_iter()
+            .map(|messages| Ok(format_simple_query(messages?, &self.null_string)))
+            .collect()
     }
 
     fn engine_name(&self) -> &str {
         "postgres"
     }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "arrays" | "decimals" | "transactions" | "mvcc")
+    }
+}
+
+/// Runs the statements with the simple query protocol, pipelined in one round trip. Each of them
+/// is a separate query, so it runs in its own implicit transaction as if run alone, and fails
+/// without affecting the others.
+pub(super) async fn run_pipelined(
+    client: &tokio_postgres::Client,
+    sqls: &[String],
+) -> Vec<Result<Vec<tokio_postgres::SimpleQueryMessage>, tokio_postgres::Error>> {
+    // The queries are sent in order when the futures are first polled.
+    futures::future::join_all(sqls.iter().map(|sql| client.simple_query(sql))).await
+}
+
+/// Returns `Some(true)` if the SQL opens a transaction block, e.g. `BEGIN`, `Some(false)` if it
+/// closes it, e.g. `COMMIT` or `ROLLBACK`, or `None` otherwise. The client keeps track of the
+/// transaction block with it, as tokio-postgres does not expose the status of the connection.
+pub(super) fn transaction_change(sql: &str) -> Option<bool> {
+    let sql = sql.to_ascii_lowercase();
+    let mut words = sql
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .filter(|w| !w.is_empty());
+    match (words.next()?, words.next()) {
+        ("begin", _) | ("start", Some("transaction")) => Some(true),
+        ("rollback", Some("to")) | ("commit" | "rollback", Some("prepared")) => None,
+        ("commit" | "end" | "rollback" | "abort", _) => Some(false),
+        ("prepare", Some("transaction")) => Some(false),
+        _ => None,
+    }
+}
+
+/// Formats the result of a simple query, with `NULL`s written as `null_string`. It is the rows
+/// of the queries if any of them returns rows, or else the number of rows affected by the last
+/// statement. The names of the columns are those of the last query. The types of the columns are
+/// not known, as the server sends values as text.
+pub(super) fn format_simple_query(
+    messages: Vec<tokio_postgres::SimpleQueryMessage>,
+    null_string: &str,
+) -> DBOutput {
+    let mut names = vec![];
+    let mut rows = vec![];
+    let mut has_rows = false;
+    let mut rows_affected = 0;
+    for message in messages {
+        match message {
+            tokio_postgres::SimpleQueryMessage::Row(row) => {
+                let row = (0..row.len())
+                    .map(|i| match row.get(i) {
+                        Some("") => "(empty)".to_string(),
+                        Some(v) => v.to_string(),
+                        None => null_string.to_string(),
+                    })
+                    .collect();
+                rows.push(row);
+                has_rows = true;
+            }
+            tokio_postgres::SimpleQueryMessage::RowDescription(columns) => {
+                names = columns.iter().map(|c| c.name().to_string()).collect();
+                has_rows = true;
+            }
+            tokio_postgres::SimpleQueryMessage::CommandComplete(count) => rows_affected = count,
+            _ => {}
+        }
+    }
+    if has_rows {
+        DBOutput::Rows {
+            names,
+            types: vec![],
+            rows,
+        }
+    } else {
+        DBOutput::StatementComplete(Some(rows_affected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_change() {
+        assert_eq!(transaction_change("BEGIN"), Some(true));
+        assert_eq!(
+            transaction_change("START TRANSACTION READ ONLY;"),
+            Some(true)
+        );
+        assert_eq!(transaction_change("COMMIT"), Some(false));
+        assert_eq!(transaction_change("ROLLBACK"), Some(false));
+        assert_eq!(transaction_change("ROLLBACK TO SAVEPOINT s1"), None);
+        assert_eq!(transaction_change("COMMIT PREPARED 'x'"), None);
+        assert_eq!(transaction_change("PREPARE TRANSACTION 'x'"), Some(false));
+        assert_eq!(transaction_change("SELECT 1"), None);
+    }
 }
@@ -1,45 +1,186 @@
+use std::net::IpAddr;
 use std::sync::Arc;
 
-use anyhow::Context;
 use async_trait::async_trait;
+use bytes::BytesMut;
 use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
-use pg_interval::Interval;
-use postgres_types::Type;
+use futures::TryStreamExt;
+use hashlink::LruCache;
+use itertools::Itertools;
+use postgres_types::{to_sql_checked, FromSql, IsNull, Kind, ToSql, Type};
 use rust_decimal::Decimal;
+use sqllogictest::{ColumnType, DBOutput, Protocol};
 use tokio::task::JoinHandle;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::{RowStream, SimpleQueryMessage, Statement};
+use uuid::Uuid;
 
-use crate::{DBConfig, Result};
+use super::postgres::{connect_pg, format_simple_query, run_pipelined, transaction_change};
+use crate::{ArrayQuoting, CharPadding, DBConfig, Result};
 
 pub struct PostgresExtended {
     client: Arc<tokio_postgres::Client>,
     join_handle: JoinHandle<()>,
+    /// Whether the server is Amazon Redshift, which speaks the postgres protocol, but supports
+    /// fewer types. See `redshift_value_to_str`.
+    redshift: bool,
+    /// See `--max-bytea-len`.
+    max_bytea_len: Option<usize>,
+    /// See `--char-padding`.
+    char_padding: CharPadding,
+    /// See `--array-quoting`.
+    array_quoting: ArrayQuoting,
+    /// See `--strict-types`.
+    strict_types: bool,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+    /// See [`DBConfig::normalize_numeric`].
+    normalize_numeric: bool,
+    /// See [`DBConfig::float_precision`]. Overridden by `control float_precision`.
+    float_precision: Option<usize>,
+    /// Prepared statements by their SQL. See `--statement-cache-size`.
+    statements: LruCache<String, Statement>,
+    /// Whether a transaction block is open, in which the cached statements are not used. See
+    /// [`Self::query`].
+    in_transaction: bool,
+    /// See `--max-rows`.
+    max_rows: Option<usize>,
+    /// See [`DBConfig::protocol`]. Overridden by `control protocol`.
+    protocol: Protocol,
 }
 
 impl PostgresExtended {
-    pub(super) async fn connect(config: &DBConfig) -> Result<Self> {
-        let (host, port) = config.random_addr();
-
-        let (client, connection) = tokio_postgres::Config::new()
-            .host(host)
-            .port(port)
-            .dbname(&config.db)
-            .user(&config.user)
-            .password(&config.pass)
-            .connect(tokio_postgres::NoTls)
-            .await
-            .context(format!("failed to connect to postgres at {host}:{port}"))?;
-
-        let join_handle = tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                log::error!("PostgresExtended connection error: {:?}", e);
-            }
-        });
+    pub(super) async fn connect(
+        config: &DBConfig,
+        max_bytea_len: Option<usize>,
+        char_padding: CharPadding,
+        array_quoting: ArrayQuoting,
+        strict_types: bool,
+        statement_cache_size: usize,
+        max_rows: Option<usize>,
+    ) -> Result<Self> {
+        let (client, join_handle) = connect_pg(config, "PostgresExtended").await?;
 
         Ok(Self {
             client: Arc::new(client),
             join_handle,
+            redshift: false,
+            max_bytea_len,
+            char_padding,
+            array_quoting,
+            strict_types,
+            null_string: config.null_string.clone(),
+            normalize_numeric: config.normalize_numeric,
+            float_precision: config.float_precision,
+            statements: LruCache::new(statement_cache_size),
+            in_transaction: false,
+            max_rows,
+            protocol: config.protocol,
         })
     }
+
+    pub(super) async fn connect_redshift(
+        config: &DBConfig,
+        statement_cache_size: usize,
+    ) -> Result<Self> {
+        let mut engine = Self::connect(
+            config,
+            None,
+            CharPadding::Preserve,
+            ArrayQuoting::Postgres,
+            false,
+            statement_cache_size,
+            None,
+        )
+        .await?;
+        engine.redshift = true;
+        Ok(engine)
+    }
+
+    pub(super) fn set_float_precision(&mut self, precision: Option<usize>) {
+        self.float_precision = precision;
+    }
+
+    pub(super) fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    /// Updates whether a transaction block is open after the SQL is run. A failed `COMMIT`
+    /// closes it as well.
+    fn track_transaction(&mut self, sql: &str, ok: bool) {
+        match transaction_change(sql) {
+            Some(true) if ok => self.in_transaction = true,
+            Some(false) => self.in_transaction = false,
+            _ => {}
+        }
+    }
+
+    /// Runs the SQL with a prepared statement, which is cached for the next time the same SQL is
+    /// run. The rows are streamed rather than collected, so that large results are not buffered.
+    ///
+    /// A cached statement is prepared again if it is outdated, i.e. its result type is changed
+    /// by DDL, or it is deallocated by `DISCARD ALL`. Inside a transaction block, the error of
+    /// an outdated statement aborts the transaction, so the cache is not used there. The internal
+    /// queries, which are not run again, pass `cache: false`. The statement is returned with the
+    /// rows for its columns.
+    async fn query(
+        &mut self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+        cache: bool,
+    ) -> Result<(Statement, RowStream), tokio_postgres::Error> {
+        let params = || params.iter().map(|p| *p as &dyn ToSql);
+        let cache = cache && !self.in_transaction && self.statements.capacity() > 0;
+        let cached = match cache {
+            true => self.statements.get(sql).cloned(),
+            false => None,
+        };
+        if let Some(statement) = cached {
+            match self.client.query_raw(&statement, params()).await {
+                Err(e) if is_outdated_statement(&e) => self.statements.clear(),
+                result => return Ok((statement, result?)),
+            }
+        }
+        let statement = self.client.prepare(sql).await?;
+        if cache {
+            self.statements.insert(sql.to_string(), statement.clone());
+        }
+        let rows = self.client.query_raw(&statement, params()).await?;
+        Ok((statement, rows))
+    }
+
+    /// Writes a row of the output, with the values cast by the server taken from `casted`.
+    fn format_row(&self, cells: Vec<Cell>, casted: &[String]) -> Vec<String> {
+        cells
+            .into_iter()
+            .map(|cell| match cell {
+                Cell::Text(text) => text,
+                Cell::Cast(i) => varchar_to_str(&casted[i]),
+                Cell::CastArray(ty, indices) => {
+                    let values = indices
+                        .into_iter()
+                        .map(|i| i.map(|i| casted[i].clone()))
+                        .collect();
+                    self.format_array(&ty, Some(values))
+                }
+            })
+            .collect()
+    }
+
+    /// Formats the elements of an array column like postgres, e.g. `{1,NULL}`, quoted according
+    /// to `--array-quoting`.
+    fn format_array(&self, ty: &Type, values: Option<Vec<Option<String>>>) -> String {
+        match values {
+            Some(values) => {
+                let delimiter = match ty.kind() {
+                    Kind::Array(member) => array_delimiter(base_type(member)),
+                    _ => ',',
+                };
+                elements_to_str(&values, delimiter, self.array_quoting)
+            }
+            None => "NULL".to_string(),
+        }
+    }
 }
 
 impl Drop for PostgresExtended {
@@ -49,84 +190,27 @@ impl Drop for PostgresExtended {
 }
 
 macro_rules! array_process {
-    ($row:ident, $output:ident, $idx:ident, $t:ty) => {
-        let value: Option<Vec<Option<$t>>> = $row.get($idx);
-        match value {
-            Some(value) => {
-                write!($output, "{{").unwrap();
-                for (i, v) in value.iter().enumerate() {
-                    match v {
-                        Some(v) => {
-                            write!($output, "{}", v).unwrap();
-                        }
-                        None => {
-                            write!($output, "NULL").unwrap();
-                        }
-                    }
-                    if i < value.len() - 1 {
-                        write!($output, ",").unwrap();
-                    }
-                }
-                write!($output, "}}").unwrap();
-            }
-            None => {
-                write!($output, "NULL").unwrap();
-            }
-        }
-    };
-    ($row:ident, $output:ident, $idx:ident, $t:ty, $convert:ident) => {
+    ($self:ident, $row:ident, $output:ident, $idx:ident, $t:ty) => {
         let value: Option<Vec<Option<$t>>> = $row.get($idx);
-        match value {
-            Some(value) => {
-                write!($output, "{{").unwrap();
-                for (i, v) in value.iter().enumerate() {
-                    match v {
-                        Some(v) => {
-                            write!($output, "{}", $convert(v)).unwrap();
-                        }
-                        None => {
-                            write!($output, "NULL").unwrap();
-                        }
-                    }
-                    if i < value.len() - 1 {
-                        write!($output, ",").unwrap();
-                    }
-                }
-                write!($output, "}}").unwrap();
-            }
-            None => {
-                write!($output, "NULL").unwrap();
-            }
-        }
+        let value = value.map(|value| {
+            value
+                .iter()
+                .map(|v| v.as_ref().map(|v| v.to_string()))
+                .collect_vec()
+        });
+        let value = $self.format_array($row.columns()[$idx].type_(), value);
+        write!($output, "{}", value).unwrap();
     };
-    ($self:ident, $row:ident, $output:ident, $idx:ident, $t:ty, $ty_name:expr) => {
+    ($self:ident, $row:ident, $output:ident, $idx:ident, $t:ty, $convert:ident) => {
         let value: Option<Vec<Option<$t>>> = $row.get($idx);
-        match value {
-            Some(value) => {
-                write!($output, "{{").unwrap();
-                for (i, v) in value.iter().enumerate() {
-                    match v {
-                        Some(v) => {
-                            let sql = format!("select ($1::{})::varchar", stringify!($ty_name));
-                            let tmp_rows = $self.client.query(&sql, &[&v]).await.unwrap();
-                            let value: &str = tmp_rows.get(0).unwrap().get(0);
-                            assert!(value.len() > 0);
-                            write!($output, "{}", value).unwrap();
-                        }
-                        None => {
-                            write!($output, "NULL").unwrap();
-                        }
-                    }
-                    if i < value.len() - 1 {
-                        write!($output, ",").unwrap();
-                    }
-                }
-                write!($output, "}}").unwrap();
-            }
-            None => {
-                write!($output, "NULL").unwrap();
-            }
-        }
+        let value = value.map(|value| {
+            value
+                .iter()
+                .map(|v| v.as_ref().map(|v| $convert(v).to_string()))
+                .collect_vec()
+        });
+        let value = $self.format_array($row.columns()[$idx].type_(), value);
+        write!($output, "{}", value).unwrap();
     };
 }
 
@@ -153,21 +237,6 @@ macro_rules! single_process {
             }
         }
     };
-    ($self:ident, $row:ident, $output:ident, $idx:ident, $t:ty, $ty_name:expr) => {
-        let value: Option<$t> = $row.get($idx);
-        match value {
-            Some(value) => {
-                let sql = format!("select ($1::{})::varchar", stringify!($ty_name));
-                let tmp_rows = $self.client.query(&sql, &[&value]).await.unwrap();
-                let value: &str = tmp_rows.get(0).unwrap().get(0);
-                assert!(value.len() > 0);
-                write!($output, "{}", value).unwrap();
-            }
-            None => {
-                write!($output, "NULL").unwrap();
-            }
-        }
-    };
 }
 
 fn bool_to_str(value: &bool) -> &'static str {
@@ -186,38 +255,973 @@ fn varchar_to_str(value: &str) -> String {
     }
 }
 
-fn float4_to_str(value: &f32) -> String {
+/// Quotes an identifier for SQL, e.g. `"my ""type"""`.
+fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Formats the bytes as `\x`-prefixed hex like `psql`, truncated to `max_len` bytes followed by
+/// `...` if longer.
+fn bytea_to_str(value: &[u8], max_len: Option<usize>) -> String {
+    use std::fmt::Write;
+
+    let len = max_len.map_or(value.len(), |max_len| value.len().min(max_len));
+    let mut s = String::with_capacity(2 + len * 2 + 3);
+    s.push_str("\\x");
+    for b in &value[..len] {
+        write!(s, "{:02x}", b).unwrap();
+    }
+    if len < value.len() {
+        s.push_str("...");
+    }
+    s
+}
+
+/// Formats a `char(n)`, whose trailing pad spaces are stripped unless [`CharPadding::Preserve`].
+fn bpchar_to_str(value: &str, padding: CharPadding) -> String {
+    match padding {
+        CharPadding::Strip => value.trim_end_matches(' ').to_string(),
+        CharPadding::Preserve => value.to_string(),
+    }
+}
+
+/// Formats an internal `"char"`, which is a single byte. Bytes other than ASCII are escaped in
+/// octal like postgres, e.g. `\377`, and the zero byte is the empty string.
+fn char_to_str(value: &i8) -> String {
+    match *value as u8 {
+        0 => String::new(),
+        b if b.is_ascii() => (b as char).to_string(),
+        b => format!("\\{:03o}", b),
+    }
+}
+
+/// Rounds the float to `precision` significant digits. The result is parsed back, so that it is
+/// written in the shortest form, e.g. `0.3` instead of `3.00e-1`.
+macro_rules! round_float {
+    ($value:expr, $precision:expr) => {
+        match $precision {
+            Some(precision) if $value.is_finite() => {
+                format!("{:.*e}", precision - 1, $value).parse().unwrap()
+            }
+            _ => $value,
+        }
+    };
+}
+
+/// Formats the float like postgres, rounded to `precision` significant digits if given.
+fn float4_to_str(value: &f32, precision: Option<usize>) -> String {
+    let value: f32 = round_float!(*value, precision);
     if value.is_nan() {
         "NaN".to_string()
-    } else if *value == f32::INFINITY {
+    } else if value == f32::INFINITY {
         "Infinity".to_string()
-    } else if *value == f32::NEG_INFINITY {
+    } else if value == f32::NEG_INFINITY {
         "-Infinity".to_string()
     } else {
         value.to_string()
     }
 }
 
-fn float8_to_str(value: &f64) -> String {
+/// Formats the float like postgres, rounded to `precision` significant digits if given.
+fn float8_to_str(value: &f64, precision: Option<usize>) -> String {
+    let value: f64 = round_float!(*value, precision);
     if value.is_nan() {
         "NaN".to_string()
-    } else if *value == f64::INFINITY {
+    } else if value == f64::INFINITY {
         "Infinity".to_string()
-    } else if *value == f64::NEG_INFINITY {
+    } else if value == f64::NEG_INFINITY {
         "-Infinity".to_string()
     } else {
         value.to_string()
     }
 }
 
-#[async_trait]
-impl sqllogictest::AsyncDB for PostgresExtended {
-    type Error = tokio_postgres::error::Error;
+/// A value of any type, whose raw bytes are interpreted as UTF-8 text.
+struct RawText(String);
+
+impl<'a> FromSql<'a> for RawText {
+    fn from_sql(
+        _: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(RawText(String::from_utf8_lossy(raw).into_owned()))
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+}
+
+fn is_enum(ty: &Type) -> bool {
+    matches!(ty.kind(), Kind::Enum(_))
+}
+
+fn raw_text_to_str(value: &RawText) -> String {
+    varchar_to_str(&value.0)
+}
+
+/// A value of any type in the binary format, which can be sent back to the server. See
+/// [`ServerCasts`].
+#[derive(Debug)]
+struct RawValue(Vec<u8>);
+
+impl<'a> FromSql<'a> for RawValue {
+    fn from_sql(
+        _: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(RawValue(raw.to_vec()))
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+}
+
+/// A value of a type which is not supported, whose decoding fails with the name of the type.
+enum Unsupported {}
+
+impl<'a> FromSql<'a> for Unsupported {
+    fn from_sql(
+        ty: &Type,
+        _: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Err(format!("unsupported type {}", ty.name()).into())
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+}
+
+impl ToSql for RawValue {
+    fn to_sql(
+        &self,
+        _: &Type,
+        out: &mut BytesMut,
+    ) -> std::result::Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        out.extend_from_slice(&self.0);
+        Ok(IsNull::No)
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+
+    to_sql_checked!();
+}
+
+/// Values formatted by casting them to `varchar` on the server, for the types whose text
+/// representation is not known locally, e.g. the name of a `REGCLASS` or a `TIMESTAMPTZ` in the
+/// time zone of the session, or the types not supported at all. Every type can be cast to
+/// `varchar` through its text representation.
+///
+/// The values of a result set are collected and cast together, instead of a round trip for each
+/// value.
+#[derive(Default)]
+struct ServerCasts {
+    values: Vec<(Type, RawValue)>,
+}
+
+impl ServerCasts {
+    /// Postgres allows at most 1664 columns in a query.
+    const MAX_VALUES_PER_QUERY: usize = 1000;
+
+    /// Adds a value to be cast, and returns its index in the result of [`ServerCasts::cast`].
+    fn push(&mut self, ty: &Type, value: RawValue) -> usize {
+        self.values.push((ty.clone(), value));
+        self.values.len() - 1
+    }
+
+    /// Casts all the values, with one query for every [`ServerCasts::MAX_VALUES_PER_QUERY`].
+    async fn cast(
+        &self,
+        engine: &mut PostgresExtended,
+    ) -> Result<Vec<String>, tokio_postgres::Error> {
+        let mut output = Vec::with_capacity(self.values.len());
+        for values in self.values.chunks(Self::MAX_VALUES_PER_QUERY) {
+            // The type may be in a schema outside of the `search_path`.
+            let sql = format!(
+                "select {}",
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (ty, _))| format!(
+                        "(${}::{}.{})::varchar",
+                        i + 1,
+                        quote_ident(ty.schema()),
+                        quote_ident(ty.name())
+                    ))
+                    .join(", ")
+            );
+            let params = values
+                .iter()
+                .map(|(_, value)| value as &(dyn ToSql + Sync))
+                .collect_vec();
+            let (_, rows) = engine.query(&sql, &params, false).await?;
+            let rows: Vec<_> = rows.try_collect().await?;
+            output.extend((0..rows[0].len()).map(|i| rows[0].get::<_, String>(i)));
+        }
+        Ok(output)
+    }
+}
+
+/// A value in the output of a query, which is formatted after the [`ServerCasts`] of its result
+/// set.
+enum Cell {
+    Text(String),
+    /// The index of a value in the [`ServerCasts`].
+    Cast(usize),
+    /// An array of the given type, with the indices of its elements in the [`ServerCasts`].
+    CastArray(Type, Vec<Option<usize>>),
+}
+
+/// Whether the error is caused by running an outdated prepared statement. See
+/// [`PostgresExtended::query`].
+fn is_outdated_statement(e: &tokio_postgres::Error) -> bool {
+    match e.as_db_error() {
+        Some(e) => {
+            (*e.code() == SqlState::FEATURE_NOT_SUPPORTED
+                && e.message() == "cached plan must not change result type")
+                || *e.code() == SqlState::INVALID_SQL_STATEMENT_NAME
+        }
+        None => false,
+    }
+}
+
+/// Whether the type is an OID alias like `REGCLASS`, whose text representation is a name looked
+/// up on the server.
+fn is_reg(ty: &Type) -> bool {
+    matches!(
+        *ty,
+        Type::REGPROC
+            | Type::REGPROCEDURE
+            | Type::REGOPER
+            | Type::REGOPERATOR
+            | Type::REGCLASS
+            | Type::REGTYPE
+            | Type::REGCONFIG
+            | Type::REGDICTIONARY
+            | Type::REGNAMESPACE
+            | Type::REGROLE
+            | Type::REGCOLLATION
+    )
+}
+
+/// A `JSON` or `JSONB` value in the text representation of postgres, i.e. as shown by `psql`.
+///
+/// Unlike `serde_json::Value`, the key order and whitespace are kept, e.g. `{"b": 1, "a": 2}` for
+/// `JSONB`, whose binary format is a version byte followed by the text.
+struct Json(String);
+
+impl<'a> FromSql<'a> for Json {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let text = match *ty {
+            Type::JSONB => match raw.split_first() {
+                Some((1, text)) => text,
+                _ => return Err("unsupported jsonb version".into()),
+            },
+            _ => raw,
+        };
+        Ok(Json(std::str::from_utf8(text)?.to_string()))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::JSON | Type::JSONB)
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// An `INET` or `CIDR` value, formatted like postgres, i.e. with the netmask unless it is a host
+/// address of `INET`.
+struct Inet(String);
+
+impl<'a> FromSql<'a> for Inet {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        // The family, the netmask bits, whether it is a `CIDR`, and the length of the address.
+        let (addr, bits, max_bits) = match raw {
+            [2, bits, _, 4, addr @ ..] if addr.len() == 4 => {
+                let addr: [u8; 4] = addr.try_into().unwrap();
+                (IpAddr::from(addr), *bits, 32)
+            }
+            [3, bits, _, 16, addr @ ..] if addr.len() == 16 => {
+                let addr: [u8; 16] = addr.try_into().unwrap();
+                (IpAddr::from(addr), *bits, 128)
+            }
+            _ => return Err(format!("invalid {}", ty.name()).into()),
+        };
+        if *ty == Type::INET && bits == max_bits {
+            Ok(Inet(addr.to_string()))
+        } else {
+            Ok(Inet(format!("{}/{}", addr, bits)))
+        }
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::INET | Type::CIDR)
+    }
+}
+
+impl std::fmt::Display for Inet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A `MACADDR` or `MACADDR8` value, formatted like `08:00:2b:01:02:03`.
+struct MacAddr(String);
+
+impl<'a> FromSql<'a> for MacAddr {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let len = if *ty == Type::MACADDR8 { 8 } else { 6 };
+        if raw.len() != len {
+            return Err(format!("invalid {}", ty.name()).into());
+        }
+        Ok(MacAddr(raw.iter().map(|b| format!("{:02x}", b)).join(":")))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::MACADDR | Type::MACADDR8)
+    }
+}
+
+impl std::fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A `BIT` or `VARBIT` value, formatted as its bits, e.g. `1010`.
+struct BitString(String);
+
+impl<'a> FromSql<'a> for BitString {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        // The number of bits, followed by the bits padded to whole bytes.
+        let (len, bytes) = match raw {
+            [a, b, c, d, bytes @ ..] => (i32::from_be_bytes([*a, *b, *c, *d]) as usize, bytes),
+            _ => return Err(format!("invalid {}", ty.name()).into()),
+        };
+        if bytes.len() * 8 < len {
+            return Err(format!("invalid {}", ty.name()).into());
+        }
+        let bits = (0..len)
+            .map(|i| {
+                if bytes[i / 8] & (0x80 >> (i % 8)) != 0 {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect();
+        Ok(BitString(bits))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::BIT | Type::VARBIT)
+    }
+}
+
+impl std::fmt::Display for BitString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A `MONEY` value, formatted without the currency symbol and group separators of the locale,
+/// like the cast to `NUMERIC`, e.g. `-1234.56`.
+///
+/// The value is an amount in the smallest currency unit, which is assumed to be 1/100 like in
+/// most locales.
+struct Money(i64);
 
-    async fn run(&mut self, sql: &str) -> Result<String, Self::Error> {
+impl<'a> FromSql<'a> for Money {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let raw: [u8; 8] = raw
+            .try_into()
+            .map_err(|_| format!("invalid {}", ty.name()))?;
+        Ok(Money(i64::from_be_bytes(raw)))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::MONEY
+    }
+}
+
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+}
+
+/// A value of a geometric type, i.e. `POINT`, `LINE`, `LSEG`, `BOX`, `PATH`, `POLYGON` or
+/// `CIRCLE`, decoded locally and formatted like postgres, e.g. `(1,2)` for a point.
+struct Geometry(String);
+
+impl<'a> FromSql<'a> for Geometry {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let invalid = || format!("invalid {}", ty.name());
+        // `PATH` starts with whether it is closed, and `PATH` and `POLYGON` with the number of
+        // points, which is implied by the length.
+        let (closed, raw) = match (ty, raw) {
+            (&Type::PATH, [closed, _, _, _, _, raw @ ..]) => (*closed != 0, raw),
+            (&Type::POLYGON, [_, _, _, _, raw @ ..]) => (true, raw),
+            (&Type::PATH | &Type::POLYGON, _) => return Err(invalid().into()),
+            _ => (true, raw),
+        };
+        if raw.len() % 8 != 0 {
+            return Err(invalid().into());
+        }
+        let floats = raw
+            .chunks(8)
+            .map(|v| float8_to_str(&f64::from_be_bytes(v.try_into().unwrap()), None))
+            .collect_vec();
+        let points = || {
+            floats
+                .chunks(2)
+                .map(|point| format!("({})", point.join(",")))
+                .join(",")
+        };
+        let value = match (ty, floats.as_slice()) {
+            (&Type::POINT, [_, _]) | (&Type::BOX, [_, _, _, _]) => points(),
+            (&Type::LSEG, [_, _, _, _]) => format!("[{}]", points()),
+            (&Type::LINE, [a, b, c]) => format!("{{{},{},{}}}", a, b, c),
+            (&Type::CIRCLE, [x, y, r]) => format!("<({},{}),{}>", x, y, r),
+            (&Type::PATH | &Type::POLYGON, _) if floats.len() % 2 == 0 => {
+                if closed {
+                    format!("({})", points())
+                } else {
+                    format!("[{}]", points())
+                }
+            }
+            _ => return Err(invalid().into()),
+        };
+        Ok(Geometry(value))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(
+            *ty,
+            Type::POINT
+                | Type::LINE
+                | Type::LSEG
+                | Type::BOX
+                | Type::PATH
+                | Type::POLYGON
+                | Type::CIRCLE
+        )
+    }
+}
+
+impl std::fmt::Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A value of a built-in range or multirange type, formatted like postgres, e.g. `[1,10)`,
+/// `(,2020-01-01]`, `empty` or `{[1,3),[5,7)}`.
+///
+/// The columns of `TSTZRANGE` and `TSTZMULTIRANGE` are cast by the server instead, like
+/// `TIMESTAMPTZ`, so that they are formatted in the time zone of the session.
+#[derive(Debug)]
+struct Range {
+    text: String,
+}
+
+impl Range {
+    const EMPTY: u8 = 0x01;
+    const LOWER_INCLUSIVE: u8 = 0x02;
+    const UPPER_INCLUSIVE: u8 = 0x04;
+    const LOWER_INFINITE: u8 = 0x08;
+    const UPPER_INFINITE: u8 = 0x10;
+
+    /// Formats a range of `element`s, e.g. `[1,10)`.
+    fn range_to_str(
+        element: &Type,
+        raw: &[u8],
+    ) -> std::result::Result<String, Box<dyn std::error::Error + Sync + Send>> {
+        let (flags, mut buf) = raw.split_first().ok_or("invalid range")?;
+        if flags & Range::EMPTY != 0 {
+            return Ok("empty".to_string());
+        }
+        // Each finite bound is prefixed by its length.
+        let mut bounds = [String::new(), String::new()];
+        for (bound, infinite) in bounds
+            .iter_mut()
+            .zip([Range::LOWER_INFINITE, Range::UPPER_INFINITE])
+        {
+            if flags & infinite == 0 {
+                let (value, rest) = split_value(buf)?;
+                buf = rest;
+                let value = value_to_str(element, value.ok_or("invalid range")?)?;
+                *bound = quote(&value, "\"\\,()[]");
+            }
+        }
+        let [lower, upper] = bounds;
+        Ok(format!(
+            "{}{},{}{}",
+            if flags & Range::LOWER_INCLUSIVE != 0 {
+                '['
+            } else {
+                '('
+            },
+            lower,
+            upper,
+            if flags & Range::UPPER_INCLUSIVE != 0 {
+                ']'
+            } else {
+                ')'
+            },
+        ))
+    }
+}
+
+impl<'a> FromSql<'a> for Range {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let text = match ty.kind() {
+            Kind::Range(element) => Range::range_to_str(element, raw)?,
+            Kind::Multirange(element) => {
+                // The number of ranges, followed by the ranges.
+                let (count, mut buf) = split_i32(raw)?;
+                let ranges = (0..count)
+                    .map(|_| {
+                        let (range, rest) = split_value(buf)?;
+                        buf = rest;
+                        Range::range_to_str(element, range.ok_or("invalid range")?)
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                format!("{{{}}}", ranges.join(","))
+            }
+            _ => return Err(format!("{} is not a range", ty.name()).into()),
+        };
+        Ok(Range { text })
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(
+            *ty,
+            Type::INT4_RANGE
+                | Type::INT8_RANGE
+                | Type::NUM_RANGE
+                | Type::DATE_RANGE
+                | Type::TS_RANGE
+                | Type::TSTZ_RANGE
+                | Type::INT4MULTI_RANGE
+                | Type::INT8MULTI_RANGE
+                | Type::NUMMULTI_RANGE
+                | Type::DATEMULTI_RANGE
+                | Type::TSMULTI_RANGE
+                | Type::TSTZMULTI_RANGE
+        )
+    }
+}
+
+impl std::fmt::Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.text.fmt(f)
+    }
+}
+
+/// A value of a composite type or an anonymous `RECORD`, formatted like postgres, e.g.
+/// `(1,"a b",)`, where the last field is `NULL`.
+///
+/// `RECORD[]` is also decoded here, as tokio-postgres does not know it as an array.
+struct Composite(String);
+
+impl<'a> FromSql<'a> for Composite {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        if *ty == Type::RECORD_ARRAY {
+            return Ok(Composite(array_to_str(&Type::RECORD, raw)?));
+        }
+
+        let fields = match ty.kind() {
+            Kind::Composite(fields) => fields.as_slice(),
+            _ => &[],
+        };
+        // The number of fields, followed by the OID and the value of each field.
+        let (count, mut buf) = split_i32(raw)?;
+        let mut values = vec![];
+        for i in 0..count as usize {
+            let (oid, rest) = split_i32(buf)?;
+            let (value, rest) = split_value(rest)?;
+            buf = rest;
+            let value = match value {
+                Some(value) => {
+                    // The fields of anonymous records are only known by their OIDs.
+                    let field_ty = match fields.get(i) {
+                        Some(field) => field.type_().clone(),
+                        None => Type::from_oid(oid as u32)
+                            .ok_or_else(|| format!("unsupported type {} in record", oid))?,
+                    };
+                    quote(&value_to_str(&field_ty, value)?, "\"\\,()")
+                }
+                None => String::new(),
+            };
+            values.push(value);
+        }
+        Ok(Composite(format!("({})", values.join(","))))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        is_composite(ty)
+    }
+}
+
+impl std::fmt::Display for Composite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+fn is_composite(ty: &Type) -> bool {
+    *ty == Type::RECORD || *ty == Type::RECORD_ARRAY || matches!(ty.kind(), Kind::Composite(_))
+}
+
+fn is_domain(ty: &Type) -> bool {
+    matches!(ty.kind(), Kind::Domain(_))
+}
+
+/// Resolves a domain to its base type, i.e. `pg_type.typbasetype`, which may be a domain itself.
+fn base_type(ty: &Type) -> &Type {
+    match ty.kind() {
+        Kind::Domain(base) => base_type(base),
+        _ => ty,
+    }
+}
+
+/// Splits an `INT4` from the front of a binary value.
+fn split_i32(buf: &[u8]) -> std::result::Result<(i32, &[u8]), &'static str> {
+    match buf {
+        [a, b, c, d, rest @ ..] => Ok((i32::from_be_bytes([*a, *b, *c, *d]), rest)),
+        _ => Err("unexpected end of value"),
+    }
+}
+
+/// Splits a value prefixed by its length from the front of a binary value, where `None` is a
+/// `NULL`.
+fn split_value(buf: &[u8]) -> std::result::Result<(Option<&[u8]>, &[u8]), &'static str> {
+    let (len, rest) = split_i32(buf)?;
+    match usize::try_from(len) {
+        Ok(len) if len <= rest.len() => {
+            let (value, rest) = rest.split_at(len);
+            Ok((Some(value), rest))
+        }
+        Ok(_) => Err("unexpected end of value"),
+        Err(_) => Ok((None, rest)),
+    }
+}
+
+/// Formats a binary array of `element`s like the array columns, e.g. `{1,NULL}`.
+fn array_to_str(
+    element: &Type,
+    raw: &[u8],
+) -> std::result::Result<String, Box<dyn std::error::Error + Sync + Send>> {
+    Ok(elements_to_str(
+        &array_elements(element, raw)?,
+        array_delimiter(element),
+        ArrayQuoting::Postgres,
+    ))
+}
+
+/// Formats the elements of a binary array of `element`s, which are `None` for `NULL`.
+fn array_elements(
+    element: &Type,
+    raw: &[u8],
+) -> std::result::Result<Vec<Option<String>>, Box<dyn std::error::Error + Sync + Send>> {
+    // The number of dimensions, whether there are `NULL`s, the element type, the length and the
+    // lower bound of each dimension, and then the elements.
+    let (ndim, buf) = split_i32(raw)?;
+    let mut buf = buf.get(8..).ok_or("invalid array")?;
+    let mut len = if ndim == 0 { 0 } else { 1 };
+    for _ in 0..ndim {
+        let (dim, rest) = split_i32(buf)?;
+        buf = rest.get(4..).ok_or("invalid array")?;
+        len *= dim;
+    }
+    let mut values = vec![];
+    for _ in 0..len {
+        let (value, rest) = split_value(buf)?;
+        buf = rest;
+        values.push(
+            value
+                .map(|value| value_to_str(element, value))
+                .transpose()?,
+        );
+    }
+    Ok(values)
+}
+
+/// Returns the delimiter of the elements in arrays of `element`s. Boxes contain commas, so they
+/// are delimited by semicolons.
+fn array_delimiter(element: &Type) -> char {
+    if *element == Type::BOX {
+        ';'
+    } else {
+        ','
+    }
+}
+
+/// Formats the elements of an array, e.g. `{1,NULL}`. With [`ArrayQuoting::Legacy`], the
+/// elements are written as they are and delimited by commas, except that the empty string is
+/// `(empty)`.
+fn elements_to_str(values: &[Option<String>], delimiter: char, quoting: ArrayQuoting) -> String {
+    let values = values.iter().map(|v| match (v, quoting) {
+        (Some(v), ArrayQuoting::Postgres) => quote_element(v, delimiter),
+        (Some(v), ArrayQuoting::Legacy) => varchar_to_str(v),
+        (None, _) => "NULL".to_string(),
+    });
+    let delimiter = match quoting {
+        ArrayQuoting::Postgres => delimiter,
+        ArrayQuoting::Legacy => ',',
+    };
+    format!("{{{}}}", values.format(&delimiter.to_string()))
+}
+
+/// Quotes an array element like postgres, if it is empty or `NULL`, or contains whitespace, the
+/// `delimiter` or one of `"\{}`. Unlike [`quote`], quotes are escaped by a backslash.
+fn quote_element(value: &str, delimiter: char) -> String {
+    if value.is_empty()
+        || value.eq_ignore_ascii_case("NULL")
+        || value.contains(|c: char| c == delimiter || "\"\\{}".contains(c) || c.is_whitespace())
+    {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Quotes a value in a range or a composite like postgres, if it is empty or contains whitespace
+/// or one of the `special` characters.
+fn quote(value: &str, special: &str) -> String {
+    if value.is_empty() || value.contains(|c: char| special.contains(c) || c.is_whitespace()) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Formats a binary value in the text representation of postgres, for the bounds of ranges, the
+/// fields of composites and the elements of their arrays. Unlike the columns, `TIMESTAMPTZ` is
+/// formatted in UTC, and the empty string is not replaced by `(empty)`.
+fn value_to_str(
+    ty: &Type,
+    raw: &[u8],
+) -> std::result::Result<String, Box<dyn std::error::Error + Sync + Send>> {
+    Ok(match *ty {
+        Type::BOOL => bool_to_str(&bool::from_sql(ty, raw)?).to_string(),
+        Type::INT2 => i16::from_sql(ty, raw)?.to_string(),
+        Type::INT4 => i32::from_sql(ty, raw)?.to_string(),
+        Type::INT8 => i64::from_sql(ty, raw)?.to_string(),
+        Type::OID => u32::from_sql(ty, raw)?.to_string(),
+        Type::FLOAT4 => float4_to_str(&f32::from_sql(ty, raw)?, None),
+        Type::FLOAT8 => float8_to_str(&f64::from_sql(ty, raw)?, None),
+        Type::NUMERIC => Decimal::from_sql(ty, raw)?.to_string(),
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME | Type::UNKNOWN => {
+            String::from_utf8(raw.to_vec())?
+        }
+        Type::CHAR => char_to_str(&i8::from_sql(ty, raw)?),
+        Type::DATE => NaiveDate::from_sql(ty, raw)?.to_string(),
+        Type::TIME => NaiveTime::from_sql(ty, raw)?.to_string(),
+        Type::TIMESTAMP => NaiveDateTime::from_sql(ty, raw)?.to_string(),
+        Type::TIMESTAMPTZ => DateTime::<chrono::Utc>::from_sql(ty, raw)?
+            .format("%Y-%m-%d %H:%M:%S%.f+00")
+            .to_string(),
+        Type::UUID => Uuid::from_sql(ty, raw)?.to_string(),
+        Type::BYTEA => bytea_to_str(raw, None),
+        Type::MONEY => Money::from_sql(ty, raw)?.to_string(),
+        Type::BIT | Type::VARBIT => BitString::from_sql(ty, raw)?.0,
+        _ if <Json as FromSql>::accepts(ty) => Json::from_sql(ty, raw)?.0,
+        _ if <Inet as FromSql>::accepts(ty) => Inet::from_sql(ty, raw)?.0,
+        _ if <MacAddr as FromSql>::accepts(ty) => MacAddr::from_sql(ty, raw)?.0,
+        _ if <Geometry as FromSql>::accepts(ty) => Geometry::from_sql(ty, raw)?.0,
+        _ if <Range as FromSql>::accepts(ty) => Range::from_sql(ty, raw)?.text,
+        _ if is_composite(ty) => Composite::from_sql(ty, raw)?.0,
+        _ if is_enum(ty) => String::from_utf8(raw.to_vec())?,
+        Type::RECORD_ARRAY => array_to_str(&Type::RECORD, raw)?,
+        _ => match ty.kind() {
+            Kind::Array(member) => array_to_str(member, raw)?,
+            Kind::Domain(base) => value_to_str(base, raw)?,
+            _ => return Err(format!("unsupported type {}", ty.name()).into()),
+        },
+    })
+}
+
+/// Formats a `NUMERIC` with its scale, e.g. `1.50`, or without the trailing zeros of the
+/// fraction if `normalize`, e.g. `1.5`. See `--normalize-numeric`.
+fn numeric_to_str(value: &str, normalize: bool) -> String {
+    if !normalize || !value.contains('.') {
+        return value.to_string();
+    }
+    match value.trim_end_matches('0').trim_end_matches('.') {
+        "-0" => "0".to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// Returns the class of the column type, which the type string of a query is checked against.
+fn column_type(ty: &Type) -> ColumnType {
+    match *ty {
+        Type::INT2 | Type::INT4 | Type::INT8 | Type::OID => ColumnType::Integer,
+        Type::FLOAT4 | Type::FLOAT8 | Type::NUMERIC => ColumnType::FloatingPoint,
+        Type::BOOL => ColumnType::Boolean,
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::CHAR | Type::NAME => ColumnType::Text,
+        _ => ColumnType::Any,
+    }
+}
+
+/// A `NUMERIC` of any precision, formatted with its scale.
+///
+/// Redshift decimals have up to 38 digits, which do not fit in a [`Decimal`].
+struct Numeric(String);
+
+impl<'a> FromSql<'a> for Numeric {
+    fn from_sql(
+        _: &Type,
+        raw: &'a [u8],
+    ) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        let read =
+            |i: usize| -> std::result::Result<i16, Box<dyn std::error::Error + Sync + Send>> {
+                let bytes = raw.get(i * 2..i * 2 + 2).ok_or("invalid numeric")?;
+                Ok(i16::from_be_bytes([bytes[0], bytes[1]]))
+            };
+        let (ndigits, weight, sign, dscale) = (read(0)?, read(1)?, read(2)? as u16, read(3)?);
+        if sign == 0xC000 {
+            return Ok(Numeric("NaN".to_string()));
+        }
+        let digits = (0..ndigits as usize)
+            .map(|i| read(4 + i))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        // Each digit is a base-10000 group. `weight` is the position of the first group relative
+        // to the decimal point.
+        let group = |pos: i32| -> i16 {
+            usize::try_from(pos)
+                .ok()
+                .and_then(|pos| digits.get(pos))
+                .copied()
+                .unwrap_or(0)
+        };
+
+        let mut s = String::new();
+        if sign == 0x4000 {
+            s.push('-');
+        }
+        if weight < 0 {
+            s.push('0');
+        }
+        for pos in 0..=weight as i32 {
+            match pos {
+                0 => s += &group(pos).to_string(),
+                _ => s += &format!("{:04}", group(pos)),
+            }
+        }
+        if dscale > 0 {
+            let mut frac = String::new();
+            for pos in weight as i32 + 1..=weight as i32 + (dscale as i32 + 3) / 4 {
+                frac += &format!("{:04}", group(pos));
+            }
+            frac.truncate(dscale as usize);
+            s.push('.');
+            s += &frac;
+        }
+        Ok(Numeric(s))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::NUMERIC
+    }
+}
+
+/// Formats the values whose handling differs on Redshift, or returns `None` to fall back to the
+/// postgres handling.
+///
+/// Redshift has no arrays, intervals or `varchar` casts of bound parameters, so `TIMESTAMPTZ` is
+/// formatted locally in the same way as Redshift, i.e. `2023-01-01 00:00:00+00`, and types
+/// unknown to postgres, e.g. `SUPER` and `GEOMETRY`, whose binary format is not text, are errors.
+/// Cast them to `varchar` in the query instead. `NUMERIC`s are decoded without loss of precision.
+fn redshift_value_to_str(
+    row: &tokio_postgres::Row,
+    idx: usize,
+    normalize_numeric: bool,
+) -> std::result::Result<Option<String>, tokio_postgres::Error> {
+    let value = match row.columns()[idx].type_().clone() {
+        Type::INT2 | Type::INT4 | Type::INT8 | Type::FLOAT4 | Type::FLOAT8 => return Ok(None),
+        Type::BOOL | Type::DATE | Type::TIME | Type::TIMESTAMP | Type::VARCHAR | Type::TEXT => {
+            return Ok(None)
+        }
+        Type::NUMERIC => row
+            .get::<_, Option<Numeric>>(idx)
+            .map(|v| numeric_to_str(&v.0, normalize_numeric)),
+        Type::BPCHAR | Type::NAME => row
+            .get::<_, Option<String>>(idx)
+            .map(|v| varchar_to_str(&v)),
+        Type::TIMESTAMPTZ => row
+            .get::<_, Option<DateTime<chrono::Utc>>>(idx)
+            .map(|v| v.format("%Y-%m-%d %H:%M:%S%.f+00").to_string()),
+        _ => match row.try_get::<_, Unsupported>(idx)? {},
+    };
+    Ok(Some(value.unwrap_or_else(|| "NULL".to_string())))
+}
+
+impl PostgresExtended {
+    /// Runs the SQL. See [`AsyncDB::run`](sqllogictest::AsyncDB::run).
+    async fn run_sql(&mut self, sql: &str) -> Result<DBOutput, tokio_postgres::Error> {
         use std::fmt::Write;
 
-        let mut output = String::new();
+        if self.protocol == Protocol::Simple {
+            // The values are formatted by the server like in the `postgres` engine. The messages
+            // are read as they arrive, to stop at `max_rows` like below.
+            let mut stream = Box::pin(self.client.simple_query_raw(sql).await?);
+            let mut messages = vec![];
+            let mut num_rows = 0;
+            let mut truncated = false;
+            while let Some(message) = stream.try_next().await? {
+                if let SimpleQueryMessage::Row(_) = message {
+                    if Some(num_rows) == self.max_rows {
+                        // The rest of the messages are discarded by the connection.
+                        truncated = true;
+                        break;
+                    }
+                    num_rows += 1;
+                }
+                messages.push(message);
+            }
+            let mut output = format_simple_query(messages, &self.null_string);
+            if let (true, DBOutput::Rows { rows, .. }) = (truncated, &mut output) {
+                rows.push(vec!["...".to_string()]);
+            }
+            return Ok(output);
+        }
 
         let is_query_sql = {
             let lower_sql = sql.to_ascii_lowercase();
@@ -228,12 +1232,46 @@ impl sqllogictest::AsyncDB for PostgresExtended {
                 || lower_sql.starts_with("describe")
         };
         if is_query_sql {
-            let rows = self.client.query(sql, &[]).await?;
-            for row in rows {
+            let (statement, rows) = self.query(sql, &[], true).await?;
+            let types = statement
+                .columns()
+                .iter()
+                .map(|column| column_type(column.type_()))
+                .collect();
+            let names = statement
+                .columns()
+                .iter()
+                .map(|column| column.name().to_string())
+                .collect();
+            let mut rows = Box::pin(rows);
+            let mut casts = ServerCasts::default();
+            // The rows waiting for `casts`. The rows after them are buffered too, to keep the
+            // order.
+            let mut cells = vec![];
+            let mut output = vec![];
+            let mut num_rows = 0;
+            while let Some(row) = rows.try_next().await? {
+                if Some(num_rows) == self.max_rows {
+                    // The rest of the rows are discarded by the connection.
+                    cells.push(vec![Cell::Text("...".to_string())]);
+                    break;
+                }
+                num_rows += 1;
+                let mut row_cells = Vec::with_capacity(row.len());
                 for (idx, column) in row.columns().iter().enumerate() {
-                    if idx != 0 {
-                        write!(output, " ").unwrap();
+                    if row.get::<_, Option<RawValue>>(idx).is_none() {
+                        row_cells.push(Cell::Text(self.null_string.clone()));
+                        continue;
+                    }
+                    if self.redshift {
+                        if let Some(value) =
+                            redshift_value_to_str(&row, idx, self.normalize_numeric)?
+                        {
+                            row_cells.push(Cell::Text(value));
+                            continue;
+                        }
                     }
+                    let mut output = String::new();
                     match column.type_().clone() {
                         Type::INT2 => {
                             single_process!(row, output, idx, i16);
@@ -245,7 +1283,10 @@ impl sqllogictest::AsyncDB for PostgresExtended {
                             single_process!(row, output, idx, i64);
                         }
                         Type::NUMERIC => {
-                            single_process!(row, output, idx, Decimal);
+                            let numeric_to_str = |v: &Decimal| {
+                                numeric_to_str(&v.to_string(), self.normalize_numeric)
+                            };
+                            single_process!(row, output, idx, Decimal, numeric_to_str);
                         }
                         Type::DATE => {
                             single_process!(row, output, idx, NaiveDate);
@@ -259,88 +1300,328 @@ impl sqllogictest::AsyncDB for PostgresExtended {
                         Type::BOOL => {
                             single_process!(row, output, idx, bool, bool_to_str);
                         }
+                        Type::UUID => {
+                            single_process!(row, output, idx, Uuid);
+                        }
+                        Type::JSON | Type::JSONB => {
+                            single_process!(row, output, idx, Json);
+                        }
+                        Type::BYTEA => {
+                            let bytea_to_str = |v: &Vec<u8>| bytea_to_str(v, self.max_bytea_len);
+                            single_process!(row, output, idx, Vec<u8>, bytea_to_str);
+                        }
+                        Type::INET | Type::CIDR => {
+                            single_process!(row, output, idx, Inet);
+                        }
+                        Type::MACADDR | Type::MACADDR8 => {
+                            single_process!(row, output, idx, MacAddr);
+                        }
+                        Type::BIT | Type::VARBIT => {
+                            let bit_string_to_str = |v: &BitString| varchar_to_str(&v.0);
+                            single_process!(row, output, idx, BitString, bit_string_to_str);
+                        }
+                        Type::BPCHAR => {
+                            let bpchar_to_str =
+                                |v: &String| varchar_to_str(&bpchar_to_str(v, self.char_padding));
+                            single_process!(row, output, idx, String, bpchar_to_str);
+                        }
+                        Type::CHAR => {
+                            let char_to_str = |v: &i8| varchar_to_str(&char_to_str(v));
+                            single_process!(row, output, idx, i8, char_to_str);
+                        }
+                        Type::MONEY => {
+                            single_process!(row, output, idx, Money);
+                        }
+                        Type::POINT
+                        | Type::LINE
+                        | Type::LSEG
+                        | Type::BOX
+                        | Type::PATH
+                        | Type::POLYGON
+                        | Type::CIRCLE => {
+                            single_process!(row, output, idx, Geometry);
+                        }
+                        Type::INT4_RANGE
+                        | Type::INT8_RANGE
+                        | Type::NUM_RANGE
+                        | Type::DATE_RANGE
+                        | Type::TS_RANGE => {
+                            single_process!(row, output, idx, Range);
+                        }
+                        Type::INT4MULTI_RANGE
+                        | Type::INT8MULTI_RANGE
+                        | Type::NUMMULTI_RANGE
+                        | Type::DATEMULTI_RANGE
+                        | Type::TSMULTI_RANGE => {
+                            single_process!(row, output, idx, Range);
+                        }
+                        Type::OID => {
+                            single_process!(row, output, idx, u32);
+                        }
+                        Type::NAME => {
+                            single_process!(row, output, idx, String, varchar_to_str);
+                        }
+                        Type::OID_VECTOR => {
+                            let value: Option<Vec<u32>> = row.get(idx);
+                            match value {
+                                Some(value) => {
+                                    write!(output, "{}", value.iter().join(" ")).unwrap()
+                                }
+                                None => write!(output, "NULL").unwrap(),
+                            }
+                        }
+                        Type::INT2_VECTOR => {
+                            let value: Option<Vec<i16>> = row.get(idx);
+                            match value {
+                                Some(value) => {
+                                    write!(output, "{}", value.iter().join(" ")).unwrap()
+                                }
+                                None => write!(output, "NULL").unwrap(),
+                            }
+                        }
+                        ty if is_reg(&ty)
+                            || matches!(ty.kind(), Kind::Array(member) if is_reg(member)) =>
+                        {
+                            row_cells.push(Cell::Cast(casts.push(&ty, row.get(idx))));
+                            continue;
+                        }
                         Type::INT2_ARRAY => {
-                            array_process!(row, output, idx, i16);
+                            array_process!(self, row, output, idx, i16);
                         }
                         Type::INT4_ARRAY => {
-                            array_process!(row, output, idx, i32);
+                            array_process!(self, row, output, idx, i32);
                         }
                         Type::INT8_ARRAY => {
-                            array_process!(row, output, idx, i64);
+                            array_process!(self, row, output, idx, i64);
                         }
                         Type::BOOL_ARRAY => {
-                            array_process!(row, output, idx, bool, bool_to_str);
+                            array_process!(self, row, output, idx, bool, bool_to_str);
                         }
                         Type::FLOAT4_ARRAY => {
-                            array_process!(row, output, idx, f32, float4_to_str);
+                            let float4_to_str = |v: &f32| float4_to_str(v, self.float_precision);
+                            array_process!(self, row, output, idx, f32, float4_to_str);
                         }
                         Type::FLOAT8_ARRAY => {
-                            array_process!(row, output, idx, f64, float8_to_str);
+                            let float8_to_str = |v: &f64| float8_to_str(v, self.float_precision);
+                            array_process!(self, row, output, idx, f64, float8_to_str);
                         }
                         Type::NUMERIC_ARRAY => {
-                            array_process!(row, output, idx, Decimal);
+                            let numeric_to_str = |v: &Decimal| {
+                                numeric_to_str(&v.to_string(), self.normalize_numeric)
+                            };
+                            array_process!(self, row, output, idx, Decimal, numeric_to_str);
                         }
                         Type::DATE_ARRAY => {
-                            array_process!(row, output, idx, NaiveDate);
+                            array_process!(self, row, output, idx, NaiveDate);
                         }
                         Type::TIME_ARRAY => {
-                            array_process!(row, output, idx, NaiveTime);
+                            array_process!(self, row, output, idx, NaiveTime);
                         }
                         Type::TIMESTAMP_ARRAY => {
-                            array_process!(row, output, idx, NaiveDateTime);
+                            array_process!(self, row, output, idx, NaiveDateTime);
+                        }
+                        Type::UUID_ARRAY => {
+                            array_process!(self, row, output, idx, Uuid);
+                        }
+                        Type::JSON_ARRAY | Type::JSONB_ARRAY => {
+                            array_process!(self, row, output, idx, Json);
+                        }
+                        Type::BYTEA_ARRAY => {
+                            let bytea_to_str = |v: &Vec<u8>| bytea_to_str(v, self.max_bytea_len);
+                            array_process!(self, row, output, idx, Vec<u8>, bytea_to_str);
+                        }
+                        Type::INET_ARRAY | Type::CIDR_ARRAY => {
+                            array_process!(self, row, output, idx, Inet);
+                        }
+                        Type::MACADDR_ARRAY | Type::MACADDR8_ARRAY => {
+                            array_process!(self, row, output, idx, MacAddr);
                         }
-                        Type::VARCHAR_ARRAY | Type::TEXT_ARRAY => {
-                            array_process!(row, output, idx, String, varchar_to_str);
+                        Type::BIT_ARRAY | Type::VARBIT_ARRAY => {
+                            array_process!(self, row, output, idx, BitString);
+                        }
+                        Type::MONEY_ARRAY => {
+                            array_process!(self, row, output, idx, Money);
+                        }
+                        Type::POINT_ARRAY
+                        | Type::LINE_ARRAY
+                        | Type::LSEG_ARRAY
+                        | Type::BOX_ARRAY
+                        | Type::PATH_ARRAY
+                        | Type::POLYGON_ARRAY
+                        | Type::CIRCLE_ARRAY => {
+                            array_process!(self, row, output, idx, Geometry);
+                        }
+                        Type::INT4_RANGE_ARRAY
+                        | Type::INT8_RANGE_ARRAY
+                        | Type::NUM_RANGE_ARRAY
+                        | Type::DATE_RANGE_ARRAY
+                        | Type::TS_RANGE_ARRAY => {
+                            array_process!(self, row, output, idx, Range);
+                        }
+                        Type::INT4MULTI_RANGE_ARRAY
+                        | Type::INT8MULTI_RANGE_ARRAY
+                        | Type::NUMMULTI_RANGE_ARRAY
+                        | Type::DATEMULTI_RANGE_ARRAY
+                        | Type::TSMULTI_RANGE_ARRAY => {
+                            array_process!(self, row, output, idx, Range);
+                        }
+                        Type::OID_ARRAY => {
+                            array_process!(self, row, output, idx, u32);
+                        }
+                        Type::VARCHAR_ARRAY | Type::TEXT_ARRAY | Type::NAME_ARRAY => {
+                            array_process!(self, row, output, idx, String);
+                        }
+                        Type::BPCHAR_ARRAY => {
+                            let bpchar_to_str = |v: &String| bpchar_to_str(v, self.char_padding);
+                            array_process!(self, row, output, idx, String, bpchar_to_str);
+                        }
+                        Type::CHAR_ARRAY => {
+                            array_process!(self, row, output, idx, i8, char_to_str);
                         }
                         Type::VARCHAR | Type::TEXT => {
                             single_process!(row, output, idx, String, varchar_to_str);
                         }
                         Type::FLOAT4 => {
+                            let float4_to_str = |v: &f32| float4_to_str(v, self.float_precision);
                             single_process!(row, output, idx, f32, float4_to_str);
                         }
                         Type::FLOAT8 => {
+                            let float8_to_str = |v: &f64| float8_to_str(v, self.float_precision);
                             single_process!(row, output, idx, f64, float8_to_str);
                         }
-                        Type::INTERVAL => {
-                            single_process!(self, row, output, idx, Interval, INTERVAL);
-                        }
-                        Type::TIMESTAMPTZ => {
-                            single_process!(
-                                self,
-                                row,
-                                output,
-                                idx,
-                                DateTime<chrono::Utc>,
-                                TIMESTAMPTZ
-                            );
-                        }
-                        Type::INTERVAL_ARRAY => {
-                            array_process!(self, row, output, idx, Interval, INTERVAL);
-                        }
-                        Type::TIMESTAMPTZ_ARRAY => {
-                            array_process!(
-                                self,
-                                row,
-                                output,
-                                idx,
-                                DateTime<chrono::Utc>,
-                                TIMESTAMPTZ
-                            );
-                        }
-                        _ => {
-                            todo!("Don't support {} type now.", column.type_().name())
+                        // `TIMESTAMPTZ`s are formatted in the time zone of the session, and
+                        // `INTERVAL`s in its `IntervalStyle`.
+                        ty @ (Type::INTERVAL
+                        | Type::TIMESTAMPTZ
+                        | Type::TSTZ_RANGE
+                        | Type::TSTZMULTI_RANGE) => {
+                            row_cells.push(Cell::Cast(casts.push(&ty, row.get(idx))));
+                            continue;
+                        }
+                        Type::INTERVAL_ARRAY
+                        | Type::TIMESTAMPTZ_ARRAY
+                        | Type::TSTZ_RANGE_ARRAY
+                        | Type::TSTZMULTI_RANGE_ARRAY => {
+                            let member = match column.type_().kind() {
+                                Kind::Array(member) => member,
+                                _ => unreachable!(),
+                            };
+                            let values: Vec<Option<RawValue>> = row.get(idx);
+                            let values = values
+                                .into_iter()
+                                .map(|v| v.map(|v| casts.push(member, v)))
+                                .collect();
+                            row_cells.push(Cell::CastArray(column.type_().clone(), values));
+                            continue;
+                        }
+                        ty if is_composite(&ty) => {
+                            single_process!(row, output, idx, Composite);
+                        }
+                        ty if matches!(ty.kind(), Kind::Array(member) if is_composite(member)) => {
+                            array_process!(self, row, output, idx, Composite);
+                        }
+                        // The types not built in are looked up in `pg_type` by tokio-postgres.
+                        // The binary format of an enum is its label.
+                        ty if is_enum(&ty) => {
+                            single_process!(row, output, idx, RawText, raw_text_to_str);
+                        }
+                        ty if matches!(ty.kind(), Kind::Array(member) if is_enum(member)) => {
+                            array_process!(self, row, output, idx, RawText, raw_text_to_str);
+                        }
+                        // The columns of domains are reported with their base types, but the
+                        // arrays of domains are not, so the elements are decoded as the base type.
+                        ty if matches!(ty.kind(), Kind::Array(member) if is_domain(member)) => {
+                            let element = match ty.kind() {
+                                Kind::Array(member) => base_type(member).clone(),
+                                _ => unreachable!(),
+                            };
+                            let value: RawValue = row.get(idx);
+                            match array_elements(&element, &value.0) {
+                                // `TIMESTAMPTZ`s are decoded in UTC, not the time zone of the
+                                // session.
+                                Ok(values) if element != Type::TIMESTAMPTZ => {
+                                    write!(output, "{}", self.format_array(&ty, Some(values)))
+                                        .unwrap()
+                                }
+                                _ => {
+                                    row_cells.push(Cell::Cast(casts.push(&ty, value)));
+                                    continue;
+                                }
+                            }
+                        }
+                        _ if self.strict_types => match row.try_get::<_, Unsupported>(idx)? {},
+                        // The other types are formatted by the server.
+                        ty => {
+                            row_cells.push(Cell::Cast(casts.push(&ty, row.get(idx))));
+                            continue;
                         }
                     }
+                    row_cells.push(Cell::Text(output));
                 }
-                writeln!(output).unwrap();
+                if cells.is_empty() && row_cells.iter().all(|c| matches!(c, Cell::Text(_))) {
+                    output.push(self.format_row(row_cells, &[]));
+                } else {
+                    cells.push(row_cells);
+                }
+            }
+
+            // The results of the casts are received after the rest of the rows are discarded.
+            drop(rows);
+            let casted = casts.cast(self).await?;
+            for row_cells in cells {
+                output.push(self.format_row(row_cells, &casted));
             }
+            Ok(DBOutput::Rows {
+                names,
+                types,
+                rows: output,
+            })
         } else {
-            self.client.execute(sql, &[]).await?;
+            let (_, rows) = self.query(sql, &[], true).await?;
+            let mut rows = Box::pin(rows);
+            while rows.try_next().await?.is_some() {}
+            Ok(DBOutput::StatementComplete(rows.rows_affected()))
         }
-        Ok(output)
+    }
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for PostgresExtended {
+    type Error = tokio_postgres::error::Error;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let result = self.run_sql(sql).await;
+        self.track_transaction(sql, result.is_ok());
+        result
+    }
+
+    async fn run_batch(&mut self, sqls: &[String]) -> Vec<Result<DBOutput, Self::Error>> {
+        // Only statements are run in batches, so the simple protocol is enough for their counts.
+        let results: Vec<_> = run_pipelined(&self.client, sqls)
+            .await
+            .into_iter()
+            .map(|result| result.map(|messages| format_simple_query(messages, &self.null_string)))
+            .collect();
+        for (sql, result) in sqls.iter().zip(&results) {
+            self.track_transaction(sql, result.is_ok());
+        }
+        results
     }
 
     fn engine_name(&self) -> &str {
-        "postgres-extended"
+        if self.redshift {
+            "redshift"
+        } else {
+            "postgres-extended"
+        }
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        match capability {
+            // See `redshift_value_to_str`.
+            "arrays" => !self.redshift,
+            "decimals" | "transactions" | "mvcc" => true,
+            _ => false,
+        }
     }
 }
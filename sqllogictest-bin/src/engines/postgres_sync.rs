@@ -0,0 +1,65 @@
+use anyhow::Context;
+use sqllogictest::DBOutput;
+
+use super::postgres::{format_simple_query, pg_config};
+use crate::{DBConfig, Result, SslMode};
+
+/// Postgres engine using the blocking `postgres` crate. It implements [`sqllogictest::DB`]
+/// instead of [`sqllogictest::AsyncDB`], for harnesses which are not async, and otherwise
+/// behaves like [`Postgres`](super::postgres::Postgres).
+///
+/// The client runs its own runtime, so it must not be used or dropped in an async context
+/// without `block_in_place`.
+pub struct PostgresSync {
+    /// Taken when dropped.
+    client: Option<::postgres::Client>,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+}
+
+impl PostgresSync {
+    pub(super) fn connect(config: &DBConfig) -> Result<Self> {
+        let (pg_config, addr) = pg_config(config)?;
+        let pg_config = ::postgres::Config::from(pg_config);
+        let client = match config.ssl_mode {
+            SslMode::Disable => pg_config.connect(::postgres::NoTls),
+            #[cfg(feature = "postgres-tls")]
+            _ => pg_config.connect(super::postgres::make_tls(config)?),
+            #[cfg(not(feature = "postgres-tls"))]
+            _ => anyhow::bail!("sqllogictest-bin is built without the `postgres-tls` feature"),
+        }
+        .context(format!("failed to connect to postgres at {addr}"))?;
+
+        Ok(Self {
+            client: Some(client),
+            null_string: config.null_string.clone(),
+        })
+    }
+}
+
+impl Drop for PostgresSync {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            // Closing the client blocks on its runtime.
+            tokio::task::block_in_place(|| drop(client))
+        }
+    }
+}
+
+impl sqllogictest::DB for PostgresSync {
+    type Error = ::postgres::Error;
+
+    fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let client = self.client.as_mut().unwrap();
+        let messages = client.simple_query(sql)?;
+        Ok(format_simple_query(messages, &self.null_string))
+    }
+
+    fn engine_name(&self) -> &str {
+        "postgres-sync"
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "arrays" | "decimals" | "transactions" | "mvcc")
+    }
+}
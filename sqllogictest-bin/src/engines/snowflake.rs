@@ -0,0 +1,358 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use itertools::Itertools;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use rsa::pkcs8::{DecodePrivateKey, EncodePublicKey};
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqllogictest::DBOutput;
+use thiserror::Error;
+
+use crate::{DBConfig, Result};
+
+/// Lifetime of a JWT. Snowflake rejects tokens valid for more than one hour.
+const JWT_LIFETIME_SECS: u64 = 3600;
+
+/// The first delay between the polls of a running statement, which is doubled after each poll up
+/// to [`MAX_POLL_INTERVAL`].
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a statement may run, unless `--statement-timeout` is set.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Snowflake engine using the SQL REST API, authenticated with a key pair.
+///
+/// The host is the account URL, e.g. `myorg-myaccount.snowflakecomputing.com`, and the `db`
+/// option is interpreted as `database[.schema]`. Each record is a separate request, so session
+/// state like `USE` or `SET` does not survive across records.
+pub struct Snowflake {
+    client: reqwest::Client,
+    url: String,
+    database: String,
+    schema: Option<String>,
+    /// `ACCOUNT.USER`, the subject of the JWT.
+    subject: String,
+    /// `ACCOUNT.USER.SHA256:<fingerprint of the public key>`, the issuer of the JWT.
+    issuer: String,
+    key: EncodingKey,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+    /// How long a statement may run before it is canceled. See [`DBConfig::statement_timeout`].
+    timeout: Duration,
+}
+
+#[derive(Debug, Error)]
+pub enum SnowflakeError {
+    #[error("http request failed")]
+    Http(#[from] reqwest::Error),
+    #[error("jwt encoding failed")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+    #[error("sql failed {0}")]
+    Sql(String),
+    #[error("row has {values} values, but there are {columns} columns")]
+    ColumnCount { columns: usize, values: usize },
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    sub: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResultSet {
+    code: Option<String>,
+    message: Option<String>,
+    statement_handle: Option<String>,
+    result_set_meta_data: Option<ResultSetMetaData>,
+    data: Option<Vec<Vec<Option<String>>>>,
+    /// Returned for DML statements, which also return the counts as a row.
+    stats: Option<Stats>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Stats {
+    #[serde(default)]
+    num_rows_inserted: u64,
+    #[serde(default)]
+    num_rows_updated: u64,
+    #[serde(default)]
+    num_rows_deleted: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResultSetMetaData {
+    row_type: Vec<RowType>,
+    #[serde(default)]
+    partition_info: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct RowType {
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+impl Snowflake {
+    pub(super) async fn connect(config: &DBConfig, private_key_path: &str) -> Result<Self> {
+        let (host, port) = config.addr();
+        let (database, schema) = match config.db.split_once('.') {
+            Some((database, schema)) => (database.to_string(), Some(schema.to_string())),
+            None => (config.db.clone(), None),
+        };
+
+        let pem = std::fs::read_to_string(private_key_path)
+            .context(format!("failed to read private key {private_key_path}"))?;
+        let private_key =
+            RsaPrivateKey::from_pkcs8_pem(&pem).context("failed to parse private key")?;
+        let public_key = RsaPublicKey::from(&private_key)
+            .to_public_key_der()
+            .context("failed to encode public key")?;
+        let fingerprint = base64::encode(Sha256::digest(public_key.as_bytes()));
+
+        // The account identifier is the first label of the host, without the region.
+        let account = host.split('.').next().unwrap_or(host).to_ascii_uppercase();
+        let subject = format!("{}.{}", account, config.user.to_ascii_uppercase());
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: format!("https://{host}:{port}/api/v2/statements"),
+            database,
+            schema,
+            issuer: format!("{subject}.SHA256:{fingerprint}"),
+            subject,
+            key: EncodingKey::from_rsa_pem(pem.as_bytes())?,
+            null_string: config.null_string.clone(),
+            timeout: config.statement_timeout.unwrap_or(DEFAULT_TIMEOUT),
+        })
+    }
+
+    fn jwt(&self) -> Result<String, SnowflakeError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let claims = Claims {
+            iss: &self.issuer,
+            sub: &self.subject,
+            iat: now,
+            exp: now + JWT_LIFETIME_SECS,
+        };
+        Ok(jsonwebtoken::encode(
+            &Header::new(Algorithm::RS256),
+            &claims,
+            &self.key,
+        )?)
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<ResultSet, SnowflakeError> {
+        let resp = request
+            .bearer_auth(self.jwt()?)
+            .header("X-Snowflake-Authorization-Token-Type", "KEYPAIR_JWT")
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+        let status = resp.status();
+        let result: ResultSet = resp.json().await?;
+        if !status.is_success() {
+            return Err(SnowflakeError::Sql(format!(
+                "{}: {}",
+                result.code.unwrap_or_default(),
+                result.message.unwrap_or_default()
+            )));
+        }
+        Ok(result)
+    }
+}
+
+/// Splits seconds with a fractional part, e.g. `1.500000000` -> `(1, 500000000)`. The
+/// nanoseconds are counted forward, e.g. `-1.500000000` -> `(-2, 500000000)`.
+fn split_secs(v: &str) -> Option<(i64, u32)> {
+    let (secs, frac) = v.split_once('.').unwrap_or((v, ""));
+    let nanos: u32 = format!("{:0<9}", frac).parse().ok()?;
+    let whole: i64 = secs.parse().ok()?;
+    if secs.starts_with('-') && nanos > 0 {
+        return Some((whole - 1, 1_000_000_000 - nanos));
+    }
+    Some((whole, nanos))
+}
+
+fn timestamp_to_datetime(secs: i64, nanos: u32) -> Option<NaiveDateTime> {
+    NaiveDate::from_ymd_opt(1970, 1, 1)?
+        .and_hms_opt(0, 0, 0)?
+        .checked_add_signed(chrono::Duration::seconds(secs))?
+        .checked_add_signed(chrono::Duration::nanoseconds(nanos as i64))
+}
+
+/// Formats a value according to its column type. Snowflake encodes every value as a string, and
+/// dates and times as offsets from the epoch.
+fn value_to_str(ty: &str, value: &str) -> String {
+    let formatted = match ty {
+        "boolean" => match value {
+            "true" => Some("t".to_string()),
+            "false" => Some("f".to_string()),
+            _ => None,
+        },
+        "date" => value.parse().ok().and_then(|days| {
+            NaiveDate::from_ymd_opt(1970, 1, 1)
+                .and_then(|epoch| epoch.checked_add_signed(chrono::Duration::days(days)))
+                .map(|d| d.to_string())
+        }),
+        "time" => split_secs(value).and_then(|(secs, nanos)| {
+            NaiveTime::from_num_seconds_from_midnight_opt(secs as u32, nanos).map(|t| t.to_string())
+        }),
+        "timestamp_ntz" | "timestamp_ltz" => split_secs(value)
+            .and_then(|(secs, nanos)| timestamp_to_datetime(secs, nanos).map(|t| t.to_string())),
+        // `<seconds> <offset>`, where the offset is in minutes, plus 1440.
+        "timestamp_tz" => value.split_once(' ').and_then(|(ts, offset)| {
+            let (secs, nanos) = split_secs(ts)?;
+            let offset = offset.parse::<i32>().ok()? - 1440;
+            let utc = timestamp_to_datetime(secs, nanos)?;
+            let tz = FixedOffset::east_opt(offset * 60)?;
+            Some(tz.from_utc_datetime(&utc).to_string())
+        }),
+        _ => None,
+    };
+    match formatted {
+        Some(v) => v,
+        None if value.is_empty() => "(empty)".to_string(),
+        // Numbers, texts, and semi-structured values (JSON) are printed as is.
+        None => value.to_string(),
+    }
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for Snowflake {
+    type Error = SnowflakeError;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let body = json!({
+            "statement": sql,
+            "database": self.database,
+            "schema": self.schema,
+            // Canceled by the server as well, in case the client stops polling.
+            "timeout": self.timeout.as_secs().max(1),
+        });
+        let start = Instant::now();
+        let mut result = self.send(self.client.post(&self.url).json(&body)).await?;
+        // The statement is still running until the result set is returned.
+        let mut interval = MIN_POLL_INTERVAL;
+        while result.result_set_meta_data.is_none() {
+            let handle = match &result.statement_handle {
+                Some(handle) => handle.clone(),
+                None => return Err(SnowflakeError::Sql("missing statement handle".to_string())),
+            };
+            if start.elapsed() >= self.timeout {
+                // The response of the cancellation is not a result set, so it is not checked.
+                let url = format!("{}/{}/cancel", self.url, handle);
+                let _ = self.send(self.client.post(url)).await;
+                return Err(SnowflakeError::Sql(format!(
+                    "statement {handle} timed out after {}",
+                    humantime::format_duration(self.timeout)
+                )));
+            }
+            tokio::time::sleep(interval.min(self.timeout.saturating_sub(start.elapsed()))).await;
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+            let url = format!("{}/{}", self.url, handle);
+            result = self.send(self.client.get(url)).await?;
+        }
+
+        if let Some(stats) = result.stats {
+            return Ok(DBOutput::StatementComplete(Some(
+                stats.num_rows_inserted + stats.num_rows_updated + stats.num_rows_deleted,
+            )));
+        }
+        let meta = match result.result_set_meta_data {
+            Some(meta) => meta,
+            None => return Err(SnowflakeError::Sql("missing result set metadata".to_string())),
+        };
+        let types = meta.row_type.into_iter().map(|t| t.ty).collect_vec();
+        let mut partitions = vec![result.data.unwrap_or_default()];
+        // Large result sets are split into partitions, only the first of which is returned.
+        for partition in 1..meta.partition_info.len() {
+            let url = format!(
+                "{}/{}",
+                self.url,
+                result.statement_handle.as_deref().unwrap_or_default()
+            );
+            let request = self.client.get(url).query(&[("partition", partition)]);
+            partitions.push(self.send(request).await?.data.unwrap_or_default());
+        }
+
+        let mut rows = vec![];
+        for row in partitions.into_iter().flatten() {
+            if row.len() != types.len() {
+                return Err(SnowflakeError::ColumnCount {
+                    columns: types.len(),
+                    values: row.len(),
+                });
+            }
+            let values = types
+                .iter()
+                .zip(&row)
+                .map(|(ty, value)| match value {
+                    Some(v) => value_to_str(ty, v),
+                    None => self.null_string.clone(),
+                })
+                .collect();
+            rows.push(values);
+        }
+        Ok(DBOutput::Rows {
+            names: vec![],
+            types: vec![],
+            rows,
+        })
+    }
+
+    fn engine_name(&self) -> &str {
+        "snowflake"
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "arrays" | "decimals" | "transactions")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_secs() {
+        assert_eq!(split_secs("1.500000000"), Some((1, 500_000_000)));
+        assert_eq!(split_secs("42"), Some((42, 0)));
+        assert_eq!(split_secs("-1.500000000"), Some((-2, 500_000_000)));
+        assert_eq!(split_secs("-0.250000000"), Some((-1, 750_000_000)));
+        assert_eq!(split_secs("x"), None);
+    }
+
+    #[test]
+    fn test_value_to_str() {
+        assert_eq!(value_to_str("boolean", "true"), "t");
+        assert_eq!(value_to_str("date", "19358"), "2023-01-01");
+        assert_eq!(value_to_str("time", "3723.500000000"), "01:02:03.500");
+        assert_eq!(
+            value_to_str("timestamp_ntz", "-0.500000000"),
+            "1969-12-31 23:59:59.500"
+        );
+        assert_eq!(
+            value_to_str("timestamp_tz", "1672531200.000000000 1500"),
+            "2023-01-01 01:00:00 +01:00"
+        );
+        assert_eq!(value_to_str("fixed", "1.50"), "1.50");
+        assert_eq!(value_to_str("text", ""), "(empty)");
+        // Values which can not be parsed are printed as is.
+        assert_eq!(value_to_str("date", "x"), "x");
+    }
+}
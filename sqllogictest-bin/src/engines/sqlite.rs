@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use sqllogictest::DBOutput;
+
+use super::Watchdog;
+use crate::{DBConfig, Result};
+
+/// In-process SQLite engine. The `db` option is used as the path of the database file, and
+/// `:memory:` opens a fresh in-memory database.
+pub struct Sqlite {
+    conn: Connection,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+    /// See [`DBConfig::statement_timeout`]. SQL running longer is interrupted.
+    timeout: Option<Duration>,
+}
+
+impl Sqlite {
+    pub(super) fn connect(config: &DBConfig) -> Result<Self> {
+        let conn = if config.db == ":memory:" {
+            Connection::open_in_memory()
+        } else {
+            Connection::open(&config.db)
+        }
+        .context(format!("failed to open sqlite database {}", config.db))?;
+
+        Ok(Self {
+            conn,
+            null_string: config.null_string.clone(),
+            timeout: config.statement_timeout,
+        })
+    }
+}
+
+fn value_to_str(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(v) => v.to_string(),
+        ValueRef::Real(v) => v.to_string(),
+        ValueRef::Text([]) => "(empty)".to_string(),
+        ValueRef::Text(v) => String::from_utf8_lossy(v).into_owned(),
+        ValueRef::Blob(v) => v.iter().map(|b| format!("{:02X}", b)).collect(),
+    }
+}
+
+/// SQLite is synchronous, so we implement the blocking [`sqllogictest::DB`] trait. The caller is
+/// responsible for not blocking the async runtime.
+impl sqllogictest::DB for Sqlite {
+    type Error = rusqlite::Error;
+
+    fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let interrupt = self.conn.get_interrupt_handle();
+        let _watchdog = self
+            .timeout
+            .map(|timeout| Watchdog::start(timeout, move || interrupt.interrupt()));
+        let mut stmt = self.conn.prepare(sql)?;
+        let column_count = stmt.column_count();
+        if column_count == 0 {
+            return Ok(DBOutput::StatementComplete(Some(stmt.execute([])? as u64)));
+        }
+        let names = stmt.column_names().into_iter().map(String::from).collect();
+        let mut rows = vec![];
+        let mut query = stmt.query([])?;
+        while let Some(row) = query.next()? {
+            let mut values = Vec::with_capacity(column_count);
+            for idx in 0..column_count {
+                values.push(match row.get_ref(idx)? {
+                    ValueRef::Null => self.null_string.clone(),
+                    value => value_to_str(value),
+                });
+            }
+            rows.push(values);
+        }
+        // The types of values are dynamic in SQLite, so those of the columns are not known.
+        Ok(DBOutput::Rows {
+            names,
+            types: vec![],
+            rows,
+        })
+    }
+
+    fn engine_name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "transactions")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqllogictest::DB;
+
+    use super::*;
+
+    #[test]
+    fn test_value_to_str() {
+        assert_eq!(value_to_str(ValueRef::Integer(-1)), "-1");
+        assert_eq!(value_to_str(ValueRef::Real(0.5)), "0.5");
+        assert_eq!(value_to_str(ValueRef::Text(b"")), "(empty)");
+        assert_eq!(value_to_str(ValueRef::Text(b"a b")), "a b");
+        assert_eq!(value_to_str(ValueRef::Blob(&[0x0a, 0xff])), "0AFF");
+    }
+
+    #[test]
+    fn test_run() {
+        let mut db = Sqlite {
+            conn: Connection::open_in_memory().unwrap(),
+            null_string: "null".into(),
+            timeout: None,
+        };
+        assert_eq!(
+            db.run("create table t (a int, b text)").unwrap(),
+            DBOutput::StatementComplete(Some(0))
+        );
+        assert_eq!(
+            db.run("insert into t values (1, ''), (null, 'x')").unwrap(),
+            DBOutput::StatementComplete(Some(2))
+        );
+        assert_eq!(
+            db.run("select * from t order by a").unwrap(),
+            DBOutput::Rows {
+                names: vec!["a".into(), "b".into()],
+                types: vec![],
+                rows: vec![
+                    vec!["null".into(), "x".into()],
+                    vec!["1".into(), "(empty)".into()],
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_run_timeout() {
+        let mut db = Sqlite {
+            conn: Connection::open_in_memory().unwrap(),
+            null_string: "NULL".into(),
+            timeout: Some(Duration::from_millis(50)),
+        };
+        let sql = "with recursive c(x) as (select 1 union all select x + 1 from c) \
+                   select count(*) from c";
+        assert!(matches!(
+            db.run(sql),
+            Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::OperationInterrupted
+        ));
+        // The connection is still usable.
+        assert_eq!(
+            db.run("select 1").unwrap(),
+            DBOutput::Rows {
+                names: vec!["1".into()],
+                types: vec![],
+                rows: vec![vec!["1".into()]],
+            }
+        );
+    }
+}
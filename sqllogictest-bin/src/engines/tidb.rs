@@ -0,0 +1,178 @@
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, NaiveTime};
+use mysql_async::consts::ColumnType;
+use mysql_async::prelude::Queryable;
+use mysql_async::{Column, Value};
+use rust_decimal::Decimal;
+use sqllogictest::DBOutput;
+
+use super::mysql::{self, Mysql};
+use super::postgres::transaction_change;
+use crate::{DBConfig, Result};
+
+/// Session variables set on connection.
+const SESSION_SETUP: &[&str] = &[
+    // Allow records with multiple statements, which TiDB rejects by default.
+    "SET @@tidb_multi_statement_mode = 'ON'",
+];
+
+/// Error codes of transaction conflicts, which are expected to be retried by the client.
+const RETRYABLE_ERRORS: &[u16] = &[
+    8002, // ErrForUpdateCantRetry
+    8022, // ErrTxnRetryable
+    8028, // ErrInfoSchemaChanged
+    9007, // ErrWriteConflict
+];
+
+/// TiDB engine, which is the mysql engine with TiDB-specific session settings and retries.
+///
+/// Records failing with a transaction conflict are run again, up to `max_retries` times. Inside
+/// an explicit transaction, TiDB has already rolled the transaction back, and running the record
+/// again would run it outside of the transaction, so the error is returned.
+///
+/// Decimals and times are reformatted in the same way as `postgres-extended`, e.g. `DATETIME(6)`
+/// values are printed without trailing zeros in the fractional seconds.
+pub struct Tidb {
+    inner: Mysql,
+    max_retries: usize,
+    /// See [`DBConfig::normalize_numeric`].
+    normalize_numeric: bool,
+    /// Whether an explicit transaction is open, in which the records are not retried.
+    in_transaction: bool,
+}
+
+impl Tidb {
+    pub(super) async fn connect(config: &DBConfig, max_retries: usize) -> Result<Self> {
+        let mut inner = Mysql::connect(config).await?;
+        for sql in SESSION_SETUP {
+            inner.conn().query_drop(*sql).await?;
+        }
+        Ok(Self {
+            inner,
+            max_retries,
+            normalize_numeric: config.normalize_numeric,
+            in_transaction: false,
+        })
+    }
+}
+
+/// Returns whether the error is a transaction conflict. See [`RETRYABLE_ERRORS`].
+fn is_conflict(error: &mysql_async::Error) -> bool {
+    matches!(error, mysql_async::Error::Server(e) if RETRYABLE_ERRORS.contains(&e.code))
+}
+
+fn value_to_str(value: &Value, column: &Column, normalize_numeric: bool) -> String {
+    let text = match value {
+        Value::Bytes(bytes) if !bytes.is_empty() => String::from_utf8_lossy(bytes),
+        _ => return mysql::value_to_str(value, column),
+    };
+    // Values which can not be parsed, e.g. negative `TIME`s, are printed as is.
+    let formatted = match column.column_type() {
+        ColumnType::MYSQL_TYPE_NEWDECIMAL | ColumnType::MYSQL_TYPE_DECIMAL => {
+            text.parse::<Decimal>().ok().map(|v| {
+                if normalize_numeric {
+                    v.normalize().to_string()
+                } else {
+                    v.to_string()
+                }
+            })
+        }
+        ColumnType::MYSQL_TYPE_DATETIME | ColumnType::MYSQL_TYPE_TIMESTAMP => {
+            NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S%.f")
+                .ok()
+                .map(|v| v.to_string())
+        }
+        ColumnType::MYSQL_TYPE_TIME => NaiveTime::parse_from_str(&text, "%H:%M:%S%.f")
+            .ok()
+            .map(|v| v.to_string()),
+        _ => None,
+    };
+    formatted.unwrap_or_else(|| mysql::value_to_str(value, column))
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for Tidb {
+    type Error = mysql_async::Error;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let normalize_numeric = self.normalize_numeric;
+        let value_to_str =
+            |value: &Value, column: &Column| value_to_str(value, column, normalize_numeric);
+        let mut retries = 0;
+        let result = loop {
+            match self.inner.run_with(sql, value_to_str).await {
+                Err(e) if is_conflict(&e) && !self.in_transaction && retries < self.max_retries => {
+                    retries += 1;
+                    log::warn!("retrying ({retries}/{}): {}", self.max_retries, e);
+                }
+                Err(e) if is_conflict(&e) && self.in_transaction => {
+                    log::warn!("not retrying inside an explicit transaction: {e}");
+                    break Err(e);
+                }
+                result => break result,
+            }
+        };
+        match transaction_change(sql) {
+            Some(true) if result.is_ok() => self.in_transaction = true,
+            Some(false) => self.in_transaction = false,
+            _ => {}
+        }
+        result
+    }
+
+    fn engine_name(&self) -> &str {
+        "tidb"
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "decimals" | "transactions" | "mvcc")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(column_type: ColumnType, text: &str, normalize_numeric: bool) -> String {
+        let value = Value::Bytes(text.as_bytes().to_vec());
+        value_to_str(&value, &Column::new(column_type), normalize_numeric)
+    }
+
+    #[test]
+    fn test_value_to_str() {
+        let decimal = ColumnType::MYSQL_TYPE_NEWDECIMAL;
+        assert_eq!(format(decimal, "1.50", false), "1.50");
+        assert_eq!(format(decimal, "1.50", true), "1.5");
+        assert_eq!(
+            format(
+                ColumnType::MYSQL_TYPE_DATETIME,
+                "2023-01-02 03:04:05.100000",
+                false
+            ),
+            "2023-01-02 03:04:05.100"
+        );
+        let time = ColumnType::MYSQL_TYPE_TIME;
+        assert_eq!(format(time, "03:04:05.000000", false), "03:04:05");
+        // Negative times can not be parsed, and are printed as is.
+        assert_eq!(format(time, "-03:04:05", false), "-03:04:05");
+        assert_eq!(
+            format(ColumnType::MYSQL_TYPE_VAR_STRING, "", false),
+            "(empty)"
+        );
+    }
+
+    #[test]
+    fn test_is_conflict() {
+        let error = |code| {
+            mysql_async::Error::Server(mysql_async::ServerError {
+                code,
+                message: String::new(),
+                state: "HY000".into(),
+            })
+        };
+        assert!(is_conflict(&error(9007)));
+        assert!(is_conflict(&error(8022)));
+        // ER_DUP_ENTRY
+        assert!(!is_conflict(&error(1062)));
+    }
+}
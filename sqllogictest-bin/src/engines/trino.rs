@@ -0,0 +1,257 @@
+use async_trait::async_trait;
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_json::Value;
+use sqllogictest::DBOutput;
+use thiserror::Error;
+
+use crate::{DBConfig, Result};
+
+/// Trino (and Presto) engine using the REST client protocol.
+///
+/// The `db` option is interpreted as `catalog[.schema]`.
+pub struct Trino {
+    client: reqwest::Client,
+    url: String,
+    user: String,
+    catalog: String,
+    schema: Option<String>,
+    /// See [`DBConfig::null_string`].
+    null_string: String,
+}
+
+#[derive(Debug, Error)]
+pub enum TrinoError {
+    #[error("http request failed")]
+    Http(#[from] reqwest::Error),
+    #[error("sql failed {0}")]
+    Sql(String),
+    #[error("row has {values} values, but there are {columns} columns")]
+    ColumnCount { columns: usize, values: usize },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryResults {
+    next_uri: Option<String>,
+    columns: Option<Vec<Column>>,
+    data: Option<Vec<Vec<Value>>>,
+    error: Option<QueryError>,
+    /// The number of rows affected by a statement, e.g. an `INSERT`.
+    update_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Column {
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryError {
+    message: String,
+    error_name: Option<String>,
+}
+
+impl Trino {
+    pub(super) async fn connect(config: &DBConfig) -> Result<Self> {
+        let (host, port) = config.addr();
+        let (catalog, schema) = match config.db.split_once('.') {
+            Some((catalog, schema)) => (catalog.to_string(), Some(schema.to_string())),
+            None => (config.db.clone(), None),
+        };
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: format!("http://{host}:{port}/v1/statement"),
+            user: config.user.clone(),
+            catalog,
+            schema,
+            null_string: config.null_string.clone(),
+        })
+    }
+
+    /// Follow the session changes requested by the server, e.g. after a `USE` statement.
+    fn update_session(&mut self, headers: &reqwest::header::HeaderMap) {
+        let get = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        if let Some(catalog) = get("X-Trino-Set-Catalog") {
+            self.catalog = catalog.to_string();
+        }
+        if let Some(schema) = get("X-Trino-Set-Schema") {
+            self.schema = Some(schema.to_string());
+        }
+    }
+}
+
+/// Strips the parameters of a type, e.g. `decimal(10, 2)` -> `decimal`.
+fn base_type(ty: &str) -> &str {
+    ty.split_once('(').map(|(base, _)| base).unwrap_or(ty)
+}
+
+fn value_to_str(ty: &str, value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(true) => "t".to_string(),
+        Value::Bool(false) => "f".to_string(),
+        Value::Array(values) if base_type(ty) == "array" => {
+            let elem_ty = &ty["array(".len()..ty.len() - 1];
+            format!(
+                "{{{}}}",
+                values.iter().map(|v| value_to_str(elem_ty, v)).join(",")
+            )
+        }
+        // Rows are encoded as JSON arrays as well.
+        Value::Array(values) => {
+            format!("({})", values.iter().map(|v| value_to_str("", v)).join(","))
+        }
+        Value::String(v) if v.is_empty() => "(empty)".to_string(),
+        // Decimals, dates and timestamps are encoded as strings, and already formatted by the
+        // server. Special floating point values (`NaN`, `Infinity`) are encoded as strings too.
+        Value::String(v) => v.clone(),
+        Value::Number(v) => v.to_string(),
+        Value::Object(_) => value.to_string(),
+    }
+}
+
+#[async_trait]
+impl sqllogictest::AsyncDB for Trino {
+    type Error = TrinoError;
+
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("X-Trino-User", &self.user)
+            .header("X-Trino-Catalog", &self.catalog);
+        if let Some(schema) = &self.schema {
+            request = request.header("X-Trino-Schema", schema);
+        }
+        let mut resp = request
+            .body(sql.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let mut output = vec![];
+        let mut types = None;
+        let mut update_count = None;
+        loop {
+            self.update_session(resp.headers());
+            let results: QueryResults = resp.json().await?;
+            if let Some(error) = results.error {
+                return Err(TrinoError::Sql(match error.error_name {
+                    Some(name) => format!("{}: {}", name, error.message),
+                    None => error.message,
+                }));
+            }
+            if let Some(columns) = results.columns {
+                types = Some(columns.into_iter().map(|c| c.ty).collect_vec());
+            }
+            update_count = update_count.or(results.update_count);
+            let columns = types.as_deref().unwrap_or_default();
+            for row in results.data.unwrap_or_default() {
+                if row.len() != columns.len() {
+                    return Err(TrinoError::ColumnCount {
+                        columns: columns.len(),
+                        values: row.len(),
+                    });
+                }
+                let values = columns
+                    .iter()
+                    .zip(&row)
+                    .map(|(ty, value)| match value {
+                        Value::Null => self.null_string.clone(),
+                        value => value_to_str(ty, value),
+                    })
+                    .collect();
+                output.push(values);
+            }
+            // The query is finished once there is no `nextUri`.
+            match results.next_uri {
+                Some(next_uri) => {
+                    resp = self
+                        .client
+                        .get(next_uri)
+                        .header("X-Trino-User", &self.user)
+                        .send()
+                        .await?
+                        .error_for_status()?;
+                }
+                None => break,
+            }
+        }
+        // Statements also return the number of rows they affect as a row.
+        match (update_count, types) {
+            (Some(count), _) => Ok(DBOutput::StatementComplete(Some(count))),
+            (None, Some(_)) => Ok(DBOutput::Rows {
+                names: vec![],
+                types: vec![],
+                rows: output,
+            }),
+            (None, None) => Ok(DBOutput::StatementComplete(Some(0))),
+        }
+    }
+
+    fn engine_name(&self) -> &str {
+        "trino"
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        matches!(capability, "arrays" | "decimals")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_base_type() {
+        assert_eq!(base_type("decimal(10, 2)"), "decimal");
+        assert_eq!(base_type("array(row(a integer))"), "array");
+        assert_eq!(base_type("varchar"), "varchar");
+    }
+
+    #[test]
+    fn test_value_to_str() {
+        assert_eq!(value_to_str("boolean", &json!(false)), "f");
+        assert_eq!(value_to_str("varchar", &json!("")), "(empty)");
+        assert_eq!(value_to_str("decimal(3, 2)", &json!("1.50")), "1.50");
+        assert_eq!(value_to_str("double", &json!("NaN")), "NaN");
+        assert_eq!(
+            value_to_str("array(array(integer))", &json!([[1, 2], [null]])),
+            "{{1,2},{NULL}}"
+        );
+        assert_eq!(
+            value_to_str("row(a integer, b varchar)", &json!([1, "x"])),
+            "(1,x)"
+        );
+    }
+
+    #[test]
+    fn test_query_results() {
+        let results: QueryResults = serde_json::from_value(json!({
+            "id": "20230101_000000_00000_abcde",
+            "nextUri": "http://localhost:8080/v1/statement/executing/1",
+            "columns": [{"name": "a", "type": "integer"}],
+            "data": [[1]],
+            "updateCount": null
+        }))
+        .unwrap();
+        assert!(results.next_uri.is_some());
+        assert_eq!(results.columns.unwrap()[0].ty, "integer");
+        assert_eq!(results.data.unwrap(), vec![vec![json!(1)]]);
+        assert!(results.error.is_none() && results.update_count.is_none());
+
+        let results: QueryResults = serde_json::from_value(json!({
+            "error": {"message": "line 1:1: mismatched input", "errorName": "SYNTAX_ERROR"}
+        }))
+        .unwrap();
+        let error = results.error.unwrap();
+        assert_eq!(error.error_name.as_deref(), Some("SYNTAX_ERROR"));
+        assert_eq!(error.message, "line 1:1: mismatched input");
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqllogictest::DBOutput;
+use thiserror::Error;
+use wasmtime::{Config, Engine, Linker, Memory, MemoryAccessError, Module, Store, TypedFunc};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::WasiCtxBuilder;
+
+use super::{parse_text_output, Watchdog};
+use crate::{DBConfig, Result};
+
+/// Engine compiled to WebAssembly, run in a WASI sandbox. This is a safer alternative to
+/// [`Plugin`](super::plugin::Plugin), as the module has no access to the filesystem or network,
+/// except for stdout and stderr. It is therefore only for embedded databases, e.g. SQLite
+/// compiled to WASI, which keep their data in the memory of the module.
+///
+/// The module must export its `memory` and the following functions, where strings are UTF-8
+/// passed as pointer and length. Reactor modules are initialized by calling `_initialize`.
+///
+/// - `slt_alloc(len: i32) -> i32`: allocates a buffer, into which the host writes the input.
+/// - `slt_free(ptr: i32, len: i32)`: frees a buffer allocated by `slt_alloc` or returned by
+///   `slt_run`.
+/// - `slt_connect(ptr: i32, len: i32) -> i32` (optional): called once with the connection
+///   config, a JSON object with the keys `db`, `user` and `pass`. Returns non-zero on failure.
+/// - `slt_run(ptr: i32, len: i32) -> i64`: runs the SQL, and returns a buffer packed as
+///   `ptr << 32 | len`. The first byte of the buffer is 0 on success, followed by the formatted
+///   rows: one row per line, with values separated by spaces. Otherwise, it is followed by the
+///   error message.
+pub struct Wasm {
+    store: Store<WasiP1Ctx>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    free: TypedFunc<(i32, i32), ()>,
+    run: TypedFunc<(i32, i32), i64>,
+    /// See [`DBConfig::statement_timeout`]. SQL running longer is interrupted by the epoch
+    /// deadline of the store, which traps.
+    timeout: Option<Duration>,
+}
+
+#[derive(Debug, Error)]
+pub enum WasmError {
+    #[error("wasm failed")]
+    Wasm(#[from] anyhow::Error),
+    #[error("wasm memory access out of bounds")]
+    Memory(#[from] MemoryAccessError),
+    #[error("sql failed {0}")]
+    Sql(String),
+}
+
+impl Wasm {
+    pub(super) fn connect(path: &str, config: &DBConfig) -> Result<Self> {
+        let engine =
+            Engine::new(Config::new().epoch_interruption(config.statement_timeout.is_some()))?;
+        let module = Module::from_file(&engine, path)
+            .context(format!("failed to load wasm module {path}"))?;
+        let mut linker = Linker::new(&engine);
+        preview1::add_to_linker_sync(&mut linker, |ctx| ctx)?;
+        let wasi = WasiCtxBuilder::new()
+            .inherit_stdout()
+            .inherit_stderr()
+            .build_p1();
+        let mut store = Store::new(&engine, wasi);
+        // Only used with `epoch_interruption`. See `Wasm::run`.
+        store.set_epoch_deadline(1);
+        let instance = linker.instantiate(&mut store, &module)?;
+        if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut store, "_initialize") {
+            initialize.call(&mut store, ())?;
+        }
+
+        let mut wasm = Self {
+            memory: instance
+                .get_memory(&mut store, "memory")
+                .context("wasm module does not export `memory`")?,
+            alloc: instance.get_typed_func(&mut store, "slt_alloc")?,
+            free: instance.get_typed_func(&mut store, "slt_free")?,
+            run: instance.get_typed_func(&mut store, "slt_run")?,
+            store,
+            timeout: config.statement_timeout,
+        };
+
+        if let Ok(connect) =
+            instance.get_typed_func::<(i32, i32), i32>(&mut wasm.store, "slt_connect")
+        {
+            let config = serde_json::json!({
+                "db": config.db,
+                "user": config.user,
+                "pass": config.pass,
+            });
+            let (ptr, len) = wasm.write_str(&config.to_string())?;
+            let ret = connect.call(&mut wasm.store, (ptr, len))?;
+            wasm.free.call(&mut wasm.store, (ptr, len))?;
+            if ret != 0 {
+                anyhow::bail!("wasm module {path} failed to connect");
+            }
+        }
+
+        Ok(wasm)
+    }
+
+    /// Copies the string into a buffer allocated by the module.
+    fn write_str(&mut self, s: &str) -> Result<(i32, i32), WasmError> {
+        let len = s.len() as i32;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, s.as_bytes())?;
+        Ok((ptr, len))
+    }
+}
+
+impl sqllogictest::DB for Wasm {
+    type Error = WasmError;
+
+    fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
+        let (ptr, len) = self.write_str(sql)?;
+        // The epoch of the engine of this module is only incremented by the watchdog, so the
+        // deadline is only reached when the timeout has passed.
+        self.store.set_epoch_deadline(1);
+        let watchdog = self.timeout.map(|timeout| {
+            let engine = self.store.engine().clone();
+            Watchdog::start(timeout, move || engine.increment_epoch())
+        });
+        let ret = self.run.call(&mut self.store, (ptr, len));
+        drop(watchdog);
+        self.store.set_epoch_deadline(1);
+        let ret = ret?;
+        self.free.call(&mut self.store, (ptr, len))?;
+
+        let (ptr, len) = ((ret >> 32) as i32, ret as i32);
+        let mut buf = vec![0; len as u32 as usize];
+        self.memory
+            .read(&self.store, ptr as u32 as usize, &mut buf)?;
+        self.free.call(&mut self.store, (ptr, len))?;
+
+        match buf.split_first() {
+            Some((0, output)) => Ok(parse_text_output(&String::from_utf8_lossy(output))),
+            Some((_, error)) => Err(WasmError::Sql(String::from_utf8_lossy(error).into_owned())),
+            None => Err(WasmError::Sql("empty result".to_string())),
+        }
+    }
+
+    fn engine_name(&self) -> &str {
+        "wasm"
+    }
+}
@@ -11,7 +11,7 @@
 //!
 //! impl sqllogictest::DB for Database {
 //!     type Error = ...;
-//!     fn run(&self, sql: &str) -> Result<String, Self::Error> {
+//!     fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
 //!         ...
 //!     }
 //! }
@@ -1,5 +1,6 @@
 //! Sqllogictest parser.
 
+use std::cell::Cell;
 use std::fmt;
 use std::path::Path;
 use std::sync::Arc;
@@ -12,12 +13,14 @@ use crate::ParseErrorKind::InvalidIncludeFile;
 pub struct Location {
     file: Arc<str>,
     line: u32,
+    /// Records start at the first column, but a mismatch may be located at a value in a line.
+    column: u32,
     upper: Option<Arc<Location>>,
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}:{}", self.file, self.line)?;
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)?;
         if let Some(upper) = &self.upper {
             write!(f, "\nat {}", upper)?;
         }
@@ -36,14 +39,31 @@ impl Location {
         self.line
     }
 
-    fn new(file: impl Into<Arc<str>>, line: u32) -> Self {
+    /// Column number, counted in characters from 1.
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    pub(crate) fn new(file: impl Into<Arc<str>>, line: u32) -> Self {
         Self {
             file: file.into(),
             line,
+            column: 1,
             upper: None,
         }
     }
 
+    /// Returns the location at `column` of the line `lines` after this one, e.g. of a line of
+    /// the expected results of a query.
+    #[must_use]
+    pub(crate) fn offset(&self, lines: u32, column: u32) -> Self {
+        Self {
+            line: self.line + lines,
+            column,
+            ..self.clone()
+        }
+    }
+
     /// Returns the location of next line.
     #[must_use]
     fn next_line(mut self) -> Self {
@@ -51,11 +71,28 @@ impl Location {
         self
     }
 
+    /// Returns whether `file` is the file of this location, or of any location including it.
+    fn is_in(&self, file: &Path) -> bool {
+        let file = match std::fs::canonicalize(file) {
+            Ok(file) => file,
+            Err(_) => return false,
+        };
+        let mut loc = Some(self);
+        while let Some(l) = loc {
+            if std::fs::canonicalize(&*l.file).is_ok_and(|f| f == file) {
+                return true;
+            }
+            loc = l.upper.as_deref();
+        }
+        false
+    }
+
     /// Returns the location of next level file.
     fn include(&self, file: &str) -> Self {
         Self {
             file: file.into(),
             line: 0,
+            column: 1,
             upper: Some(Arc::new(self.clone())),
         }
     }
@@ -74,10 +111,18 @@ pub enum Record {
         conditions: Vec<Condition>,
         /// The SQL command is expected to fail instead of to succeed.
         error: bool,
+        /// The expected error, e.g. `division by zero` in `statement error division by zero`.
+        expected_error: Option<ExpectedError>,
         /// The SQL command.
         sql: String,
         /// Expected rows affected.
         expected_count: Option<u64>,
+        /// Rerun the statement if it fails, e.g. after `retry 3 backoff 1s`.
+        retry: Option<Retry>,
+        /// The statement is a known failure, e.g. after `# xfail: issue-123`, with the reason. Its
+        /// failure is reported as expected instead of failing the test, and its success as
+        /// unexpected.
+        xfail: Option<String>,
     },
     /// A query is an SQL command from which we expect to receive results. The result set might be
     /// empty.
@@ -87,26 +132,344 @@ pub enum Record {
         type_string: String,
         sort_mode: Option<SortMode>,
         label: Option<String>,
+        /// How the expected results are laid out, e.g. `query II nosort valuewise`, instead of
+        /// [`Control::ResultMode`].
+        result_mode: Option<ResultMode>,
+        /// The first line of the expected results is the names of the columns, e.g. `query II
+        /// rowsort colnames`, or `query II colnames,rowsort` in [`Dialect::Cockroach`]. See
+        /// [`DBOutput::Rows`](crate::DBOutput::Rows).
+        colnames: bool,
+        /// Compare the values of `R` columns within the tolerance, e.g. `query IR rowsort
+        /// tolerance=1e-6`, instead of [`Runner::set_float_tolerance`](crate::Runner::set_float_tolerance).
+        float_tolerance: Option<FloatTolerance>,
+        /// The query is expected to fail, e.g. `query error`, instead of returning results.
+        error: bool,
+        /// The expected error. See [`Record::Statement`].
+        expected_error: Option<ExpectedError>,
         /// The SQL command.
         sql: String,
         /// The expected results.
         expected_results: String,
+        /// The location of the first line of the expected results, after `----`, if any. Each
+        /// of the following lines is located by its offset from it.
+        results_loc: Option<Location>,
+        /// Rerun the query if it fails or its results mismatch. See [`Record::Statement`].
+        retry: Option<Retry>,
+        /// The query is a known failure. See [`Record::Statement`].
+        xfail: Option<String>,
     },
     /// A sleep period.
     Sleep { loc: Location, duration: Duration },
     /// Subtest.
     Subtest { loc: Location, name: String },
-    /// A halt record merely causes sqllogictest to ignore the rest of the test script.
-    /// For debugging use only.
-    Halt { loc: Location },
-    /// Control statements.
-    Control(Control),
+    /// Set the variable `name`, which replaces `$name` and `${name}` in the following records of
+    /// the file, e.g. `let $id = 42`.
+    Let {
+        loc: Location,
+        name: String,
+        value: LetValue,
+    },
+    /// Run the following records on the connection named `name`, created on first use, so that
+    /// tests can involve several sessions, e.g. of concurrent transactions. `connection default`
+    /// switches back to the connection of the runner.
+    Connection { loc: Location, name: String },
+    /// Query results with more values than the threshold are compared by their hash, and
+    /// written as `N values hashing to X` in the expected results, until the end of the file. 0
+    /// disables hashing.
+    HashThreshold { loc: Location, threshold: u64 },
+    /// A halt record merely causes sqllogictest to ignore the rest of the test script, or of the
+    /// included script it is in. With conditions, e.g. `onlyif mysql`, it is only effective if
+    /// they are met. For debugging use only.
+    Halt {
+        loc: Location,
+        conditions: Vec<Condition>,
+    },
+    /// A custom directive registered by the embedder, e.g. `flush` or `barrier 3`, for control
+    /// records specific to an engine. `text` is the whole line. See
+    /// [`ParseOptions::directives`].
+    Custom {
+        loc: Location,
+        conditions: Vec<Condition>,
+        name: String,
+        text: String,
+    },
+    /// A malformed record, or an unknown directive, skipped in [`ParseMode::Lenient`] and
+    /// reported as a warning. `text` is its lines.
+    Invalid {
+        loc: Location,
+        text: String,
+        error: ParseError,
+    },
+    /// A comment line, e.g. `# a comment`. Only kept with [`ParseOptions::keep_trivia`].
+    Comment { loc: Location, text: String },
+    /// An empty line, besides the one which ends a record. Only kept with
+    /// [`ParseOptions::keep_trivia`].
+    Newline { loc: Location },
+    /// Skip the rest of the file if the database does not have the capability, e.g. `require
+    /// parquet` in [`Dialect::DuckDb`]. See [`AsyncDB::has_capability`](crate::AsyncDB::has_capability).
+    Require { loc: Location, capability: String },
+    /// Open the database file at `path`, or a new in-memory database without it, instead of the
+    /// current one, e.g. `load __TEST_DIR__/test.db readonly` in [`Dialect::DuckDb`].
+    Load {
+        loc: Location,
+        path: Option<String>,
+        readonly: bool,
+    },
+    /// Close and reopen the database, e.g. to check that data is persisted, with `restart` in
+    /// [`Dialect::DuckDb`].
+    Restart { loc: Location },
+    /// Control statements, e.g. `control sortmode rowsort`.
+    Control { loc: Location, control: Control },
+}
+
+impl Record {
+    /// Whether the record can be run together with the records around it by
+    /// [`Runner::run_batch_async`](crate::Runner::run_batch_async), i.e. it is a statement whose
+    /// output is not checked, and which is not retried or a known failure.
+    pub fn can_batch(&self) -> bool {
+        matches!(
+            self,
+            Record::Statement {
+                expected_count: None,
+                retry: None,
+                xfail: None,
+                ..
+            }
+        )
+    }
+
+    /// Returns the location of the record.
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            Record::Include { loc, .. }
+            | Record::Statement { loc, .. }
+            | Record::Query { loc, .. }
+            | Record::Sleep { loc, .. }
+            | Record::Subtest { loc, .. }
+            | Record::Let { loc, .. }
+            | Record::Connection { loc, .. }
+            | Record::HashThreshold { loc, .. }
+            | Record::Halt { loc, .. }
+            | Record::Custom { loc, .. }
+            | Record::Invalid { loc, .. }
+            | Record::Comment { loc, .. }
+            | Record::Newline { loc }
+            | Record::Require { loc, .. }
+            | Record::Load { loc, .. }
+            | Record::Restart { loc }
+            | Record::Control { loc, .. } => Some(loc.clone()),
+        }
+    }
+}
+
+/// Writes the record as in a script, in the canonical form, e.g. with `----` only before
+/// expected results which are not empty. See [`unparse`].
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::Include { filename, .. } => writeln!(f, "include {filename}"),
+            Record::Statement {
+                conditions,
+                error,
+                expected_error,
+                sql,
+                expected_count,
+                retry,
+                xfail,
+                ..
+            } => {
+                write_prefix(f, conditions, retry, xfail)?;
+                match (expected_count, expected_error) {
+                    (Some(count), _) => writeln!(f, "statement count {count}")?,
+                    (None, Some(e)) if !is_block(e) => writeln!(f, "statement error {e}")?,
+                    _ if *error => writeln!(f, "statement error")?,
+                    _ => writeln!(f, "statement ok")?,
+                }
+                writeln!(f, "{sql}")?;
+                write_error_block(f, expected_error)
+            }
+            Record::Query {
+                conditions,
+                type_string,
+                sort_mode,
+                label,
+                result_mode,
+                colnames,
+                float_tolerance,
+                error,
+                expected_error,
+                sql,
+                expected_results,
+                results_loc,
+                retry,
+                xfail,
+                ..
+            } => {
+                write_prefix(f, conditions, retry, xfail)?;
+                if *error {
+                    match expected_error {
+                        Some(e) if !is_block(e) => writeln!(f, "query error {e}")?,
+                        _ => writeln!(f, "query error")?,
+                    }
+                    writeln!(f, "{sql}")?;
+                    return write_error_block(f, expected_error);
+                }
+                write!(f, "query {type_string}")?;
+                if let Some(sort_mode) = sort_mode {
+                    write!(f, " {}", sort_mode.as_str())?;
+                }
+                if let Some(label) = label {
+                    write!(f, " {label}")?;
+                }
+                if let Some(result_mode) = result_mode {
+                    write!(f, " {}", result_mode.as_str())?;
+                }
+                if let Some(tolerance) = float_tolerance {
+                    write!(f, " tolerance={tolerance}")?;
+                }
+                if *colnames {
+                    write!(f, " colnames")?;
+                }
+                writeln!(f)?;
+                writeln!(f, "{sql}")?;
+                // An empty results block is kept, as it expects no rows.
+                if results_loc.is_some() || !expected_results.is_empty() {
+                    writeln!(f, "----")?;
+                    write!(f, "{expected_results}")?;
+                }
+                Ok(())
+            }
+            Record::Sleep { duration, .. } => {
+                writeln!(f, "sleep {}", humantime::format_duration(*duration))
+            }
+            Record::Subtest { name, .. } => writeln!(f, "subtest {name}"),
+            Record::Let { name, value, .. } => match value {
+                LetValue::Literal(value) => writeln!(f, "let ${name} = {value}"),
+                LetValue::Query(sql) => writeln!(f, "let ${name}\n{sql}"),
+            },
+            Record::Connection { name, .. } => writeln!(f, "connection {name}"),
+            Record::HashThreshold { threshold, .. } => writeln!(f, "hash-threshold {threshold}"),
+            Record::Halt { conditions, .. } => {
+                write_prefix(f, conditions, &None, &None)?;
+                writeln!(f, "halt")
+            }
+            Record::Require { capability, .. } => writeln!(f, "require {capability}"),
+            Record::Load { path, readonly, .. } => {
+                write!(f, "load")?;
+                if let Some(path) = path {
+                    write!(f, " {path}")?;
+                }
+                if *readonly {
+                    write!(f, " readonly")?;
+                }
+                writeln!(f)
+            }
+            Record::Restart { .. } => writeln!(f, "restart"),
+            Record::Custom {
+                conditions, text, ..
+            } => {
+                write_prefix(f, conditions, &None, &None)?;
+                writeln!(f, "{text}")
+            }
+            Record::Comment { text, .. } | Record::Invalid { text, .. } => writeln!(f, "{text}"),
+            Record::Newline { .. } => writeln!(f),
+            Record::Control { control, .. } => match control {
+                Control::SortMode(sort_mode) => {
+                    writeln!(f, "control sortmode {}", sort_mode.as_str())
+                }
+                Control::FloatPrecision(precision) => {
+                    writeln!(f, "control float_precision {precision}")
+                }
+                Control::Protocol(protocol) => {
+                    writeln!(f, "control protocol {}", protocol.as_str())
+                }
+                Control::Substitution(on) => {
+                    writeln!(f, "control substitution {}", if *on { "on" } else { "off" })
+                }
+                Control::ResultMode(mode) => writeln!(f, "control resultmode {}", mode.as_str()),
+                Control::ResultTerminator(terminator) => {
+                    writeln!(f, "control result_terminator {}", terminator.as_str())
+                }
+                Control::Normalize(normalizer) => writeln!(f, "control normalize {normalizer}"),
+                Control::Shuffle(on) => {
+                    writeln!(f, "control shuffle {}", if *on { "on" } else { "off" })
+                }
+                // Injected by the parser, not written in the script.
+                Control::BeginInclude(_) | Control::EndInclude(_) => Ok(()),
+            },
+        }
+    }
+}
+
+/// Writes the known failure, the conditions and the retry of a record, on the lines before it.
+fn write_prefix(
+    f: &mut fmt::Formatter<'_>,
+    conditions: &[Condition],
+    retry: &Option<Retry>,
+    xfail: &Option<String>,
+) -> fmt::Result {
+    if let Some(reason) = xfail {
+        writeln!(f, "# xfail: {reason}")?;
+    }
+    for condition in conditions {
+        writeln!(f, "{condition}")?;
+    }
+    if let Some(Retry { attempts, backoff }) = retry {
+        write!(f, "retry {attempts}")?;
+        if !backoff.is_zero() {
+            write!(f, " backoff {}", humantime::format_duration(*backoff))?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+/// Whether the expected error is written on the lines after `----`, since it spans several.
+fn is_block(expected_error: &ExpectedError) -> bool {
+    expected_error.to_string().contains('\n')
+}
+
+fn write_error_block(
+    f: &mut fmt::Formatter<'_>,
+    expected_error: &Option<ExpectedError>,
+) -> fmt::Result {
+    match expected_error {
+        Some(e) if is_block(e) => writeln!(f, "----\n{e}"),
+        _ => Ok(()),
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Control {
     /// Control sort mode.
     SortMode(SortMode),
+    /// Round floats in the output to this many significant digits, e.g.
+    /// `control float_precision 6`. See
+    /// [`AsyncDB::set_float_precision`](crate::AsyncDB::set_float_precision).
+    FloatPrecision(usize),
+    /// Run the following records with this protocol, e.g. `control protocol simple`. See
+    /// [`AsyncDB::set_protocol`](crate::AsyncDB::set_protocol).
+    Protocol(Protocol),
+    /// Substitute variables in the following SQL and expected results of the file, e.g.
+    /// `${HOME}`, if on, or nothing at all if off, e.g. `control substitution on`. Variables set
+    /// by `let` take precedence over environment variables. By default, only `__TEST_DIR__`,
+    /// `__DATABASE__` and the variables set by `let` are substituted.
+    Substitution(bool),
+    /// How the expected results of the following queries in the file are laid out, e.g.
+    /// `control resultmode valuewise`.
+    ResultMode(ResultMode),
+    /// How the expected results of the following queries end, e.g. `control result_terminator
+    /// separator`. It is applied by the parser, so it is only kept with
+    /// [`ParseOptions::keep_trivia`].
+    ResultTerminator(ResultTerminator),
+    /// Rewrite the actual and the expected results of the following queries in the file, e.g.
+    /// `control normalize [0-9a-f]{8}(-[0-9a-f]{4}){3}-[0-9a-f]{12} <uuid>`, where the regex is
+    /// the first word, and the replacement the rest of the line. See [`Normalizer`].
+    Normalize(Normalizer),
+    /// Whether the following records are independent of each other, so that they may be run in
+    /// any order, e.g. `control shuffle on`. They are run in order by the runner, and shuffled
+    /// by `sqllogictest --shuffle` to find tests which depend on the order they run in.
+    Shuffle(bool),
     /// Pseudo control command to indicate the begin of an include statement. Automatically
     /// injected by sqllogictest parser.
     BeginInclude(String),
@@ -124,14 +487,30 @@ pub enum Condition {
     /// The statement or query is not evaluated if a `skipif` record for the target database engine
     /// is seen in the prefix.
     SkipIf { engine_name: String },
+    /// The statement or query is skipped if a `requires` record for a capability which the target
+    /// database engine does not have is seen, e.g. `requires arrays`. See
+    /// [`AsyncDB::has_capability`](crate::AsyncDB::has_capability).
+    Requires { capability: String },
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::OnlyIf { engine_name } => write!(f, "onlyif {engine_name}"),
+            Condition::SkipIf { engine_name } => write!(f, "skipif {engine_name}"),
+            Condition::Requires { capability } => write!(f, "requires {capability}"),
+        }
+    }
 }
 
 impl Condition {
-    /// Evaluate condition on given `targe_name`, returns whether to skip this record.
-    pub fn should_skip(&self, target_name: &str) -> bool {
+    /// Evaluate condition on given `targe_name`, and the capabilities of the target given by
+    /// `has_capability`, returns whether to skip this record.
+    pub fn should_skip(&self, target_name: &str, has_capability: impl Fn(&str) -> bool) -> bool {
         match self {
             Condition::OnlyIf { engine_name } => engine_name != target_name,
             Condition::SkipIf { engine_name } => engine_name == target_name,
+            Condition::Requires { capability } => !has_capability(capability),
         }
     }
 }
@@ -168,6 +547,386 @@ impl SortMode {
     }
 }
 
+/// How the values of the expected results of a query are laid out.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ResultMode {
+    /// Each row on a line, with values separated by any whitespace, e.g. a space, a tab, or
+    /// the spaces of aligned columns.
+    #[default]
+    RowWise,
+    /// Each value on a line, like in the files of the original sqllogictest. The values are
+    /// grouped into rows of as many values as the characters of the type string.
+    ValueWise,
+}
+
+impl ResultMode {
+    pub fn try_from_str(s: &str) -> Result<Self, ParseErrorKind> {
+        match s {
+            "rowwise" => Ok(Self::RowWise),
+            "valuewise" => Ok(Self::ValueWise),
+            _ => Err(ParseErrorKind::InvalidResultMode(s.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::RowWise => "rowwise",
+            Self::ValueWise => "valuewise",
+        }
+    }
+}
+
+/// How the expected results of a query end.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ResultTerminator {
+    /// At the first empty line, like in the original sqllogictest.
+    #[default]
+    BlankLine,
+    /// At the next `----` line, so that results may contain empty lines, which are ignored like
+    /// in the output.
+    Separator,
+}
+
+impl ResultTerminator {
+    pub fn try_from_str(s: &str) -> Result<Self, ParseErrorKind> {
+        match s {
+            "blank_line" => Ok(Self::BlankLine),
+            "separator" => Ok(Self::Separator),
+            _ => Err(ParseErrorKind::InvalidResultTerminator(s.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BlankLine => "blank_line",
+            Self::Separator => "separator",
+        }
+    }
+}
+
+/// The tolerance of the comparison of floats, e.g. `1e-6` in `query R tolerance=1e-6`. Two values
+/// match if they differ by at most the tolerance, relative to the larger of them if it is larger
+/// than 1, so that it is an absolute tolerance for small values and a relative one for large
+/// values.
+#[derive(Debug, Clone)]
+pub struct FloatTolerance {
+    /// The tolerance as written, to write it back unchanged.
+    text: String,
+    value: f64,
+}
+
+impl FloatTolerance {
+    pub fn try_from_str(s: &str) -> Result<Self, ParseErrorKind> {
+        match s.parse::<f64>() {
+            Ok(value) if value.is_finite() && value >= 0.0 => Ok(Self {
+                text: s.to_string(),
+                value,
+            }),
+            _ => Err(ParseErrorKind::InvalidFloatTolerance(s.to_string())),
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+impl PartialEq for FloatTolerance {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl Eq for FloatTolerance {}
+
+impl std::fmt::Display for FloatTolerance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// The dialect of the scripts, for test corpora of other projects which extend the format.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Dialect {
+    /// The format of this crate.
+    #[default]
+    Default,
+    /// The logic tests of CockroachDB. Query options are separated by commas, e.g. `query III
+    /// colnames,rowsort`, where `colnames` means the first line of the expected results is the
+    /// column names, and `partialsort` is checked like `rowsort`. Expected errors are regexes,
+    /// or SQLSTATEs after `pgcode`. The test configurations of `skipif config` and `onlyif
+    /// config` never match the engine.
+    Cockroach,
+    /// The sqllogictests of DuckDB, with `require`, `load` and `restart`. See
+    /// [`Record::Require`], [`Record::Load`] and [`Record::Restart`].
+    DuckDb,
+}
+
+impl Dialect {
+    pub fn try_from_str(s: &str) -> Result<Self, ParseErrorKind> {
+        match s {
+            "default" => Ok(Self::Default),
+            "cockroach" => Ok(Self::Cockroach),
+            "duckdb" => Ok(Self::DuckDb),
+            _ => Err(ParseErrorKind::InvalidDialect(s.to_string())),
+        }
+    }
+}
+
+/// How strictly scripts are parsed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ParseMode {
+    /// Malformed records and unknown directives fail the parse.
+    #[default]
+    Normal,
+    /// Malformed records and unknown directives are skipped, and kept as [`Record::Invalid`]
+    /// to be reported as warnings, e.g. to run files written for other engines.
+    Lenient,
+    /// Like [`ParseMode::Normal`], and lines must not end with whitespace, or mix tabs and
+    /// spaces, e.g. to keep files clean in CI.
+    Strict,
+}
+
+impl ParseMode {
+    pub fn try_from_str(s: &str) -> Result<Self, ParseErrorKind> {
+        match s {
+            "normal" => Ok(Self::Normal),
+            "lenient" => Ok(Self::Lenient),
+            "strict" => Ok(Self::Strict),
+            _ => Err(ParseErrorKind::InvalidParseMode(s.to_string())),
+        }
+    }
+}
+
+/// Options of the parser. See [`parse_with_options`] and [`parse_file_with_options`].
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ParseOptions {
+    /// How the expected results of queries end, until changed by
+    /// `control result_terminator separator` in a file.
+    pub result_terminator: ResultTerminator,
+    /// Keep comments, empty lines and `control result_terminator` as records, so that
+    /// [`unparse`] writes the script back as it was, e.g. to update its expected results. Loops
+    /// are expanded, and included files are not, regardless.
+    pub keep_trivia: bool,
+    /// The names of custom directives, e.g. `flush`, whose lines are parsed into
+    /// [`Record::Custom`] instead of failing as invalid. See
+    /// [`Runner::register_directive`](crate::Runner::register_directive).
+    pub directives: Vec<String>,
+    /// How strictly scripts are parsed.
+    pub mode: ParseMode,
+    /// The dialect of the scripts.
+    pub dialect: Dialect,
+}
+
+/// The protocol to run SQL with, for databases which support several, e.g. postgres.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Protocol {
+    /// The simple query protocol, where values are sent in the text format.
+    Simple,
+    /// The extended query protocol, where statements are prepared, and values may be sent in the
+    /// binary format.
+    Extended,
+}
+
+impl Protocol {
+    pub fn try_from_str(s: &str) -> Result<Self, ParseErrorKind> {
+        match s {
+            "simple" => Ok(Self::Simple),
+            "extended" => Ok(Self::Extended),
+            _ => Err(ParseErrorKind::InvalidProtocol(s.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Simple => "simple",
+            Self::Extended => "extended",
+        }
+    }
+}
+
+/// How to rerun a record which fails, declared by `retry 3 backoff 1s` before it, for systems
+/// which are eventually consistent, e.g. streaming databases.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Retry {
+    /// The number of times the record is rerun before it fails.
+    pub attempts: usize,
+    /// The time to wait before each rerun.
+    pub backoff: Duration,
+}
+
+/// The value of a variable set by [`Record::Let`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LetValue {
+    /// The rest of the line after `=`, e.g. `42` in `let $id = 42`.
+    Literal(String),
+    /// The single value returned by the SQL on the lines after `let $id`, e.g. ids generated by
+    /// the database.
+    Query(String),
+}
+
+/// The expected error of `statement error` and `query error`.
+#[derive(Debug, Clone)]
+pub enum ExpectedError {
+    /// A substring of the error message, e.g. `statement error division by zero`.
+    Substring(String),
+    /// A regex which the error message must match, e.g. `statement error regex: ^ERROR:.*denied`,
+    /// for messages with parts which vary between runs, like OIDs.
+    Regex(regex::Regex),
+    /// The SQLSTATE of the error, e.g. `statement error sqlstate 23505`. See
+    /// [`AsyncDB::error_code`](crate::AsyncDB::error_code).
+    SqlState(String),
+}
+
+impl ExpectedError {
+    /// Parses the expected error, which is a regex if prefixed by `regex:`, or a SQLSTATE if
+    /// prefixed by `sqlstate`.
+    pub fn parse(s: String) -> Result<Self, ParseErrorKind> {
+        if let Some(pattern) = s.strip_prefix("regex:") {
+            return regex::Regex::new(pattern.trim())
+                .map(Self::Regex)
+                .map_err(|_| ParseErrorKind::InvalidErrorRegex(pattern.trim().to_string()));
+        }
+        if let Some(code) = s.strip_prefix("sqlstate ") {
+            let code = code.trim();
+            // SQLSTATEs are five digits or uppercase letters.
+            if code.len() != 5
+                || !code
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+            {
+                return Err(ParseErrorKind::InvalidSqlState(code.to_string()));
+            }
+            return Ok(Self::SqlState(code.to_string()));
+        }
+        Ok(Self::Substring(s))
+    }
+
+    /// Parses the expected error in the dialect. In [`Dialect::Cockroach`], it is a regex, or
+    /// a SQLSTATE if prefixed by `pgcode`, e.g. `pgcode 42P01 relation "t" does not exist`,
+    /// where the message after the code is not checked.
+    pub fn parse_in(s: String, dialect: Dialect) -> Result<Self, ParseErrorKind> {
+        match dialect {
+            Dialect::Default | Dialect::DuckDb => Self::parse(s),
+            Dialect::Cockroach => match s.strip_prefix("pgcode ") {
+                Some(rest) => {
+                    let code = rest.split_whitespace().next().unwrap_or_default();
+                    Self::parse(format!("sqlstate {code}"))
+                }
+                None => Self::parse(format!("regex: {s}")),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ExpectedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Substring(s) => write!(f, "{s}"),
+            Self::Regex(regex) => write!(f, "regex: {regex}"),
+            Self::SqlState(code) => write!(f, "sqlstate {code}"),
+        }
+    }
+}
+
+// `Regex` is not comparable, so regexes are compared by their patterns.
+impl PartialEq for ExpectedError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Substring(a), Self::Substring(b)) => a == b,
+            (Self::Regex(a), Self::Regex(b)) => a.as_str() == b.as_str(),
+            (Self::SqlState(a), Self::SqlState(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ExpectedError {}
+
+/// A rule which rewrites the actual and the expected results of queries before they are compared,
+/// replacing the matches of the regex, e.g. to scrub UUIDs, timestamps or object IDs which vary
+/// between runs. The replacement may refer to groups, e.g. `$1`. See [`Control::Normalize`].
+#[derive(Debug, Clone)]
+pub struct Normalizer {
+    regex: regex::Regex,
+    replacement: String,
+}
+
+impl Normalizer {
+    pub fn new(regex: &str, replacement: impl Into<String>) -> Result<Self, ParseErrorKind> {
+        Ok(Self {
+            regex: regex::Regex::new(regex)
+                .map_err(|_| ParseErrorKind::InvalidNormalizerRegex(regex.to_string()))?,
+            replacement: replacement.into(),
+        })
+    }
+
+    /// Replaces all matches of the regex in `s`.
+    pub fn apply(&self, s: &str) -> String {
+        self.regex
+            .replace_all(s, self.replacement.as_str())
+            .into_owned()
+    }
+}
+
+impl fmt::Display for Normalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.regex)?;
+        if !self.replacement.is_empty() {
+            write!(f, " {}", self.replacement)?;
+        }
+        Ok(())
+    }
+}
+
+// `Regex` is not comparable, so normalizers are compared by their patterns.
+impl PartialEq for Normalizer {
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str() && self.replacement == other.replacement
+    }
+}
+
+impl Eq for Normalizer {}
+
+/// The class of the type of a column, declared by a character of the type string of a query,
+/// e.g. `I` and `T` in `query IT`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColumnType {
+    /// `I`, integers.
+    Integer,
+    /// `T`, strings.
+    Text,
+    /// `R`, floats and decimals.
+    FloatingPoint,
+    /// `B`, booleans.
+    Boolean,
+    /// Any other type, which matches every character.
+    Any,
+}
+
+impl ColumnType {
+    /// Returns the type declared by the character, or `None` if it is not one of `ITRB`.
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'I' => Some(Self::Integer),
+            'T' => Some(Self::Text),
+            'R' => Some(Self::FloatingPoint),
+            'B' => Some(Self::Boolean),
+            _ => None,
+        }
+    }
+
+    pub fn to_char(&self) -> char {
+        match self {
+            Self::Integer => 'I',
+            Self::Text => 'T',
+            Self::FloatingPoint => 'R',
+            Self::Boolean => 'B',
+            Self::Any => '?',
+        }
+    }
+}
+
 /// The error type for parsing sqllogictest.
 #[derive(thiserror::Error, Debug, PartialEq, Eq, Clone)]
 #[error("parse error at {loc}: {kind}")]
@@ -197,20 +956,50 @@ pub enum ParseErrorKind {
     UnexpectedEOF,
     #[error("invalid sort mode: {0:?}")]
     InvalidSortMode(String),
+    #[error("invalid protocol: {0:?}")]
+    InvalidProtocol(String),
+    #[error("invalid dialect: {0:?}")]
+    InvalidDialect(String),
+    #[error("invalid query option: {0:?}")]
+    InvalidQueryOption(String),
+    #[error("invalid parse mode: {0:?}")]
+    InvalidParseMode(String),
+    #[error("trailing whitespace")]
+    TrailingWhitespace,
+    #[error("tabs and spaces are mixed")]
+    MixedWhitespace,
+    #[error("invalid result mode: {0:?}")]
+    InvalidResultMode(String),
+    #[error("invalid float tolerance: {0:?}")]
+    InvalidFloatTolerance(String),
+    #[error("invalid regex of normalizer: {0:?}")]
+    InvalidNormalizerRegex(String),
+    #[error("invalid result terminator: {0:?}")]
+    InvalidResultTerminator(String),
     #[error("invalid line: {0:?}")]
     InvalidLine(String),
     #[error("invalid type string: {0:?}")]
     InvalidType(String),
     #[error("invalid number: {0:?}")]
     InvalidNumber(String),
+    #[error("invalid error regex: {0:?}")]
+    InvalidErrorRegex(String),
+    #[error("invalid sqlstate: {0:?}")]
+    InvalidSqlState(String),
     #[error("invalid duration: {0:?}")]
     InvalidDuration(String),
     #[error("invalid control: {0:?}")]
     InvalidControl(String),
     #[error("invalid include file pattern: {0:?}")]
     InvalidIncludeFile(String),
+    #[error("file includes itself: {0:?}")]
+    IncludeCycle(String),
+    #[error("loop {0:?} has no endloop")]
+    UnclosedLoop(String),
     #[error("no such file")]
     FileNotFound,
+    #[error("variable {0:?} is not set")]
+    UndefinedVariable(String),
 }
 
 impl ParseErrorKind {
@@ -221,159 +1010,1154 @@ impl ParseErrorKind {
 
 /// Parse a sqllogictest script into a list of records.
 pub fn parse(script: &str) -> Result<Vec<Record>, ParseError> {
-    parse_inner(&Location::new("<unknown>", 0), script)
+    parse_with_options(script, ParseOptions::default())
+}
+
+/// Parse a sqllogictest script into a list of records with the options.
+pub fn parse_with_options(script: &str, options: ParseOptions) -> Result<Vec<Record>, ParseError> {
+    parse_inner(&Location::new("<unknown>", 0), script, &options)
 }
 
 #[allow(clippy::collapsible_match)]
-fn parse_inner(loc: &Location, script: &str) -> Result<Vec<Record>, ParseError> {
-    let mut lines = script.split('\n').enumerate();
+fn parse_inner(
+    loc: &Location,
+    script: &str,
+    options: &ParseOptions,
+) -> Result<Vec<Record>, ParseError> {
+    // `control result_terminator` changes the options for the rest of the script.
+    let mut options = options.clone();
+    let at = |num: usize| {
+        let mut loc = loc.clone();
+        loc.line = num as u32 + 1;
+        loc
+    };
+    // The empty string after the last newline is not a line.
+    let last = script.split('\n').count() - 1;
+    // The index of the last line taken by a record, and whether it is empty.
+    let taken = Cell::new((0, false));
+    let mut lines = script
+        .split('\n')
+        .enumerate()
+        .inspect(|(num, line)| taken.set((*num, line.is_empty())));
+    if options.mode == ParseMode::Strict {
+        for (num, line) in script.split('\n').enumerate() {
+            check_whitespace(line).map_err(|(kind, column)| kind.at(at(num).offset(0, column)))?;
+        }
+    }
     let mut records = vec![];
     let mut conditions = vec![];
+    let mut retry = None;
+    // The line of the pending known failure.
+    let mut xfail: Option<(usize, &str)> = None;
     while let Some((num, line)) = lines.next() {
-        if line.is_empty() || line.starts_with('#') {
+        // Marks the following statement or query as a known failure.
+        if line.starts_with("# xfail:") {
+            if let Some((num, line)) = xfail.replace((num, line)) {
+                discard_xfail(&mut records, at(num), line, &options);
+            }
             continue;
         }
-        let mut loc = loc.clone();
-        loc.line = num as u32 + 1;
-        let tokens: Vec<&str> = line.split_whitespace().collect();
-        match tokens.as_slice() {
-            [] => continue,
-            ["include", included] => records.push(Record::Include {
-                loc,
-                filename: included.to_string(),
-            }),
-            ["halt"] => {
-                records.push(Record::Halt { loc });
-                break;
-            }
-            ["subtest", name] => {
-                records.push(Record::Subtest {
-                    loc,
-                    name: name.to_string(),
-                });
-            }
-            ["sleep", dur] => {
-                records.push(Record::Sleep {
-                    duration: humantime::parse_duration(dur).map_err(|_| {
-                        ParseErrorKind::InvalidDuration(dur.to_string()).at(loc.clone())
-                    })?,
-                    loc,
-                });
-            }
-            ["skipif", engine_name] => {
-                conditions.push(Condition::SkipIf {
-                    engine_name: engine_name.to_string(),
-                });
-            }
-            ["onlyif", engine_name] => {
-                conditions.push(Condition::OnlyIf {
-                    engine_name: engine_name.to_string(),
+        if line.is_empty() || line.starts_with('#') {
+            if options.keep_trivia && num != last {
+                records.push(match line {
+                    "" => Record::Newline { loc: at(num) },
+                    _ => Record::Comment {
+                        loc: at(num),
+                        text: line.to_string(),
+                    },
                 });
             }
-            ["statement", res @ ..] => {
-                let mut expected_count = None;
-                let error = match res {
-                    ["ok"] => false,
-                    ["error"] => true,
-                    ["count", count_str] => {
-                        expected_count = Some(count_str.parse::<u64>().map_err(|_| {
-                            ParseErrorKind::InvalidNumber((*count_str).into()).at(loc.clone())
-                        })?);
-                        false
-                    }
-                    _ => return Err(ParseErrorKind::InvalidLine(line.into()).at(loc)),
-                };
-                let mut sql = match lines.next() {
-                    Some((_, line)) => line.into(),
-                    None => return Err(ParseErrorKind::UnexpectedEOF.at(loc.next_line())),
-                };
-                for (_, line) in &mut lines {
-                    if line.is_empty() {
-                        break;
-                    }
-                    sql += "\n";
-                    sql += line;
+            continue;
+        }
+        // Parses the record, and returns whether to stop at an unconditional `halt`.
+        let result = (|| -> Result<bool, ParseError> {
+            let loc = at(num);
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            // A known failure is only for the next statement or query, after its conditions.
+            if !matches!(
+                tokens.as_slice(),
+                [
+                    "statement" | "query" | "skipif" | "onlyif" | "requires" | "retry",
+                    ..
+                ]
+            ) {
+                if let Some((num, line)) = xfail.take() {
+                    discard_xfail(&mut records, at(num), line, &options);
                 }
-                records.push(Record::Statement {
-                    loc,
-                    conditions: std::mem::take(&mut conditions),
-                    error,
-                    sql,
-                    expected_count,
-                });
             }
-            ["query", type_string, res @ ..] => {
-                let sort_mode = match res.first().map(|&s| SortMode::try_from_str(s)).transpose() {
-                    Ok(sm) => sm,
-                    Err(k) => return Err(k.at(loc)),
-                };
-                let label = res.get(1).map(|s| s.to_string());
-                // The SQL for the query is found on second an subsequent lines of the record
-                // up to first line of the form "----" or until the end of the record.
-                let mut sql = match lines.next() {
-                    Some((_, line)) => line.into(),
-                    None => return Err(ParseErrorKind::UnexpectedEOF.at(loc.next_line())),
-                };
-                let mut has_result = false;
-                for (_, line) in &mut lines {
-                    if line.is_empty() {
-                        break;
-                    }
-                    if line == "----" {
-                        has_result = true;
-                        break;
+            match tokens.as_slice() {
+                [] => return Ok(false),
+                ["include", included] => records.push(Record::Include {
+                    loc,
+                    filename: included.to_string(),
+                }),
+                ["halt"] => {
+                    let conditions = std::mem::take(&mut conditions);
+                    // Conditional halts are checked by the runner.
+                    let unconditional = conditions.is_empty();
+                    records.push(Record::Halt { loc, conditions });
+                    // The rest of the script is kept for `unparse`.
+                    if unconditional && !options.keep_trivia {
+                        return Ok(true);
                     }
-                    sql += "\n";
-                    sql += line;
                 }
-                // Lines following the "----" are expected results of the query, one value per line.
-                let mut expected_results = String::new();
-                if has_result {
-                    for (_, line) in &mut lines {
-                        if line.is_empty() {
-                            break;
+                ["let", var, rest @ ..] => {
+                    let name = match var.strip_prefix('$') {
+                        Some(name) if is_identifier(name) => name.to_string(),
+                        _ => return Err(ParseErrorKind::InvalidLine(line.into()).at(loc)),
+                    };
+                    let value = match rest {
+                        ["=", ..] => {
+                            let start = line.find('=').unwrap() + 1;
+                            LetValue::Literal(line[start..].trim().to_string())
                         }
-                        expected_results += line;
-                        expected_results.push('\n');
-                    }
+                        [] => {
+                            let sql = lines
+                                .by_ref()
+                                .map(|(_, line)| line)
+                                .take_while(|line| !line.is_empty())
+                                .collect::<Vec<_>>()
+                                .join("\n");
+                            if sql.is_empty() {
+                                return Err(ParseErrorKind::UnexpectedEOF.at(loc.next_line()));
+                            }
+                            LetValue::Query(sql)
+                        }
+                        _ => return Err(ParseErrorKind::InvalidLine(line.into()).at(loc)),
+                    };
+                    records.push(Record::Let { loc, name, value });
                 }
-                records.push(Record::Query {
-                    loc,
-                    conditions: std::mem::take(&mut conditions),
-                    type_string: type_string.to_string(),
-                    sort_mode,
-                    label,
-                    sql,
-                    expected_results,
-                });
-            }
-            ["control", res @ ..] => match res {
-                ["sortmode", sort_mode] => match SortMode::try_from_str(sort_mode) {
-                    Ok(sort_mode) => records.push(Record::Control(Control::SortMode(sort_mode))),
-                    Err(k) => return Err(k.at(loc)),
+                ["connection", name] => {
+                    records.push(Record::Connection {
+                        loc,
+                        name: name.to_string(),
+                    });
+                }
+                ["subtest", name] => {
+                    records.push(Record::Subtest {
+                        loc,
+                        name: name.to_string(),
+                    });
+                }
+                ["hash-threshold", threshold] => {
+                    records.push(Record::HashThreshold {
+                        threshold: threshold.parse::<u64>().map_err(|_| {
+                            ParseErrorKind::InvalidNumber((*threshold).into()).at(loc.clone())
+                        })?,
+                        loc,
+                    });
+                }
+                ["loop", var, start, end] => {
+                    let parse_num = |s: &str| {
+                        s.parse::<i64>()
+                            .map_err(|_| ParseErrorKind::InvalidNumber(s.into()).at(loc.clone()))
+                    };
+                    let (start, end) = (parse_num(start)?, parse_num(end)?);
+                    let body = loop_body(&mut lines, num).ok_or_else(|| {
+                        ParseErrorKind::UnclosedLoop(var.to_string()).at(loc.clone())
+                    })?;
+                    // The body is prefixed with empty lines, which are not trivia.
+                    let options = ParseOptions {
+                        keep_trivia: false,
+                        ..options.clone()
+                    };
+                    for i in start..end {
+                        records.extend(parse_inner(
+                            &loc,
+                            &substitute(&body, var, &i.to_string()),
+                            &options,
+                        )?);
+                    }
+                }
+                ["foreach", var, values @ ..] if !values.is_empty() => {
+                    let body = loop_body(&mut lines, num).ok_or_else(|| {
+                        ParseErrorKind::UnclosedLoop(var.to_string()).at(loc.clone())
+                    })?;
+                    let options = ParseOptions {
+                        keep_trivia: false,
+                        ..options.clone()
+                    };
+                    for value in values {
+                        records.extend(parse_inner(
+                            &loc,
+                            &substitute(&body, var, value),
+                            &options,
+                        )?);
+                    }
+                }
+                ["sleep", dur] => {
+                    records.push(Record::Sleep {
+                        duration: humantime::parse_duration(dur).map_err(|_| {
+                            ParseErrorKind::InvalidDuration(dur.to_string()).at(loc.clone())
+                        })?,
+                        loc,
+                    });
+                }
+                ["skipif" | "onlyif", "config", configs @ ..]
+                    if options.dialect == Dialect::Cockroach =>
+                {
+                    // No engine is named like this, so the records are always run with `skipif`,
+                    // and skipped with `onlyif`. Issues may be referenced after `#`.
+                    let engine_name = std::iter::once("config")
+                        .chain(configs.iter().copied().take_while(|t| !t.starts_with('#')))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    conditions.push(match tokens[0] {
+                        "skipif" => Condition::SkipIf { engine_name },
+                        _ => Condition::OnlyIf { engine_name },
+                    });
+                }
+                ["skipif", engine_name] => {
+                    conditions.push(Condition::SkipIf {
+                        engine_name: engine_name.to_string(),
+                    });
+                }
+                ["onlyif", engine_name] => {
+                    conditions.push(Condition::OnlyIf {
+                        engine_name: engine_name.to_string(),
+                    });
+                }
+                ["retry", attempts, rest @ ..] => {
+                    let attempts = attempts.parse::<usize>().map_err(|_| {
+                        ParseErrorKind::InvalidNumber((*attempts).into()).at(loc.clone())
+                    })?;
+                    let backoff = match rest {
+                        [] => Duration::ZERO,
+                        ["backoff", dur] => humantime::parse_duration(dur).map_err(|_| {
+                            ParseErrorKind::InvalidDuration(dur.to_string()).at(loc.clone())
+                        })?,
+                        _ => return Err(ParseErrorKind::InvalidLine(line.into()).at(loc)),
+                    };
+                    retry = Some(Retry { attempts, backoff });
+                }
+                ["require", capability @ ..]
+                    if options.dialect == Dialect::DuckDb && !capability.is_empty() =>
+                {
+                    records.push(Record::Require {
+                        loc,
+                        capability: capability.join(" "),
+                    });
+                }
+                ["load", rest @ ..] if options.dialect == Dialect::DuckDb => {
+                    let (path, readonly) = match rest {
+                        [] => (None, false),
+                        [path] => (Some(path.to_string()), false),
+                        [path, "readonly"] => (Some(path.to_string()), true),
+                        _ => return Err(ParseErrorKind::InvalidLine(line.into()).at(loc)),
+                    };
+                    records.push(Record::Load {
+                        loc,
+                        path,
+                        readonly,
+                    });
+                }
+                ["restart"] if options.dialect == Dialect::DuckDb => {
+                    records.push(Record::Restart { loc });
+                }
+                ["requires", capability] => {
+                    conditions.push(Condition::Requires {
+                        capability: capability.to_string(),
+                    });
+                }
+                ["statement", res @ ..] => {
+                    let mut expected_count = None;
+                    let mut expected_error = None;
+                    let error = match res {
+                        ["ok"] => false,
+                        ["error"] => true,
+                        ["error", ..] => {
+                            expected_error = Some(
+                                ExpectedError::parse_in(error_after_keyword(line), options.dialect)
+                                    .map_err(|kind| kind.at(loc.clone()))?,
+                            );
+                            true
+                        }
+                        ["count", count_str] | ["ok", count_str] => {
+                            expected_count = Some(count_str.parse::<u64>().map_err(|_| {
+                                ParseErrorKind::InvalidNumber((*count_str).into()).at(loc.clone())
+                            })?);
+                            false
+                        }
+                        _ => return Err(ParseErrorKind::InvalidLine(line.into()).at(loc)),
+                    };
+                    let mut sql = match lines.next() {
+                        Some((_, line)) => line.into(),
+                        None => return Err(ParseErrorKind::UnexpectedEOF.at(loc.next_line())),
+                    };
+                    for (_, line) in &mut lines {
+                        if line.is_empty() {
+                            break;
+                        }
+                        // The lines following "----" are the message of the expected error.
+                        if error && line == "----" {
+                            expected_error = Some(
+                                ExpectedError::parse_in(block(&mut lines), options.dialect)
+                                    .map_err(|kind| kind.at(loc.clone()))?,
+                            );
+                            break;
+                        }
+                        sql += "\n";
+                        sql += line;
+                    }
+                    records.push(Record::Statement {
+                        loc,
+                        conditions: std::mem::take(&mut conditions),
+                        error,
+                        expected_error,
+                        sql,
+                        expected_count,
+                        retry: retry.take(),
+                        xfail: xfail.take().map(|(_, line)| xfail_reason(line)),
+                    });
+                }
+                ["query", "error", ..] => {
+                    let mut expected_error = match tokens.len() {
+                        2 => None,
+                        _ => Some(
+                            ExpectedError::parse_in(error_after_keyword(line), options.dialect)
+                                .map_err(|kind| kind.at(loc.clone()))?,
+                        ),
+                    };
+                    let mut sql = match lines.next() {
+                        Some((_, line)) => line.into(),
+                        None => return Err(ParseErrorKind::UnexpectedEOF.at(loc.next_line())),
+                    };
+                    for (_, line) in &mut lines {
+                        if line.is_empty() {
+                            break;
+                        }
+                        if line == "----" {
+                            expected_error = Some(
+                                ExpectedError::parse_in(block(&mut lines), options.dialect)
+                                    .map_err(|kind| kind.at(loc.clone()))?,
+                            );
+                            break;
+                        }
+                        sql += "\n";
+                        sql += line;
+                    }
+                    records.push(Record::Query {
+                        loc,
+                        conditions: std::mem::take(&mut conditions),
+                        type_string: String::new(),
+                        sort_mode: None,
+                        label: None,
+                        result_mode: None,
+                        colnames: false,
+                        float_tolerance: None,
+                        error: true,
+                        expected_error,
+                        sql,
+                        expected_results: String::new(),
+                        results_loc: None,
+                        retry: retry.take(),
+                        xfail: xfail.take().map(|(_, line)| xfail_reason(line)),
+                    });
+                }
+                ["query", type_string, res @ ..] => {
+                    // `colnames` and the float tolerance may follow the other options.
+                    let (mut res, mut colnames, mut float_tolerance) = (res, false, None);
+                    loop {
+                        match res {
+                            [rest @ .., "colnames"] if !colnames => {
+                                (res, colnames) = (rest, true);
+                            }
+                            [rest @ .., last] if float_tolerance.is_none() => {
+                                match last.strip_prefix("tolerance=") {
+                                    Some(s) => {
+                                        float_tolerance = Some(
+                                            FloatTolerance::try_from_str(s)
+                                                .map_err(|k| k.at(loc.clone()))?,
+                                        );
+                                        res = rest;
+                                    }
+                                    None => break,
+                                }
+                            }
+                            _ => break,
+                        }
+                    }
+                    // The result mode may follow the sort mode and the label, or come first.
+                    let (res, result_mode) = match res {
+                        [rest @ .., last] if ResultMode::try_from_str(last).is_ok() => {
+                            (rest, ResultMode::try_from_str(last).ok())
+                        }
+                        [first, rest @ ..] if ResultMode::try_from_str(first).is_ok() => {
+                            (rest, ResultMode::try_from_str(first).ok())
+                        }
+                        _ => (res, None),
+                    };
+                    let sort_mode = match (options.dialect, res.first()) {
+                        (Dialect::Cockroach, Some(query_options)) => {
+                            cockroach_query_options(query_options, &mut colnames)
+                        }
+                        (_, first) => first.map(|&s| SortMode::try_from_str(s)).transpose(),
+                    }
+                    .map_err(|k| k.at(loc.clone()))?;
+                    let label = res.get(1).map(|s| s.to_string());
+                    // The SQL for the query is found on second an subsequent lines of the record
+                    // up to first line of the form "----" or until the end of the record.
+                    let mut sql = match lines.next() {
+                        Some((_, line)) => line.into(),
+                        None => return Err(ParseErrorKind::UnexpectedEOF.at(loc.next_line())),
+                    };
+                    let mut results_loc = None;
+                    for (num, line) in &mut lines {
+                        if line.is_empty() {
+                            break;
+                        }
+                        if line == "----" {
+                            results_loc = Some(at(num + 1));
+                            break;
+                        }
+                        sql += "\n";
+                        sql += line;
+                    }
+                    // Lines following the "----" are expected results of the query, one value per line.
+                    let mut expected_results = String::new();
+                    if results_loc.is_some() {
+                        for (_, line) in &mut lines {
+                            let end = match options.result_terminator {
+                                ResultTerminator::BlankLine => line.is_empty(),
+                                ResultTerminator::Separator => line == "----",
+                            };
+                            if end {
+                                break;
+                            }
+                            expected_results += line;
+                            expected_results.push('\n');
+                        }
+                    }
+                    records.push(Record::Query {
+                        loc,
+                        conditions: std::mem::take(&mut conditions),
+                        type_string: type_string.to_string(),
+                        sort_mode,
+                        label,
+                        result_mode,
+                        colnames,
+                        float_tolerance,
+                        error: false,
+                        expected_error: None,
+                        sql,
+                        expected_results,
+                        results_loc,
+                        retry: retry.take(),
+                        xfail: xfail.take().map(|(_, line)| xfail_reason(line)),
+                    });
+                }
+                ["control", res @ ..] => match res {
+                    ["sortmode", sort_mode] => match SortMode::try_from_str(sort_mode) {
+                        Ok(sort_mode) => records.push(Record::Control {
+                            loc,
+                            control: Control::SortMode(sort_mode),
+                        }),
+                        Err(k) => return Err(k.at(loc)),
+                    },
+                    ["float_precision", precision] => match precision.parse() {
+                        Ok(precision) if precision > 0 => records.push(Record::Control {
+                            loc,
+                            control: Control::FloatPrecision(precision),
+                        }),
+                        _ => return Err(ParseErrorKind::InvalidControl(line.into()).at(loc)),
+                    },
+                    ["protocol", protocol] => match Protocol::try_from_str(protocol) {
+                        Ok(protocol) => records.push(Record::Control {
+                            loc,
+                            control: Control::Protocol(protocol),
+                        }),
+                        Err(k) => return Err(k.at(loc)),
+                    },
+                    // Changes how the following records are parsed, so the record is only kept as trivia.
+                    ["result_terminator", terminator] => {
+                        match ResultTerminator::try_from_str(terminator) {
+                            Ok(terminator) => {
+                                options.result_terminator = terminator;
+                                if options.keep_trivia {
+                                    records.push(Record::Control {
+                                        loc,
+                                        control: Control::ResultTerminator(terminator),
+                                    });
+                                }
+                            }
+                            Err(k) => return Err(k.at(loc)),
+                        }
+                    }
+                    ["resultmode", mode] => match ResultMode::try_from_str(mode) {
+                        Ok(mode) => records.push(Record::Control {
+                            loc,
+                            control: Control::ResultMode(mode),
+                        }),
+                        Err(k) => return Err(k.at(loc)),
+                    },
+                    ["normalize", regex, ..] => {
+                        // The replacement is the rest of the line, and may contain spaces.
+                        let replacement = line
+                            .trim_start()
+                            .strip_prefix("control")
+                            .and_then(|s| s.trim_start().strip_prefix("normalize"))
+                            .and_then(|s| s.trim_start().strip_prefix(*regex))
+                            .unwrap_or_default()
+                            .trim();
+                        match Normalizer::new(regex, replacement) {
+                            Ok(normalizer) => records.push(Record::Control {
+                                loc,
+                                control: Control::Normalize(normalizer),
+                            }),
+                            Err(k) => return Err(k.at(loc)),
+                        }
+                    }
+                    ["substitution", on_off] => match *on_off {
+                        "on" => records.push(Record::Control {
+                            loc,
+                            control: Control::Substitution(true),
+                        }),
+                        "off" => records.push(Record::Control {
+                            loc,
+                            control: Control::Substitution(false),
+                        }),
+                        _ => return Err(ParseErrorKind::InvalidControl(line.into()).at(loc)),
+                    },
+                    ["shuffle", on_off] => match *on_off {
+                        "on" => records.push(Record::Control {
+                            loc,
+                            control: Control::Shuffle(true),
+                        }),
+                        "off" => records.push(Record::Control {
+                            loc,
+                            control: Control::Shuffle(false),
+                        }),
+                        _ => return Err(ParseErrorKind::InvalidControl(line.into()).at(loc)),
+                    },
+                    _ => return Err(ParseErrorKind::InvalidLine(line.into()).at(loc)),
                 },
+                [name, ..] if options.directives.iter().any(|d| d == name) => {
+                    records.push(Record::Custom {
+                        loc,
+                        conditions: std::mem::take(&mut conditions),
+                        name: name.to_string(),
+                        text: line.to_string(),
+                    });
+                }
                 _ => return Err(ParseErrorKind::InvalidLine(line.into()).at(loc)),
-            },
-            _ => return Err(ParseErrorKind::InvalidLine(line.into()).at(loc)),
+            }
+            Ok(false)
+        })();
+        match result {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(error) if options.mode == ParseMode::Lenient => {
+                // Skip the rest of the record, up to the empty line which ends it.
+                if !taken.get().1 {
+                    for (_, line) in &mut lines {
+                        if line.is_empty() {
+                            break;
+                        }
+                    }
+                }
+                let (end, empty) = taken.get();
+                let end = if empty { end } else { end + 1 };
+                let text = script.split('\n').collect::<Vec<_>>()[num..end].join("\n");
+                // The conditions, the retry and the known failure were for the skipped record.
+                conditions.clear();
+                retry = None;
+                xfail = None;
+                records.push(Record::Invalid {
+                    loc: at(num),
+                    text,
+                    error,
+                });
+            }
+            Err(error) => return Err(error),
         }
+        // The empty line which ended the record.
+        if let (end, true) = taken.get() {
+            if options.keep_trivia && end > num && end != last {
+                records.push(Record::Newline { loc: at(end) });
+            }
+        }
+    }
+    if let Some((num, line)) = xfail {
+        discard_xfail(&mut records, at(num), line, &options);
     }
     Ok(records)
 }
 
+/// Returns the reason of a known failure, e.g. `issue-123` in `# xfail: issue-123`.
+fn xfail_reason(line: &str) -> String {
+    line["# xfail:".len()..].trim().to_string()
+}
+
+/// Drops a known failure which no statement or query follows, and keeps its line as a comment
+/// for `unparse`.
+fn discard_xfail(records: &mut Vec<Record>, loc: Location, line: &str, options: &ParseOptions) {
+    if options.keep_trivia {
+        records.push(Record::Comment {
+            loc,
+            text: line.to_string(),
+        });
+    }
+}
+
+/// Parses the comma-separated options of a query in [`Dialect::Cockroach`], e.g.
+/// `colnames,partialsort(1,2)`, and returns the sort mode.
+fn cockroach_query_options(
+    query_options: &str,
+    colnames: &mut bool,
+) -> Result<Option<SortMode>, ParseErrorKind> {
+    let mut sort_mode = None;
+    let mut depth = 0;
+    // Commas in parentheses, e.g. of `partialsort(1,2)`, do not separate options.
+    let split = query_options.split(|c| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        c == ',' && depth == 0
+    });
+    for option in split {
+        match option {
+            "colnames" => *colnames = true,
+            "nosort" | "rowsort" | "valuesort" => sort_mode = Some(SortMode::try_from_str(option)?),
+            // The rows are sorted by some columns, and the others may be in any order, which
+            // `rowsort` accepts too.
+            _ if option.starts_with("partialsort(") => sort_mode = Some(SortMode::RowSort),
+            _ => return Err(ParseErrorKind::InvalidQueryOption(option.to_string())),
+        }
+    }
+    Ok(sort_mode)
+}
+
+/// Checks that the line does not end with whitespace, or mix tabs and spaces in a run of
+/// whitespace, for [`ParseMode::Strict`]. Returns the error and its column otherwise.
+fn check_whitespace(line: &str) -> Result<(), (ParseErrorKind, u32)> {
+    let column = |i: usize| line[..i].chars().count() as u32 + 1;
+    let trimmed = line.trim_end();
+    if trimmed.len() != line.len() {
+        return Err((ParseErrorKind::TrailingWhitespace, column(trimmed.len())));
+    }
+    let mut run = None;
+    for (i, c) in line.char_indices() {
+        match (c, run) {
+            (' ' | '\t', None) => run = Some((i, c)),
+            (' ' | '\t', Some((start, first))) if c != first => {
+                return Err((ParseErrorKind::MixedWhitespace, column(start)));
+            }
+            (' ' | '\t', Some(_)) => {}
+            _ => run = None,
+        }
+    }
+    Ok(())
+}
+
+/// Returns the rest of the line after the `error` keyword, e.g. `division by zero` in
+/// `statement error division by zero`.
+fn error_after_keyword(line: &str) -> String {
+    let start = line.find("error").unwrap() + "error".len();
+    line[start..].trim().to_string()
+}
+
+/// Returns the lines up to the next empty line, joined by newlines.
+fn block<'a>(lines: &mut impl Iterator<Item = (usize, &'a str)>) -> String {
+    lines
+        .map(|(_, line)| line)
+        .take_while(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the lines up to the `endloop` matching the `loop` or `foreach` at line index `num`, or
+/// `None` if there is none. The body is prefixed with empty lines, so that the records parsed
+/// from it have the line numbers in the file.
+fn loop_body<'a>(lines: &mut impl Iterator<Item = (usize, &'a str)>, num: usize) -> Option<String> {
+    let mut body = "\n".repeat(num + 1);
+    let mut depth = 0;
+    for (_, line) in lines {
+        match line.split_whitespace().next() {
+            Some("endloop") if depth == 0 => return Some(body),
+            Some("endloop") => depth -= 1,
+            Some("loop" | "foreach") => depth += 1,
+            _ => {}
+        }
+        body += line;
+        body.push('\n');
+    }
+    None
+}
+
+/// Returns whether the name is made of the characters of an identifier, like variables.
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Replaces `${var}` and `$var` with the value, where `$var` is not followed by other characters
+/// of an identifier.
+pub(crate) fn substitute(s: &str, var: &str, value: &str) -> String {
+    let s = s.replace(&format!("${{{var}}}"), value);
+    let pattern = format!("${var}");
+    let mut output = String::with_capacity(s.len());
+    let mut rest = s.as_str();
+    while let Some(pos) = rest.find(&pattern) {
+        output += &rest[..pos];
+        rest = &rest[pos + pattern.len()..];
+        if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            output += &pattern;
+        } else {
+            output += value;
+        }
+    }
+    output += rest;
+    output
+}
+
+/// Checks the records of a script for mistakes which are parsed, but make the records fail or
+/// behave unexpectedly when run, e.g. for `sqllogictest --dry-run`:
+///
+/// - Type strings of queries must be made of uppercase letters, e.g. `IT`, which catches a
+///   missing type string like `query rowsort`.
+/// - Variables like `${name}` in SQL and expected results must be set by a `let` record before,
+///   or be set in the environment after `control substitution on`. Variables are not checked
+///   after `control substitution off`.
+pub fn validate(records: &[Record]) -> Vec<ParseError> {
+    let mut errors = vec![];
+    let mut variables = std::collections::HashSet::new();
+    let mut substitution = None;
+    for record in records {
+        let (loc, texts) = match record {
+            Record::Statement { loc, sql, .. } => (loc, vec![sql]),
+            Record::Query {
+                loc,
+                type_string,
+                sql,
+                expected_results,
+                error,
+                ..
+            } => {
+                if !error && !type_string.chars().all(|c| c.is_ascii_uppercase()) {
+                    errors.push(ParseErrorKind::InvalidType(type_string.clone()).at(loc.clone()));
+                }
+                (loc, vec![sql, expected_results])
+            }
+            Record::Let {
+                loc,
+                name,
+                value: LetValue::Literal(text) | LetValue::Query(text),
+            } => {
+                // The value is substituted before the variable is set.
+                for name in unset_variables(text, &variables, substitution) {
+                    errors.push(ParseErrorKind::UndefinedVariable(name).at(loc.clone()));
+                }
+                variables.insert(name.clone());
+                continue;
+            }
+            Record::Control {
+                control: Control::Substitution(on),
+                ..
+            } => {
+                substitution = Some(*on);
+                continue;
+            }
+            _ => continue,
+        };
+        // Each variable is reported once, even if it is in the SQL and the expected results.
+        let mut unset: Vec<String> = vec![];
+        for name in texts
+            .into_iter()
+            .flat_map(|text| unset_variables(text, &variables, substitution))
+        {
+            if !unset.contains(&name) {
+                unset.push(name);
+            }
+        }
+        for name in unset {
+            errors.push(ParseErrorKind::UndefinedVariable(name).at(loc.clone()));
+        }
+    }
+    errors
+}
+
+/// Returns the names of the variables like `${name}` or `$name` in the text which are not set.
+/// See [`validate`].
+fn unset_variables(
+    text: &str,
+    variables: &std::collections::HashSet<String>,
+    substitution: Option<bool>,
+) -> Vec<String> {
+    let mut unset = vec![];
+    if substitution == Some(false) {
+        return unset;
+    }
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        rest = &rest[start + 1..];
+        let (name, set) = match rest.strip_prefix('{') {
+            Some(braced) => {
+                let end = match braced.find('}') {
+                    Some(end) => end,
+                    None => break,
+                };
+                let name = &braced[..end];
+                rest = &braced[end + 1..];
+                // Only `${name}` is substituted with environment variables.
+                let env = substitution == Some(true) && std::env::var_os(name).is_some();
+                (name, variables.contains(name) || env)
+            }
+            None => {
+                let end = rest
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                let name = &rest[..end];
+                rest = &rest[end..];
+                // `$1` is a parameter, and `$tag$` a dollar quote, e.g. of Postgres.
+                if name.starts_with(|c: char| c.is_ascii_digit()) || rest.starts_with('$') {
+                    continue;
+                }
+                (name, variables.contains(name))
+            }
+        };
+        if is_identifier(name) && !set {
+            unset.push(name.to_string());
+        }
+    }
+    unset
+}
+
+/// Writes the records back into a script. The records parsed from a script with
+/// [`ParseOptions::keep_trivia`] are written as they were, if the script has no loops and its
+/// records are in the canonical form of the [`Display`](fmt::Display) of [`Record`].
+pub fn unparse(records: &[Record]) -> String {
+    let mut script = String::new();
+    let mut terminator = ResultTerminator::BlankLine;
+    for record in records {
+        script += &record.to_string();
+        match record {
+            Record::Control {
+                control: Control::ResultTerminator(t),
+                ..
+            } => terminator = *t,
+            Record::Query {
+                expected_results,
+                results_loc,
+                ..
+            } if terminator == ResultTerminator::Separator
+                && (results_loc.is_some() || !expected_results.is_empty()) =>
+            {
+                script += "----\n";
+            }
+            _ => {}
+        }
+    }
+    script
+}
+
+/// Formats a script in a canonical form, e.g. for `sqllogictest fmt`, so that large trees of test
+/// files stay consistent:
+///
+/// - Trailing whitespace and repeated empty lines are removed, and the words of the directives
+///   are separated by single spaces, e.g. `statement  ok` becomes `statement ok`. SQL, comments,
+///   error messages, custom directives and lines which are not directives as the parser spells
+///   them, e.g. `Statement ok`, are kept as they are.
+/// - The expected results of `rowsort` queries are sorted, and those of `valuesort` queries too
+///   if they are written one value per line, as the runner sorts them before comparing.
+/// - The values of the rows of expected results are aligned in columns, if every row has one
+///   value for each column, as a value may contain spaces.
+///
+/// `result_mode` is how the expected results are laid out until changed by `control resultmode`,
+/// which the rows to sort depend on. The expected results of files ended by `----` (see
+/// [`ResultTerminator::Separator`]) are kept as they are.
+///
+/// Fails if the script or the formatted script can not be parsed with the options.
+pub fn format_script(
+    script: &str,
+    options: &ParseOptions,
+    result_mode: ResultMode,
+) -> Result<String, ParseError> {
+    let formatted = format_lines(script, options, result_mode)?;
+    // The error is located in the script if it is invalid already.
+    parse_with_options(&formatted, options.clone()).map_err(|e| {
+        parse_with_options(script, options.clone())
+            .err()
+            .unwrap_or(e)
+    })?;
+    Ok(formatted)
+}
+
+fn format_lines(
+    script: &str,
+    options: &ParseOptions,
+    mut result_mode: ResultMode,
+) -> Result<String, ParseError> {
+    let mut output: Vec<String> = vec![];
+    let mut terminator = options.result_terminator;
+    let mut sort_mode = None;
+    // The number of the last line taken.
+    let num = Cell::new(0);
+    let mut lines = script
+        .lines()
+        .map(str::trim_end)
+        .inspect(|_| num.set(num.get() + 1));
+    while let Some(line) = lines.next() {
+        if line.is_empty() {
+            // Repeated empty lines, and those at the start, are removed.
+            if output.last().is_some_and(|last| !last.is_empty()) {
+                output.push(String::new());
+            }
+            continue;
+        }
+        let line = line.trim_start();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        // Directives are only recognized as the parser spells them, so that a line which is not
+        // one, e.g. `Statement ok`, is not turned into one.
+        if line.starts_with('#') || !FORMATTED_DIRECTIVES.contains(&words[0]) {
+            output.push(line.to_string());
+            continue;
+        }
+        // The number of words of the directive followed by free text, e.g. an error message.
+        let mut free_after = None;
+        match words.as_slice() {
+            ["statement" | "query"] => {
+                let loc = Location::new("<unknown>", num.get());
+                return Err(ParseErrorKind::InvalidLine(line.into()).at(loc));
+            }
+            ["statement" | "query", "error", ..] => free_after = Some(2),
+            ["control", "normalize", ..] => free_after = Some(2),
+            ["control", "sortmode", v] => sort_mode = SortMode::try_from_str(v).ok(),
+            ["control", "resultmode", v] => {
+                result_mode = ResultMode::try_from_str(v).unwrap_or(result_mode)
+            }
+            ["control", "result_terminator", v] => {
+                terminator = ResultTerminator::try_from_str(v).unwrap_or(terminator)
+            }
+            ["let", _, "=", ..] => free_after = Some(3),
+            ["skipif" | "onlyif" | "require", ..] => free_after = Some(1),
+            _ => {}
+        }
+        output.push(match free_after {
+            Some(n) if words.len() > n => {
+                format!("{} {}", words[..n].join(" "), rest_after_words(line, n))
+            }
+            _ => words.join(" "),
+        });
+
+        match words.as_slice() {
+            ["statement", ..] | ["query", "error", ..] => {
+                // The SQL, and the message of the expected error after `----`.
+                for line in lines.by_ref().take_while(|line| !line.is_empty()) {
+                    output.push(line.to_string());
+                }
+                output.push(String::new());
+            }
+            ["query", type_string, query_options @ ..] => {
+                let mut has_results = false;
+                for line in lines.by_ref().take_while(|line| !line.is_empty()) {
+                    output.push(line.to_string());
+                    if line == "----" {
+                        has_results = true;
+                        break;
+                    }
+                }
+                if !has_results {
+                    output.push(String::new());
+                    continue;
+                }
+                let mut results = vec![];
+                for line in lines.by_ref() {
+                    let end = match terminator {
+                        ResultTerminator::BlankLine => line.is_empty(),
+                        ResultTerminator::Separator => line == "----",
+                    };
+                    if end {
+                        break;
+                    }
+                    results.push(line);
+                }
+                if terminator == ResultTerminator::Separator {
+                    output.extend(results.iter().map(|line| line.to_string()));
+                    output.push("----".to_string());
+                    continue;
+                }
+                let option = |name: &str| {
+                    query_options
+                        .iter()
+                        .any(|o| o.split(',').any(|o| o == name))
+                };
+                let query_sort_mode = ["nosort", "rowsort", "valuesort"]
+                    .into_iter()
+                    .find(|mode| option(mode))
+                    .and_then(|mode| SortMode::try_from_str(mode).ok())
+                    .or_else(|| sort_mode.clone());
+                let query_result_mode = match (option("valuewise"), option("rowwise")) {
+                    (true, _) => ResultMode::ValueWise,
+                    (_, true) => ResultMode::RowWise,
+                    _ => result_mode,
+                };
+                output.extend(format_results(
+                    results,
+                    query_sort_mode,
+                    query_result_mode,
+                    type_string.len(),
+                    option("colnames"),
+                ));
+                output.push(String::new());
+            }
+            ["let", _] => {
+                for line in lines.by_ref().take_while(|line| !line.is_empty()) {
+                    output.push(line.to_string());
+                }
+                output.push(String::new());
+            }
+            _ => {}
+        }
+    }
+    while output.last().is_some_and(|last| last.is_empty()) {
+        output.pop();
+    }
+    Ok(output.into_iter().map(|line| line + "\n").collect())
+}
+
+/// The directives formatted by [`format_script`]. The lines of others are kept as they are.
+const FORMATTED_DIRECTIVES: &[&str] = &[
+    "statement",
+    "query",
+    "control",
+    "include",
+    "halt",
+    "let",
+    "connection",
+    "subtest",
+    "hash-threshold",
+    "loop",
+    "foreach",
+    "endloop",
+    "sleep",
+    "skipif",
+    "onlyif",
+    "requires",
+    "retry",
+    "require",
+    "load",
+    "restart",
+];
+
+/// Returns the rest of the line after `n` words, e.g. the message after `statement error`.
+fn rest_after_words(line: &str, n: usize) -> &str {
+    let mut rest = line.trim_start();
+    for _ in 0..n {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    rest
+}
+
+/// Sorts the expected results of a query as they are compared, and aligns their values in
+/// columns. Hashed results are kept as they are.
+///
+/// The values of a row are only known if the row has one value for each of the `columns`, and
+/// those of the results are only aligned if all of the rows do, as a value may contain spaces.
+fn format_results(
+    results: Vec<&str>,
+    sort_mode: Option<SortMode>,
+    result_mode: ResultMode,
+    columns: usize,
+    colnames: bool,
+) -> Vec<String> {
+    let mut rows = results;
+    let names = match colnames && !rows.is_empty() {
+        true => Some(rows.remove(0)),
+        false => None,
+    };
+    // The runner compares the rows with their whitespace normalized.
+    let normalized = |row: &str| row.split_whitespace().collect::<Vec<_>>().join(" ");
+    let hashed = matches!(rows.as_slice(), [row]
+        if row.split_whitespace().skip(1).take(3).eq(["values", "hashing", "to"]));
+    match (sort_mode, result_mode) {
+        _ if hashed => {}
+        (Some(SortMode::RowSort), ResultMode::RowWise) => {
+            rows.sort_by_cached_key(|row| normalized(row))
+        }
+        (Some(SortMode::RowSort), ResultMode::ValueWise) => {
+            let mut chunks: Vec<_> = rows.chunks(columns.max(1)).map(<[_]>::to_vec).collect();
+            chunks.sort_by_cached_key(|chunk| {
+                chunk
+                    .iter()
+                    .map(|v| normalized(v))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            });
+            rows = chunks.concat();
+        }
+        (Some(SortMode::ValueSort), _)
+            if rows.iter().all(|row| row.split_whitespace().count() == 1) =>
+        {
+            rows.sort_by_key(|row| row.trim())
+        }
+        _ => {}
+    }
+    let rows: Vec<&str> = names.into_iter().chain(rows).collect();
+    let aligned = !hashed
+        && result_mode == ResultMode::RowWise
+        && columns > 1
+        && rows
+            .iter()
+            .all(|row| row.split_whitespace().count() == columns);
+    let rows: Vec<Vec<&str>> = rows
+        .into_iter()
+        .map(|row| match aligned {
+            true => row.split_whitespace().collect(),
+            false => vec![row],
+        })
+        .collect();
+    let mut widths = vec![];
+    for row in &rows {
+        for (i, value) in row.iter().enumerate() {
+            let len = value.chars().count();
+            match widths.get_mut(i) {
+                Some(width) => *width = len.max(*width),
+                None => widths.push(len),
+            }
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let mut line = String::new();
+            for (i, value) in row.iter().enumerate() {
+                if i > 0 {
+                    line.push(' ');
+                }
+                match widths.get(i).filter(|_| i + 1 < row.len()) {
+                    Some(width) => line += &format!("{value:width$}"),
+                    None => line += value,
+                }
+            }
+            line
+        })
+        .collect()
+}
+
 /// Parse a sqllogictest file and link all included scripts together.
 pub fn parse_file(filename: impl AsRef<Path>) -> Result<Vec<Record>, ParseError> {
+    parse_file_with_options(filename, ParseOptions::default())
+}
+
+/// Parse a sqllogictest file with the options, which apply to included files too, and link all
+/// included scripts together.
+pub fn parse_file_with_options(
+    filename: impl AsRef<Path>,
+    options: ParseOptions,
+) -> Result<Vec<Record>, ParseError> {
     let filename = filename.as_ref().to_str().unwrap();
-    parse_file_inner(Location::new(filename, 0))
+    parse_file_inner(Location::new(filename, 0), &options)
 }
 
-fn parse_file_inner(loc: Location) -> Result<Vec<Record>, ParseError> {
+fn parse_file_inner(loc: Location, options: &ParseOptions) -> Result<Vec<Record>, ParseError> {
     let path = Path::new(loc.file());
     if !path.exists() {
         return Err(ParseErrorKind::FileNotFound.at(loc.clone()));
     }
     let script = std::fs::read_to_string(path).unwrap();
     let mut records = vec![];
-    for rec in parse_inner(&loc, &script)? {
+    for rec in parse_inner(&loc, &script, options)? {
         if let Record::Include { filename, loc } = rec {
             let complete_filename = {
                 let mut path_buf = path.to_path_buf();
@@ -386,13 +2170,23 @@ fn parse_file_inner(loc: Location) -> Result<Vec<Record>, ParseError> {
                 .map_err(|e| InvalidIncludeFile(format!("{:?}", e)).at(loc.clone()))?
                 .filter_map(Result::ok)
             {
+                if loc.is_in(&included_file) {
+                    return Err(ParseErrorKind::IncludeCycle(
+                        included_file.to_string_lossy().to_string(),
+                    )
+                    .at(loc));
+                }
                 let included_file = included_file.as_os_str().to_string_lossy().to_string();
 
-                records.push(Record::Control(Control::BeginInclude(
-                    included_file.clone(),
-                )));
-                records.extend(parse_file_inner(loc.include(&included_file))?);
-                records.push(Record::Control(Control::EndInclude(included_file)));
+                records.push(Record::Control {
+                    loc: loc.clone(),
+                    control: Control::BeginInclude(included_file.clone()),
+                });
+                records.extend(parse_file_inner(loc.include(&included_file), options)?);
+                records.push(Record::Control {
+                    loc: loc.clone(),
+                    control: Control::EndInclude(included_file),
+                });
             }
         } else {
             records.push(rec);
@@ -403,11 +2197,912 @@ fn parse_file_inner(loc: Location) -> Result<Vec<Record>, ParseError> {
 
 #[cfg(test)]
 mod tests {
-    use crate::parse_file;
+    use std::time::Duration;
+
+    use crate::{
+        format_script, parse, parse_file, parse_with_options, unparse, validate, Condition,
+        Control, Dialect, ExpectedError, LetValue, Location, ParseErrorKind, ParseMode,
+        ParseOptions, Record, ResultMode, ResultTerminator, Retry, SortMode,
+    };
 
     #[test]
     fn test_include_glob() {
         let records = parse_file("../examples/include/include_1.slt").unwrap();
         assert_eq!(12, records.len());
     }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.slt"), "include b.slt\n").unwrap();
+        std::fs::write(
+            dir.path().join("b.slt"),
+            "statement ok\nselect 1\n\ninclude a.slt\n",
+        )
+        .unwrap();
+        let err = parse_file(dir.path().join("a.slt")).unwrap_err();
+        assert!(matches!(err.kind(), ParseErrorKind::IncludeCycle(_)));
+        assert_eq!(err.location().line(), 4);
+    }
+
+    #[test]
+    fn test_results_loc() {
+        let script = "\
+query I
+select 1
+union all
+select 2
+----
+1
+2
+
+query I
+select 1
+";
+        let records = parse(script).unwrap();
+        let locs: Vec<_> = records
+            .iter()
+            .map(|record| match record {
+                Record::Query { results_loc, .. } => results_loc.as_ref().map(|loc| loc.line()),
+                _ => panic!("unexpected record: {record:?}"),
+            })
+            .collect();
+        assert_eq!(locs, [Some(6), None]);
+        let Record::Query { results_loc, .. } = &records[0] else {
+            unreachable!()
+        };
+        let loc = results_loc.as_ref().unwrap().offset(1, 3);
+        assert_eq!(loc.to_string(), "<unknown>:7:3");
+    }
+
+    #[test]
+    fn test_loop() {
+        let script = "\
+loop i 0 2
+loop j 1 3
+statement ok
+insert into t values (${i}, $j, $jj)
+endloop
+endloop
+";
+        let records = parse(script).unwrap();
+        let sqls: Vec<_> = records
+            .iter()
+            .map(|record| match record {
+                Record::Statement { sql, loc, .. } => {
+                    assert_eq!(loc.line(), 3);
+                    sql.as_str()
+                }
+                _ => panic!("unexpected record: {record:?}"),
+            })
+            .collect();
+        assert_eq!(
+            sqls,
+            [
+                "insert into t values (0, 1, $jj)",
+                "insert into t values (0, 2, $jj)",
+                "insert into t values (1, 1, $jj)",
+                "insert into t values (1, 2, $jj)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_foreach() {
+        let script = "\
+foreach type int2 int8
+query I
+select 1::${type}
+----
+1
+endloop
+";
+        let records = parse(script).unwrap();
+        let sqls: Vec<_> = records
+            .iter()
+            .map(|record| match record {
+                Record::Query { sql, .. } => sql.as_str(),
+                _ => panic!("unexpected record: {record:?}"),
+            })
+            .collect();
+        assert_eq!(sqls, ["select 1::int2", "select 1::int8"]);
+    }
+
+    #[test]
+    fn test_retry() {
+        let script = "\
+retry 3 backoff 1s
+query I
+select 1
+----
+1
+
+statement ok
+select 1
+";
+        let records = parse(script).unwrap();
+        let retries: Vec<_> = records
+            .iter()
+            .map(|record| match record {
+                Record::Statement { retry, .. } | Record::Query { retry, .. } => *retry,
+                _ => panic!("unexpected record: {record:?}"),
+            })
+            .collect();
+        assert_eq!(
+            retries,
+            [
+                Some(Retry {
+                    attempts: 3,
+                    backoff: Duration::from_secs(1)
+                }),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_xfail() {
+        let script = "\
+# xfail: https://github.com/org/db/issues/123
+query I
+select 1
+----
+2
+
+# not a known failure
+statement ok
+select 1
+
+# xfail: issue-1
+skipif sqlite
+statement ok
+select 1
+
+# xfail: not for the statement after other records
+control sortmode rowsort
+
+statement count 2
+insert into t values (1), (2)
+";
+        let records = parse(script).unwrap();
+        let xfails: Vec<_> = records
+            .iter()
+            .filter_map(|record| match record {
+                Record::Statement { xfail, .. } | Record::Query { xfail, .. } => {
+                    Some(xfail.as_deref())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            xfails,
+            [
+                Some("https://github.com/org/db/issues/123"),
+                None,
+                Some("issue-1"),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_result_terminator() {
+        let script = "\
+query T
+select 'a b'
+----
+a
+
+b
+----
+
+control result_terminator blank_line
+
+query T
+select 'c'
+----
+c
+";
+        let options = ParseOptions {
+            result_terminator: ResultTerminator::Separator,
+            ..Default::default()
+        };
+        let records = parse_with_options(script, options).unwrap();
+        let results: Vec<_> = records
+            .iter()
+            .map(|record| match record {
+                Record::Query {
+                    expected_results, ..
+                } => expected_results.as_str(),
+                _ => panic!("unexpected record: {record:?}"),
+            })
+            .collect();
+        assert_eq!(results, ["a\n\nb\n", "c\n"]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let script = "\
+query rowsort
+select 1
+
+let $x = 1
+
+statement ok
+insert into t values (${x}, ${y})
+
+statement ok
+insert into t values ($x, $z, $1, $q$text$q$)
+
+control substitution off
+
+query I
+select '${y}'
+";
+        let errors: Vec<_> = validate(&parse(script).unwrap())
+            .into_iter()
+            .map(|e| (e.kind(), e.location().line()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (ParseErrorKind::InvalidType("rowsort".to_string()), 1),
+                (ParseErrorKind::UndefinedVariable("y".to_string()), 6),
+                (ParseErrorKind::UndefinedVariable("z".to_string()), 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let records = parse("control shuffle on\ncontrol shuffle off\n").unwrap();
+        assert_eq!(
+            records,
+            [
+                Record::Control {
+                    loc: Location::new("<unknown>", 1),
+                    control: Control::Shuffle(true),
+                },
+                Record::Control {
+                    loc: Location::new("<unknown>", 2),
+                    control: Control::Shuffle(false),
+                },
+            ]
+        );
+        assert!(parse("control shuffle maybe\n").is_err());
+    }
+
+    #[test]
+    fn test_result_mode() {
+        let records = parse("control resultmode valuewise\n").unwrap();
+        assert_eq!(
+            records,
+            [Record::Control {
+                loc: Location::new("<unknown>", 1),
+                control: Control::ResultMode(ResultMode::ValueWise),
+            }]
+        );
+        let records = parse("query II nosort label-x valuewise\nselect 1, 2\n").unwrap();
+        match &records[..] {
+            [Record::Query {
+                sort_mode,
+                label,
+                result_mode,
+                ..
+            }] => {
+                assert_eq!(*sort_mode, Some(SortMode::NoSort));
+                assert_eq!(label.as_deref(), Some("label-x"));
+                assert_eq!(*result_mode, Some(ResultMode::ValueWise));
+            }
+            _ => panic!("unexpected records: {records:?}"),
+        }
+        // The result mode may also stand alone, or come before the sort mode.
+        for (script, expected_sort_mode) in [
+            ("query I valuewise\nselect 1, 2\n----\n1\n2\n", None),
+            (
+                "query I valuewise rowsort\nselect 1, 2\n----\n1\n2\n",
+                Some(SortMode::RowSort),
+            ),
+        ] {
+            let records = parse(script).unwrap();
+            match &records[..] {
+                [Record::Query {
+                    sort_mode,
+                    result_mode,
+                    ..
+                }] => {
+                    assert_eq!(*sort_mode, expected_sort_mode);
+                    assert_eq!(*result_mode, Some(ResultMode::ValueWise));
+                }
+                _ => panic!("unexpected records: {records:?}"),
+            }
+            let unparsed = unparse(&records);
+            assert_eq!(parse(&unparsed).unwrap(), records);
+        }
+        assert_eq!(
+            unparse(&parse("query I valuewise\nselect 1, 2\n----\n1\n2\n").unwrap()),
+            "query I valuewise\nselect 1, 2\n----\n1\n2\n"
+        );
+        let err = parse("control resultmode columnwise\n").unwrap_err();
+        assert_eq!(
+            err.kind(),
+            ParseErrorKind::InvalidResultMode("columnwise".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unparse() {
+        let options = ParseOptions {
+            keep_trivia: true,
+            // Registered in `examples/custom_directive`.
+            directives: vec!["flush".to_string()],
+            ..Default::default()
+        };
+        for path in glob::glob("../examples/**/*.slt").unwrap() {
+            let script = std::fs::read_to_string(path.unwrap()).unwrap();
+            let records = parse_with_options(&script, options.clone()).unwrap();
+            assert_eq!(unparse(&records), script);
+        }
+
+        let script = "\
+# comment
+
+# xfail: issue-123
+skipif mysql
+retry 3 backoff 1s
+statement error regex: ^ERROR
+select 1 / 0
+
+
+query error
+select x
+----
+multi-line
+message
+
+let $id
+select 1
+
+# xfail: stray
+control sortmode rowsort
+
+control result_terminator separator
+
+query IT rowsort label-a valuewise
+select 1, 'a'
+----
+1
+a
+----
+halt
+
+statement count 1
+insert into t values (1)
+";
+        let records = parse_with_options(script, options.clone()).unwrap();
+        assert_eq!(unparse(&records), script);
+        // The trivia is not kept by default, and neither are the records after `halt`.
+        assert_eq!(parse(script).unwrap().len(), 6);
+
+        // Known failures before the conditions, and ones which no statement or query follows.
+        for script in [
+            "# xfail: a\nskipif x\nstatement ok\nselect 1\n",
+            "# xfail: stray\ncontrol sortmode rowsort\n",
+            "# xfail: a\n# xfail: b\nstatement ok\nselect 1\n",
+            "statement ok\nselect 1\n\n# xfail: at the end\n",
+        ] {
+            let records = parse_with_options(script, options.clone()).unwrap();
+            assert_eq!(unparse(&records), script);
+        }
+    }
+
+    #[test]
+    fn test_colnames() {
+        let script = "query IT rowsort label-a colnames\nselect 1 as a, 'x' as b\n----\na b\n1 x\n";
+        let records = parse(script).unwrap();
+        assert!(matches!(
+            &records[..],
+            [Record::Query {
+                sort_mode: Some(SortMode::RowSort),
+                label: Some(label),
+                colnames: true,
+                ..
+            }] if label == "label-a"
+        ));
+        assert_eq!(unparse(&records), script);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let script = "control normalize   id=[0-9]+   id=<id> here\n";
+        let records = parse(script).unwrap();
+        let [Record::Control {
+            control: Control::Normalize(normalizer),
+            ..
+        }] = &records[..]
+        else {
+            panic!("unexpected records: {records:?}");
+        };
+        assert_eq!(normalizer.apply("Seq Scan id=42"), "Seq Scan id=<id> here");
+        assert_eq!(
+            unparse(&records),
+            "control normalize id=[0-9]+ id=<id> here\n"
+        );
+
+        // The replacement may be empty, and refer to groups.
+        let records =
+            parse("control normalize \\(cost=[^)]*\\)\ncontrol normalize (a)(b) $2$1\n").unwrap();
+        let [Record::Control {
+            control: Control::Normalize(a),
+            ..
+        }, Record::Control {
+            control: Control::Normalize(b),
+            ..
+        }] = &records[..]
+        else {
+            panic!("unexpected records: {records:?}");
+        };
+        assert_eq!(a.apply("Scan (cost=0.00..1.01)"), "Scan ");
+        assert_eq!(b.apply("ab"), "ba");
+
+        let err = parse("control normalize (\n").unwrap_err();
+        assert_eq!(
+            err.kind(),
+            ParseErrorKind::InvalidNormalizerRegex("(".to_string())
+        );
+    }
+
+    #[test]
+    fn test_float_tolerance() {
+        let script =
+            "query IR rowsort tolerance=1e-6 colnames\nselect 1, 0.1 + 0.2\n----\na b\n1 0.3\n";
+        let records = parse(script).unwrap();
+        let [Record::Query {
+            sort_mode: Some(SortMode::RowSort),
+            float_tolerance: Some(tolerance),
+            colnames: true,
+            ..
+        }] = &records[..]
+        else {
+            panic!("unexpected records: {records:?}");
+        };
+        assert_eq!(tolerance.value(), 1e-6);
+        // The tolerance is written as it was.
+        assert_eq!(unparse(&records), script);
+
+        let err = parse("query R tolerance=-1\nselect 1.0\n").unwrap_err();
+        assert_eq!(
+            err.kind(),
+            ParseErrorKind::InvalidFloatTolerance("-1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_statement_count() {
+        let records = parse("statement ok 2\nupdate t set v = 1\n").unwrap();
+        assert!(matches!(
+            &records[..],
+            [Record::Statement {
+                error: false,
+                expected_count: Some(2),
+                ..
+            }]
+        ));
+        // It is written back as `statement count`.
+        assert_eq!(unparse(&records), "statement count 2\nupdate t set v = 1\n");
+    }
+
+    #[test]
+    fn test_custom_directive() {
+        let script = "\
+skipif mysql
+barrier 3
+
+flush
+";
+        let err = parse(script).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            ParseErrorKind::InvalidLine("barrier 3".to_string())
+        );
+
+        let options = ParseOptions {
+            directives: vec!["barrier".to_string(), "flush".to_string()],
+            ..Default::default()
+        };
+        let records = parse_with_options(script, options).unwrap();
+        let directives: Vec<_> = records
+            .iter()
+            .map(|record| match record {
+                Record::Custom {
+                    conditions,
+                    name,
+                    text,
+                    ..
+                } => (conditions.len(), name.as_str(), text.as_str()),
+                _ => panic!("unexpected record: {record:?}"),
+            })
+            .collect();
+        assert_eq!(
+            directives,
+            [(1, "barrier", "barrier 3"), (0, "flush", "flush")]
+        );
+    }
+
+    #[test]
+    fn test_lenient() {
+        let script = "\
+statement ok
+select 1
+
+skipif mysql
+statement okay
+select 2
+select 3
+
+flush
+
+query I nosort
+select 4
+----
+4
+";
+        let options = ParseOptions {
+            mode: ParseMode::Lenient,
+            ..Default::default()
+        };
+        let records = parse_with_options(script, options).unwrap();
+        let invalid: Vec<_> = records
+            .iter()
+            .filter_map(|record| match record {
+                Record::Invalid { loc, text, error } => {
+                    Some((loc.line(), text.as_str(), error.kind()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            invalid,
+            [
+                (
+                    5,
+                    "statement okay\nselect 2\nselect 3",
+                    ParseErrorKind::InvalidLine("statement okay".to_string())
+                ),
+                (9, "flush", ParseErrorKind::InvalidLine("flush".to_string())),
+            ]
+        );
+        assert_eq!(records.len(), 4);
+        // The conditions of the skipped record do not apply to the next one.
+        assert!(matches!(
+            records.last().unwrap(),
+            Record::Query { conditions, .. } if conditions.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_strict() {
+        let options = ParseOptions {
+            mode: ParseMode::Strict,
+            ..Default::default()
+        };
+        let err = parse_with_options("statement ok\nselect 1 \n", options.clone()).unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::TrailingWhitespace);
+        assert_eq!((err.location().line(), err.location().column()), (2, 9));
+        let err = parse_with_options("query II\nselect 1, 2\n----\n1 \t2\n", options.clone())
+            .unwrap_err();
+        assert_eq!(err.kind(), ParseErrorKind::MixedWhitespace);
+        assert_eq!((err.location().line(), err.location().column()), (4, 2));
+        parse_with_options("query II\nselect 1, 2\n----\n1\t2\n", options).unwrap();
+    }
+
+    #[test]
+    fn test_cockroach() {
+        let script = "\
+# LogicTest: local
+
+onlyif config local # 12345
+query IT colnames,partialsort(1,2)
+select 1 as a, 'x' as b
+----
+a  b
+1  x
+
+statement error pgcode 42P01 relation \"t\" does not exist
+select * from t
+
+query error division by (integer )?zero
+select 1 / 0
+";
+        let options = ParseOptions {
+            dialect: Dialect::Cockroach,
+            ..Default::default()
+        };
+        let records = parse_with_options(script, options.clone()).unwrap();
+        let [Record::Query {
+            conditions,
+            sort_mode,
+            colnames,
+            expected_results,
+            ..
+        }, Record::Statement {
+            expected_error: Some(ExpectedError::SqlState(code)),
+            ..
+        }, Record::Query {
+            expected_error: Some(ExpectedError::Regex(regex)),
+            ..
+        }] = &records[..]
+        else {
+            panic!("unexpected records: {records:?}");
+        };
+        assert_eq!(
+            conditions,
+            &[Condition::OnlyIf {
+                engine_name: "config local".to_string()
+            }]
+        );
+        assert_eq!(*sort_mode, Some(SortMode::RowSort));
+        assert!(colnames);
+        assert_eq!(expected_results, "a  b\n1  x\n");
+        assert_eq!(code, "42P01");
+        assert!(regex.is_match("division by zero"));
+
+        let err = parse_with_options("query I retry\nselect 1\n", options).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            ParseErrorKind::InvalidQueryOption("retry".into())
+        );
+    }
+
+    #[test]
+    fn test_duckdb() {
+        let script = "\
+require parquet
+
+load __TEST_DIR__/test.db readonly
+
+restart
+
+load
+";
+        let options = ParseOptions {
+            dialect: Dialect::DuckDb,
+            ..Default::default()
+        };
+        let records = parse_with_options(script, options.clone()).unwrap();
+        assert!(matches!(
+            &records[..],
+            [
+                Record::Require { capability, .. },
+                Record::Load {
+                    path: Some(path),
+                    readonly: true,
+                    ..
+                },
+                Record::Restart { .. },
+                Record::Load {
+                    path: None,
+                    readonly: false,
+                    ..
+                },
+            ] if capability == "parquet" && path == "__TEST_DIR__/test.db"
+        ));
+        assert_eq!(unparse(&records), script.replace("\n\n", "\n"));
+
+        // They are not directives in other dialects.
+        parse("restart\n").unwrap_err();
+    }
+
+    #[test]
+    fn test_let() {
+        let script = "\
+let $v = a b
+
+let $id
+select id
+from t
+";
+        let records = parse(script).unwrap();
+        let values: Vec<_> = records
+            .iter()
+            .map(|record| match record {
+                Record::Let { name, value, .. } => (name.as_str(), value.clone()),
+                _ => panic!("unexpected record: {record:?}"),
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("v", LetValue::Literal("a b".to_string())),
+                ("id", LetValue::Query("select id\nfrom t".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expected_error() {
+        let script = "\
+statement error division by zero
+select 1 / 0
+
+statement error
+select 1 / 0
+
+query error
+select * from t
+----
+relation \"t\"
+does not exist
+
+statement error regex: ^ERROR:.*denied
+drop table t
+
+statement error sqlstate 23505
+insert into t values (1)
+";
+        let records = parse(script).unwrap();
+        let errors: Vec<_> = records
+            .iter()
+            .map(|record| match record {
+                Record::Statement {
+                    error,
+                    expected_error,
+                    ..
+                }
+                | Record::Query {
+                    error,
+                    expected_error,
+                    ..
+                } => (*error, expected_error.as_ref().map(|e| e.to_string())),
+                _ => panic!("unexpected record: {record:?}"),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (true, Some("division by zero".to_string())),
+                (true, None),
+                (true, Some("relation \"t\"\ndoes not exist".to_string())),
+                (true, Some("regex: ^ERROR:.*denied".to_string())),
+                (true, Some("sqlstate 23505".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_script() {
+        // Empty lines at the start are removed.
+        let script = "\n\nstatement  ok  \n\
+create table t(v int, s text)
+
+# A comment  \n\
+skipif  postgres
+query IT  rowsort label-A
+select  *  from t
+----
+10 b
+2  a
+3 long
+
+
+statement  error Table T  exists
+create table t(v int)
+
+control  sortmode  rowsort
+
+query I nosort valuewise
+select v, v from t
+----
+2
+2
+10
+10
+
+query I
+select v from t
+----
+3
+1
+2
+";
+        let formatted = format_script(script, &ParseOptions::default(), ResultMode::RowWise);
+        assert_eq!(
+            formatted.unwrap(),
+            "\
+statement ok
+create table t(v int, s text)
+
+# A comment
+skipif postgres
+query IT rowsort label-A
+select  *  from t
+----
+10 b
+2  a
+3  long
+
+statement error Table T  exists
+create table t(v int)
+
+control sortmode rowsort
+
+query I nosort valuewise
+select v, v from t
+----
+2
+2
+10
+10
+
+query I
+select v from t
+----
+1
+2
+3
+"
+        );
+
+        let formatted = format_script(
+            "statement maybe\nselect 1\n",
+            &ParseOptions::default(),
+            ResultMode::RowWise,
+        );
+        assert!(formatted.is_err());
+
+        // Only the directives spelled as the parser does are formatted, so that a line which is
+        // not one is kept as it is in lenient mode.
+        let script = "Statement  ok\nselect 1\n";
+        assert!(format_script(script, &ParseOptions::default(), ResultMode::RowWise).is_err());
+        let options = ParseOptions {
+            mode: ParseMode::Lenient,
+            ..Default::default()
+        };
+        let formatted = format_script(script, &options, ResultMode::RowWise);
+        assert_eq!(formatted.unwrap(), script);
+
+        // The values of rows which contain spaces are not known, so they are not aligned, and
+        // the rows are sorted as the runner compares them.
+        let script = "\
+query TT rowsort
+select 'a b', 'c'
+----
+x  y
+a b c
+
+query T nosort
+select 'a  b'
+----
+a  b
+c
+";
+        let formatted = format_script(script, &ParseOptions::default(), ResultMode::RowWise);
+        assert_eq!(
+            formatted.unwrap(),
+            "\
+query TT rowsort
+select 'a b', 'c'
+----
+a b c
+x  y
+
+query T nosort
+select 'a  b'
+----
+a  b
+c
+"
+        );
+
+        // A directive without its kind is an error, not a panic.
+        for (script, line) in [
+            ("statement ok\nselect 1\n\nstatement\n", 4),
+            ("query\nselect 1\n", 1),
+        ] {
+            let err =
+                format_script(script, &ParseOptions::default(), ResultMode::RowWise).unwrap_err();
+            assert!(matches!(err.kind(), ParseErrorKind::InvalidLine(_)));
+            assert_eq!(err.location().line(), line);
+        }
+    }
 }
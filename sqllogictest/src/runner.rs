@@ -1,19 +1,71 @@
 //! Sqllogictest runner.
 
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::vec;
 
 use async_trait::async_trait;
 use futures::executor::block_on;
+use futures::future::BoxFuture;
+use futures::lock::Mutex;
 use futures::{stream, Future, StreamExt};
-use itertools::Itertools;
+use itertools::{EitherOrBoth, Itertools};
 use tempfile::{tempdir, TempDir};
 
 use crate::parser::*;
 
+/// The output of a SQL command run by a database.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DBOutput {
+    /// The rows returned by a query, with the names and the types of the columns. The values are
+    /// formatted by the database, with `NULL`s written consistently, e.g. as `NULL`, and empty
+    /// strings as `(empty)`. They are joined, sorted, hashed and compared by the runner.
+    Rows {
+        /// The names of the columns, checked against the first line of the expected results of
+        /// queries with `colnames`, e.g. `query II rowsort colnames`, which is skipped if they
+        /// are not known. Empty if they are not known.
+        names: Vec<String>,
+        /// The types of the columns, checked against the type string of the query if enabled by
+        /// [`Runner::enable_type_check`]. Empty if they are not known.
+        types: Vec<ColumnType>,
+        rows: Vec<Vec<String>>,
+    },
+    /// A statement which returns no rows has completed, and affected the number of rows, which
+    /// is checked by `statement count 3`. `None` if the database does not report it.
+    StatementComplete(Option<u64>),
+}
+
+impl DBOutput {
+    /// Returns the rows with their values separated by spaces, or no rows for a statement.
+    fn lines(&self) -> Vec<String> {
+        match self {
+            DBOutput::Rows { rows, .. } => rows.iter().map(|row| row.join(" ")).collect(),
+            DBOutput::StatementComplete(_) => vec![],
+        }
+    }
+
+    /// Returns the number of bytes of the values, with a separator after each of them.
+    fn byte_len(&self) -> usize {
+        match self {
+            DBOutput::Rows { rows, .. } => rows.iter().flatten().map(|v| v.len() + 1).sum(),
+            DBOutput::StatementComplete(_) => 0,
+        }
+    }
+}
+
+impl Display for DBOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DBOutput::Rows { .. } => self.lines().iter().try_for_each(|l| writeln!(f, "{l}")),
+            DBOutput::StatementComplete(Some(count)) => writeln!(f, "{count} rows affected"),
+            DBOutput::StatementComplete(None) => writeln!(f, "statement complete"),
+        }
+    }
+}
+
 /// The async database to be tested.
 #[async_trait]
 pub trait AsyncDB: Send {
@@ -21,13 +73,75 @@ pub trait AsyncDB: Send {
     type Error: std::error::Error + Send + Sync + 'static;
 
     /// Async run a SQL query and return the output.
-    async fn run(&mut self, sql: &str) -> Result<String, Self::Error>;
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error>;
+
+    /// Runs the statements in order, and returns the result of each. All of them are run, even
+    /// if some fail.
+    ///
+    /// The default implementation calls [`AsyncDB::run`] for each statement. Databases may send
+    /// them together instead, e.g. pipelined in one round trip, as long as each of them behaves
+    /// as if it is run alone.
+    async fn run_batch(&mut self, sqls: &[String]) -> Vec<Result<DBOutput, Self::Error>> {
+        let mut results = Vec::with_capacity(sqls.len());
+        for sql in sqls {
+            results.push(self.run(sql).await);
+        }
+        results
+    }
+
+    /// Runs the SQL on a reference database, e.g. another database to compare with, and returns
+    /// its result, or `None` if there is none, which is the default. The results of queries are
+    /// then compared with those of the reference database instead of the expected results, after
+    /// both are normalized and sorted, and its errors are checked against the expected errors
+    /// like those of [`AsyncDB::run`].
+    async fn run_reference(&mut self, _sql: &str) -> Option<Result<DBOutput, Self::Error>> {
+        None
+    }
 
     /// Engine name of current database.
     fn engine_name(&self) -> &str {
         ""
     }
 
+    /// Returns whether current database has the capability, e.g. `arrays`, `decimals`,
+    /// `transactions` or `mvcc`. Records with a `requires` condition on a capability which the
+    /// database does not have are skipped.
+    fn has_capability(&self, _capability: &str) -> bool {
+        false
+    }
+
+    /// Rounds floats in the output to `precision` significant digits, as requested by a
+    /// `control float_precision` record, or keeps their full precision with `None`. Ignored by
+    /// default.
+    fn set_float_precision(&mut self, _precision: Option<usize>) {}
+
+    /// Runs the following SQL with `protocol`, as requested by a `control protocol` record.
+    /// Ignored by databases which support only one protocol.
+    fn set_protocol(&mut self, _protocol: Protocol) {}
+
+    /// Returns the SQLSTATE of the error, e.g. `23505`, or `None` if it has none. Checked by
+    /// `statement error sqlstate 23505`, which is more stable than the message.
+    fn error_code(&self, _err: &Self::Error) -> Option<String> {
+        None
+    }
+
+    /// Opens the database file at `path`, or a new in-memory database if it is `None`, instead
+    /// of the current one, as requested by a [`Record::Load`]. Returns `None` if the database
+    /// can not be loaded, which fails the record.
+    async fn load(
+        &mut self,
+        _path: Option<&str>,
+        _readonly: bool,
+    ) -> Option<Result<(), Self::Error>> {
+        None
+    }
+
+    /// Closes and reopens the database, as requested by a [`Record::Restart`]. Returns `None`
+    /// if the database can not be restarted, which fails the record.
+    async fn restart(&mut self) -> Option<Result<(), Self::Error>> {
+        None
+    }
+
     /// [`Runner`] calls this function to perform sleep.
     ///
     /// The default implementation is `std::thread::sleep`, which is universial to any async runtime
@@ -44,12 +158,43 @@ pub trait DB: Send {
     type Error: std::error::Error + Send + Sync + 'static;
 
     /// Run a SQL query and return the output.
-    fn run(&mut self, sql: &str) -> Result<String, Self::Error>;
+    fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error>;
+
+    /// Runs the SQL on a reference database. See [`AsyncDB::run_reference`].
+    fn run_reference(&mut self, _sql: &str) -> Option<Result<DBOutput, Self::Error>> {
+        None
+    }
 
     /// Engine name of current database.
     fn engine_name(&self) -> &str {
         ""
     }
+
+    /// Returns whether current database has the capability. See [`AsyncDB::has_capability`].
+    fn has_capability(&self, _capability: &str) -> bool {
+        false
+    }
+
+    /// Rounds floats in the output. See [`AsyncDB::set_float_precision`].
+    fn set_float_precision(&mut self, _precision: Option<usize>) {}
+
+    /// Runs the following SQL with `protocol`. See [`AsyncDB::set_protocol`].
+    fn set_protocol(&mut self, _protocol: Protocol) {}
+
+    /// Returns the SQLSTATE of the error. See [`AsyncDB::error_code`].
+    fn error_code(&self, _err: &Self::Error) -> Option<String> {
+        None
+    }
+
+    /// Opens another database. See [`AsyncDB::load`].
+    fn load(&mut self, _path: Option<&str>, _readonly: bool) -> Option<Result<(), Self::Error>> {
+        None
+    }
+
+    /// Closes and reopens the database. See [`AsyncDB::restart`].
+    fn restart(&mut self) -> Option<Result<(), Self::Error>> {
+        None
+    }
 }
 
 /// Compat-layer for the new AsyncDB and DB trait
@@ -60,20 +205,53 @@ where
 {
     type Error = <D as DB>::Error;
 
-    async fn run(&mut self, sql: &str) -> Result<String, Self::Error> {
+    async fn run(&mut self, sql: &str) -> Result<DBOutput, Self::Error> {
         <D as DB>::run(self, sql)
     }
 
+    async fn run_reference(&mut self, sql: &str) -> Option<Result<DBOutput, Self::Error>> {
+        <D as DB>::run_reference(self, sql)
+    }
+
     fn engine_name(&self) -> &str {
         <D as DB>::engine_name(self)
     }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        <D as DB>::has_capability(self, capability)
+    }
+
+    fn set_float_precision(&mut self, precision: Option<usize>) {
+        <D as DB>::set_float_precision(self, precision)
+    }
+
+    fn set_protocol(&mut self, protocol: Protocol) {
+        <D as DB>::set_protocol(self, protocol)
+    }
+
+    fn error_code(&self, err: &Self::Error) -> Option<String> {
+        <D as DB>::error_code(self, err)
+    }
+
+    async fn load(
+        &mut self,
+        path: Option<&str>,
+        readonly: bool,
+    ) -> Option<Result<(), Self::Error>> {
+        <D as DB>::load(self, path, readonly)
+    }
+
+    async fn restart(&mut self) -> Option<Result<(), Self::Error>> {
+        <D as DB>::restart(self)
+    }
 }
 
 /// The error type for running sqllogictest.
 #[derive(thiserror::Error, Clone)]
 #[error("{kind}\nat {loc}\n")]
 pub struct TestError {
-    kind: TestErrorKind,
+    /// Boxed to keep results small, since mismatches carry the results.
+    kind: Box<TestErrorKind>,
     loc: Location,
 }
 
@@ -93,6 +271,82 @@ impl Display for ParallelTestError {
     }
 }
 
+/// The report of the records run in a file or script by [`Runner::run_file_async`] or
+/// [`Runner::run_script_async`], e.g. to build dashboards or find slow queries. Serializable
+/// with the `serde` feature.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RunReport {
+    /// The records in the order they are run, up to the first failure. Comments, empty lines
+    /// and control records are left out.
+    pub records: Vec<RecordReport>,
+    /// The records which have no effect in the way they are combined, e.g. `control protocol
+    /// simple` after `control float_precision 6`, in the order they are run.
+    pub warnings: Vec<RecordWarning>,
+}
+
+impl RunReport {
+    /// Returns the total time taken by the records.
+    pub fn duration(&self) -> Duration {
+        self.records.iter().map(|record| record.duration).sum()
+    }
+}
+
+/// The outcome and the duration of a record. See [`RunReport`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RecordReport {
+    /// The location of the record, e.g. `test.slt:12:1`.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_display"))]
+    pub location: Location,
+    /// The time taken to run the record, including its retries.
+    pub duration: Duration,
+    pub outcome: RecordOutcome,
+    /// The error if it failed, without its location.
+    pub error: Option<String>,
+}
+
+/// A record which does not fail, but does not have its effect either. See [`RunReport::warnings`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RecordWarning {
+    /// The location of the record, e.g. `test.slt:12:1`.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_display"))]
+    pub location: Location,
+    pub message: String,
+}
+
+impl Display for RecordWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// See [`RecordReport::outcome`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum RecordOutcome {
+    Passed,
+    Failed,
+    /// Skipped by its conditions, e.g. `skipif postgres`, or after a `halt`.
+    Skipped,
+    /// A known failure, e.g. after `# xfail: issue-123`, failed as expected.
+    #[cfg_attr(feature = "serde", serde(rename = "xfail"))]
+    ExpectedFailure,
+    /// A known failure passed, so it may be fixed.
+    #[cfg_attr(feature = "serde", serde(rename = "xpass"))]
+    UnexpectedPass,
+}
+
+#[cfg(feature = "serde")]
+fn serialize_display<S: serde::Serializer>(
+    value: &impl Display,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
 impl std::fmt::Debug for TestError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self)
@@ -102,16 +356,40 @@ impl std::fmt::Debug for TestError {
 impl TestError {
     /// Returns the corresponding [`TestErrorKind`] for this error.
     pub fn kind(&self) -> TestErrorKind {
-        self.kind.clone()
+        (*self.kind).clone()
     }
 
     /// Returns the location from which the error originated.
     pub fn location(&self) -> Location {
         self.loc.clone()
     }
+
+    /// Displays the error like [`Display`], but with the diff of a
+    /// [`TestErrorKind::QueryResultMismatch`] colored with ANSI escape codes if `colorize`, e.g.
+    /// when it is printed to a terminal. See [`TestErrorKind::display`].
+    pub fn display(&self, colorize: bool) -> impl Display + '_ {
+        DisplayError {
+            error: self,
+            colorize,
+        }
+    }
+}
+
+/// See [`TestError::display`].
+struct DisplayError<'a> {
+    error: &'a TestError,
+    colorize: bool,
+}
+
+impl Display for DisplayError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let TestError { kind, loc } = self.error;
+        write!(f, "{}\nat {loc}\n", kind.display(self.colorize))
+    }
 }
 
-/// The error kind for running sqllogictest.
+/// The error kind for running sqllogictest. Its [`Display`] has no colors, see
+/// [`TestErrorKind::display`] for colored diffs.
 #[derive(thiserror::Error, Debug, Clone)]
 pub enum TestErrorKind {
     #[error("parse error: {0}")]
@@ -123,29 +401,96 @@ pub enum TestErrorKind {
         sql: String,
         err: Arc<dyn std::error::Error + Send + Sync>,
     },
+    #[error("failed to create connection {name:?}: {err}")]
+    ConnectionFail {
+        name: String,
+        err: Arc<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("no handler is registered for directive {name:?}")]
+    UnknownDirective { name: String },
+    #[error("the database does not support {name:?}")]
+    UnsupportedDirective { name: String },
+    #[error("directive failed: {err}\n[Directive] {text}")]
+    DirectiveFail {
+        text: String,
+        err: Arc<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("query is expected to fail, but actually succeed:\n[SQL] {sql}")]
+    QueryOk { sql: String },
+    #[error("expected error {expected:?}, but got {actual:?}\n[SQL] {sql}")]
+    ErrorMismatch {
+        sql: String,
+        expected: String,
+        actual: String,
+    },
     #[error("statement is expected to affect {expected} rows, but actually {actual}\n[SQL] {sql}")]
     StatementResultMismatch {
         sql: String,
         expected: u64,
-        actual: String,
+        actual: u64,
     },
+    #[error("statement count is not supported by this engine\n[SQL] {sql}")]
+    StatementCountUnsupported { sql: String },
     #[error("query failed: {err}\n[SQL] {sql}")]
     QueryFail {
         sql: String,
         err: Arc<dyn std::error::Error + Send + Sync>,
     },
-    #[error("query result mismatch:\n[SQL] {sql}\n[Diff]\n{}", difference::Changeset::new(.expected, .actual, "\n"))]
+    #[error("query is expected to return a single value for ${name}, but returned {actual:?}\n[SQL] {sql}")]
+    LetResultMismatch {
+        name: String,
+        sql: String,
+        actual: String,
+    },
+    #[error(
+        "query results differ from those of the query labeled {label:?} at {first}\n[SQL] {sql}"
+    )]
+    LabelMismatch {
+        sql: String,
+        label: String,
+        /// The location of the first query with the label.
+        first: String,
+    },
+    #[error(
+        "query result mismatch:\n[SQL] {sql}\n[Diff] {}",
+        format_diff(.expected, .actual, .expected_at.as_deref(), *.diff_style, *.max_diff_lines, false)
+    )]
     QueryResultMismatch {
         sql: String,
         expected: String,
         actual: String,
+        /// The file and line of the expected results, e.g. `test.slt:12`, shown in the header
+        /// of the diff.
+        expected_at: Option<Arc<str>>,
+        /// See [`Runner::set_diff_style`].
+        diff_style: DiffStyle,
+        /// See [`Runner::set_max_diff_lines`].
+        max_diff_lines: Option<usize>,
+    },
+    #[error("query type string {expected:?} does not match the columns {actual:?}\n[SQL] {sql}")]
+    QueryTypeMismatch {
+        sql: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("query column names {expected:?} do not match {actual:?}\n[SQL] {sql}")]
+    QueryColumnNamesMismatch {
+        sql: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("query output is {len} bytes, more than the limit of {max} bytes\n[SQL] {sql}")]
+    QueryOutputTooLarge { sql: String, len: usize, max: usize },
+    #[error("failed to rewrite the expected results: {err}")]
+    OverrideFail {
+        err: Arc<dyn std::error::Error + Send + Sync>,
     },
 }
 
 impl From<ParseError> for TestError {
     fn from(e: ParseError) -> Self {
         TestError {
-            kind: TestErrorKind::ParseError(e.kind()),
+            kind: Box::new(TestErrorKind::ParseError(e.kind())),
             loc: e.location(),
         }
     }
@@ -153,20 +498,85 @@ impl From<ParseError> for TestError {
 
 impl TestErrorKind {
     fn at(self, loc: Location) -> TestError {
-        TestError { kind: self, loc }
+        TestError {
+            kind: Box::new(self),
+            loc,
+        }
+    }
+
+    /// Displays the error like [`Display`], but with the diff of a
+    /// [`TestErrorKind::QueryResultMismatch`] colored with ANSI escape codes if `colorize`.
+    pub fn display(&self, colorize: bool) -> impl Display + '_ {
+        DisplayErrorKind {
+            kind: self,
+            colorize,
+        }
+    }
+}
+
+/// See [`TestErrorKind::display`].
+struct DisplayErrorKind<'a> {
+    kind: &'a TestErrorKind,
+    colorize: bool,
+}
+
+impl Display for DisplayErrorKind<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            TestErrorKind::QueryResultMismatch {
+                sql,
+                expected,
+                actual,
+                expected_at,
+                diff_style,
+                max_diff_lines,
+            } if self.colorize => {
+                let diff = format_diff(
+                    expected,
+                    actual,
+                    expected_at.as_deref(),
+                    *diff_style,
+                    *max_diff_lines,
+                    true,
+                );
+                write!(f, "query result mismatch:\n[SQL] {sql}\n[Diff] {diff}")
+            }
+            kind => write!(f, "{kind}"),
+        }
     }
 }
 
 /// Validator will be used by `Runner` to validate the output.
 ///
+/// It is called with the actual and the expected rows of a query, and returns whether they
+/// match. Both are normalized, with the values of each row separated by single spaces, sorted
+/// according to the sort mode, and replaced by a hash if the results are hashed.
+///
 /// # Default
 ///
 /// By default, we will use `|x, y| x == y`.
-pub type Validator = fn(&Vec<String>, &Vec<String>) -> bool;
+pub type Validator = fn(&[String], &[String]) -> bool;
 
-/// A collection of hook functions.
+/// A collection of hook functions, called by the runner around the files and records it runs,
+/// e.g. to set up fixtures, collect metrics, or snapshot the state of the database.
+/// See [`Runner::set_hook`].
 #[async_trait]
 pub trait Hook: Send {
+    /// Called before each file is run by [`Runner::run_file_async`].
+    async fn before_file(&mut self, _filename: &Path) {}
+
+    /// Called after each file is run by [`Runner::run_file_async`], with the outcome, which is
+    /// the first failure of its records if any, or the error of parsing it.
+    async fn after_file(&mut self, _filename: &Path, _result: &Result<(), TestError>) {}
+
+    /// Called before each record is run, including those skipped by their conditions, but not
+    /// those skipped after a `halt`.
+    async fn before_record(&mut self, _record: &Record) {}
+
+    /// Called after each record is run, with the outcome. A retried record is only called back
+    /// after its last attempt.
+    async fn after_record(&mut self, _record: &Record, _result: &Result<(), TestError>) {}
+
     /// Called after each statement completes.
     async fn on_stmt_complete(&mut self, _sql: &str) {}
 
@@ -174,14 +584,144 @@ pub trait Hook: Send {
     async fn on_query_complete(&mut self, _sql: &str) {}
 }
 
+/// The handler of a custom directive, e.g. `flush`, for control records specific to an engine.
+/// See [`Runner::register_directive`].
+#[async_trait]
+pub trait Directive<D>: Send {
+    /// Runs the directive on the connection which records are run on, where `text` is its
+    /// line, e.g. `barrier 3`, with variables substituted like in SQL.
+    async fn run(
+        &mut self,
+        db: &mut D,
+        text: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// Creates a connection to the database. See [`Runner::set_connection_factory`].
+type MakeConnection<D> = Arc<
+    std::sync::Mutex<
+        dyn FnMut() -> BoxFuture<'static, Result<D, Box<dyn std::error::Error + Send + Sync>>>
+            + Send,
+    >,
+>;
+
 /// Sqllogictest runner.
 pub struct Runner<D: AsyncDB> {
     db: D,
+    /// See [`Runner::set_connection_factory`].
+    make_connection: Option<MakeConnection<D>>,
+    /// The state set by the records of the current file.
+    file: FileState<D>,
+    /// The last `control float_precision`, applied to connections created after it too.
+    float_precision: Option<usize>,
+    /// See [`Runner::set_float_precision`].
+    default_float_precision: Option<usize>,
+    /// The last `control protocol`, applied to connections created after it too.
+    protocol: Option<Protocol>,
+    /// See [`Runner::set_protocol`].
+    default_protocol: Option<Protocol>,
     // validator is used for validate if the result of query equals to expected.
     validator: Validator,
     testdir: Option<TempDir>,
+    database: Option<String>,
     sort_mode: Option<SortMode>,
-    hook: Option<Box<dyn Hook>>,
+    /// See [`Runner::set_hook`]. Shared by the runners of [`Runner::run_parallel_async`].
+    hook: Option<Arc<Mutex<dyn Hook>>>,
+    /// See [`Runner::register_directive`].
+    directives: HashMap<String, Arc<Mutex<dyn Directive<D>>>>,
+    /// See [`Runner::set_parse_mode`].
+    parse_mode: ParseMode,
+    /// See [`Runner::set_dialect`].
+    dialect: Dialect,
+    /// The number of lines of the diff shown in a result mismatch.
+    max_diff_lines: Option<usize>,
+    /// See [`Runner::set_diff_style`].
+    diff_style: DiffStyle,
+    /// The maximum size of the output of a query.
+    max_output_bytes: Option<usize>,
+    /// Whether the type strings of queries are checked. See [`Runner::enable_type_check`].
+    type_check: bool,
+    /// See [`Runner::set_float_tolerance`].
+    float_tolerance: Option<f64>,
+    /// See [`Runner::add_normalizer`].
+    normalizers: Vec<Normalizer>,
+    /// See [`Runner::set_result_mode`].
+    default_result_mode: ResultMode,
+    /// The value of `__NOW__`, the time the runner was created in nanoseconds since the Unix
+    /// epoch, which identifies the run.
+    now: String,
+    /// See [`Runner::report`].
+    report: RunReport,
+    /// See [`Runner::enable_override`].
+    override_results: bool,
+    /// See [`Runner::enable_record`].
+    record_results: bool,
+    /// The expected results to rewrite. See [`Runner::write_overrides`].
+    overrides: Vec<ResultOverride>,
+}
+
+/// The state set by the records of a file, which only applies to the file it is in. It is reset
+/// at the start of each file.
+struct FileState<D> {
+    /// The hash of the results of the first query with each label, e.g. `label-xyz` in
+    /// `query I rowsort label-xyz`, and its location. The results of later queries with the label
+    /// must be the same.
+    labels: HashMap<String, (String, Location)>,
+    /// The number of included files being run.
+    include_depth: usize,
+    /// The include depth at which a [`Record::Halt`] was run. The records are skipped until the
+    /// end of the file it is in.
+    halted_depth: Option<usize>,
+    /// The variables set by [`Record::Let`].
+    variables: HashMap<String, String>,
+    /// The normalizers added by [`Control::Normalize`], applied after the normalizers of the
+    /// runner.
+    normalizers: Vec<Normalizer>,
+    /// See [`Control::ResultMode`].
+    result_mode: ResultMode,
+    /// Whether variables are substituted, or `None` if only `__TEST_DIR__`, `__DATABASE__` and
+    /// the variables set by `let` are. See [`Control::Substitution`].
+    substitution: Option<bool>,
+    /// The hash threshold, see [`Record::HashThreshold`].
+    hash_threshold: u64,
+    /// The connections created by [`Record::Connection`], by name.
+    connections: HashMap<String, D>,
+    /// The name of the connection which records are run on, or `None` for `db`.
+    connection: Option<String>,
+}
+
+impl<D> FileState<D> {
+    fn new(result_mode: ResultMode) -> Self {
+        FileState {
+            labels: HashMap::new(),
+            include_depth: 0,
+            halted_depth: None,
+            variables: HashMap::new(),
+            normalizers: vec![],
+            result_mode,
+            substitution: None,
+            hash_threshold: 0,
+            connections: HashMap::new(),
+            connection: None,
+        }
+    }
+}
+
+/// The expected results of a query which did not match, to be rewritten with its actual results
+/// by [`Runner::write_overrides`].
+#[derive(Debug, Clone)]
+struct ResultOverride {
+    /// The location of the query.
+    loc: Location,
+    /// The location of the first line of the expected results, or `None` if the query has no
+    /// `----` line, in which case it is added after the SQL.
+    results_loc: Option<Location>,
+    /// The number of lines of SQL.
+    sql_lines: usize,
+    /// The number of lines of the expected results to replace.
+    old_lines: usize,
+    /// The lines to write.
+    results: Vec<String>,
 }
 
 impl<D: AsyncDB> Runner<D> {
@@ -189,14 +729,41 @@ impl<D: AsyncDB> Runner<D> {
     pub fn new(db: D) -> Self {
         Runner {
             db,
+            make_connection: None,
+            file: FileState::new(ResultMode::RowWise),
+            float_precision: None,
+            default_float_precision: None,
+            protocol: None,
+            default_protocol: None,
             validator: |x, y| x == y,
             testdir: None,
+            database: None,
             sort_mode: None,
             hook: None,
+            directives: HashMap::new(),
+            parse_mode: ParseMode::Normal,
+            dialect: Dialect::Default,
+            max_diff_lines: None,
+            diff_style: DiffStyle::Unified,
+            max_output_bytes: None,
+            type_check: false,
+            float_tolerance: None,
+            normalizers: vec![],
+            default_result_mode: ResultMode::RowWise,
+            now: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+                .to_string(),
+            report: RunReport::default(),
+            override_results: false,
+            record_results: false,
+            overrides: vec![],
         }
     }
 
-    /// Replace the pattern `__TEST_DIR__` in SQL with a temporary directory path.
+    /// Replace the pattern `__TEST_DIR__` in SQL and expected results with a temporary directory
+    /// path.
     ///
     /// This feature is useful in those tests where data will be written to local
     /// files, e.g. `COPY`.
@@ -204,62 +771,335 @@ impl<D: AsyncDB> Runner<D> {
         self.testdir = Some(tempdir().expect("failed to create testdir"));
     }
 
+    /// Replace the pattern `__DATABASE__` in SQL and expected results with the name of the
+    /// database.
+    ///
+    /// This feature is useful when each test file runs in its own database, whose name is not
+    /// known in advance.
+    pub fn set_database(&mut self, name: impl Into<String>) {
+        self.database = Some(name.into());
+    }
+
+    /// Replaces the comparison of the actual and the expected rows of queries, e.g. to ignore
+    /// the costs in query plans, or to tolerate rows in any order. See [`Validator`].
     pub fn with_validator(&mut self, validator: Validator) {
         self.validator = validator;
     }
 
+    /// Check the type strings of queries, e.g. `query IT`, against the types of the columns
+    /// returned in [`DBOutput::Rows`], if known. The number of columns must match, and each of
+    /// `ITRB` must match the class of the column type. Other characters match any type.
+    pub fn enable_type_check(&mut self) {
+        self.type_check = true;
+    }
+
+    /// Rewrite the expected results of queries which do not match with their actual results,
+    /// instead of failing, to refresh the test files after an intentional change. The results
+    /// are written sorted and laid out as they are compared, e.g. sorted for `rowsort`, one value
+    /// per line for `valuewise`, and hashed if the expected results are or they are larger than
+    /// the hash threshold, and the comments and other records are kept as they are.
+    ///
+    /// The files are rewritten by [`Runner::run_file_async`] if it succeeds, or else by
+    /// [`Runner::write_overrides`].
+    pub fn enable_override(&mut self) {
+        self.override_results = true;
+    }
+
+    /// Fill in the expected results of queries which have none, e.g. a query without a `----`
+    /// line, with their actual results, to write the SQL of a new test first and capture the
+    /// results from the database. Unlike [`Runner::enable_override`], queries whose expected
+    /// results do not match still fail, and so does the file, which is then left as it is.
+    /// Labeled queries without results, which are only compared by label, are kept as they are.
+    ///
+    /// The files are written like with [`Runner::enable_override`].
+    pub fn enable_record(&mut self) {
+        self.record_results = true;
+    }
+
+    /// Rewrite the expected results of the queries which did not match since
+    /// [`Runner::enable_override`] or [`Runner::enable_record`] in their test files, including the included ones.
+    pub fn write_overrides(&mut self) -> std::io::Result<()> {
+        let mut overrides = std::mem::take(&mut self.overrides);
+        // A query in a loop is run several times, and only its first results are kept.
+        overrides.sort_by_key(|o| (o.loc.file().to_string(), o.loc.line()));
+        overrides.dedup_by_key(|o| (o.loc.file().to_string(), o.loc.line()));
+        for (file, overrides) in &overrides.into_iter().group_by(|o| o.loc.file().to_string()) {
+            let content = std::fs::read_to_string(&file)?;
+            let mut lines = content
+                .split_inclusive('\n')
+                .map(String::from)
+                .collect_vec();
+            // Rewrite from the end, so that the lines before are not moved.
+            for o in overrides.collect_vec().into_iter().rev() {
+                let results = o.results.iter().map(|line| format!("{line}\n"));
+                match &o.results_loc {
+                    Some(results_loc) => {
+                        let start = results_loc.line() as usize - 1;
+                        let end = (start + o.old_lines).min(lines.len());
+                        lines.splice(start..end, results);
+                    }
+                    None => {
+                        let at = (o.loc.line() as usize + o.sql_lines).min(lines.len());
+                        if let Some(last) = lines[..at].last_mut() {
+                            if !last.ends_with('\n') {
+                                last.push('\n');
+                            }
+                        }
+                        lines.splice(at..at, std::iter::once("----\n".to_string()).chain(results));
+                    }
+                }
+            }
+            std::fs::write(&file, lines.concat())?;
+        }
+        Ok(())
+    }
+
+    /// Compare the values of `R` columns, e.g. the second column of `query IR`, within the
+    /// tolerance instead of textually, so that tests pass on databases which print floats
+    /// differently. Queries may override it, e.g. `query IR tolerance=1e-6`. See
+    /// [`FloatTolerance`] for how values are compared.
+    pub fn set_float_tolerance(&mut self, tolerance: f64) {
+        self.float_tolerance = Some(tolerance);
+    }
+
+    /// Rewrite the actual and the expected results of queries with the normalizer before they
+    /// are compared, e.g. to scrub UUIDs in `EXPLAIN` output, after the normalizers added before.
+    /// Test files can add their own with `control normalize`, which apply until the end of the
+    /// file.
+    pub fn add_normalizer(&mut self, normalizer: Normalizer) {
+        self.normalizers.push(normalizer);
+    }
+
+    /// Show at most `max` lines of the diff in a [`TestErrorKind::QueryResultMismatch`],
+    /// followed by a line with the number of lines left out, so that a query returning a huge
+    /// result by mistake does not flood the output. The whole results are still diffed.
+    pub fn set_max_diff_lines(&mut self, max: usize) {
+        self.max_diff_lines = Some(max);
+    }
+
+    /// Show the expected and the actual results in the style in a
+    /// [`TestErrorKind::QueryResultMismatch`]. A unified diff by default.
+    pub fn set_diff_style(&mut self, style: DiffStyle) {
+        self.diff_style = style;
+    }
+
+    /// Fail queries whose output is larger than `max` bytes with
+    /// [`TestErrorKind::QueryOutputTooLarge`], before its rows are joined, sorted and
+    /// compared, which would take several times its size in memory.
+    pub fn set_max_output_bytes(&mut self, max: usize) {
+        self.max_output_bytes = Some(max);
+    }
+
+    /// Set how the expected results of queries are laid out, until changed by `control
+    /// resultmode` in a file. Queries may override it, e.g. `query III nosort valuewise`.
+    pub fn set_result_mode(&mut self, mode: ResultMode) {
+        self.file.result_mode = mode;
+        self.default_result_mode = mode;
+    }
+
+    /// Set the number of significant digits which the database rounds floats to, or `None` for
+    /// their full precision, until changed by `control float_precision` in a file. Set it if the
+    /// database is connected with a precision, so that it is restored after such a file.
+    pub fn set_float_precision(&mut self, precision: Option<usize>) {
+        self.apply_float_precision(precision);
+        self.default_float_precision = precision;
+    }
+
+    /// Rounds floats to the precision on the database and on the connections of `connection`
+    /// records.
+    fn apply_float_precision(&mut self, precision: Option<usize>) {
+        self.float_precision = precision;
+        self.db.set_float_precision(precision);
+        for db in self.file.connections.values_mut() {
+            db.set_float_precision(precision);
+        }
+    }
+
+    /// Set the protocol which the database runs SQL with, until changed by `control protocol` in
+    /// a file. Set it if the database supports several protocols, so that it is restored after
+    /// such a file.
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.apply_protocol(protocol);
+        self.default_protocol = Some(protocol);
+    }
+
+    /// Runs SQL with the protocol on the database and on the connections of `connection` records.
+    fn apply_protocol(&mut self, protocol: Protocol) {
+        self.protocol = Some(protocol);
+        self.db.set_protocol(protocol);
+        for db in self.file.connections.values_mut() {
+            db.set_protocol(protocol);
+        }
+    }
+
+    /// Warns about the `control float_precision` or `control protocol` record at `loc` if it
+    /// sets a float precision together with the simple protocol, where values are formatted by
+    /// the database, so floats are not rounded.
+    fn check_float_precision(&mut self, loc: Location) {
+        if self.float_precision.is_some() && self.protocol == Some(Protocol::Simple) {
+            let message = "float precision is not applied with the simple protocol".to_string();
+            tracing::warn!(%loc, message);
+            self.report.warnings.push(RecordWarning {
+                location: loc,
+                message,
+            });
+        }
+    }
+
+    /// Create the connections of `connection` records with `make_connection`, e.g. `connection
+    /// con1`, so that a test file can run SQL in several sessions. Each name is connected once,
+    /// on first use, and the connections are closed at the end of the file.
+    pub fn set_connection_factory<F, Fut>(&mut self, mut make_connection: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<D, Box<dyn std::error::Error + Send + Sync>>> + Send + 'static,
+    {
+        self.make_connection = Some(Arc::new(std::sync::Mutex::new(move || {
+            Box::pin(make_connection()) as BoxFuture<_>
+        })));
+    }
+
     /// Run a single record.
     pub async fn run_async(&mut self, record: Record) -> Result<(), TestError> {
+        self.run_with_outcome_async(record).await.map(|_| ())
+    }
+
+    /// Run a single record, and return whether it passed, was skipped, or is a known failure
+    /// which failed as expected or passed. The failure of a known failure is not returned.
+    pub async fn run_with_outcome_async(
+        &mut self,
+        record: Record,
+    ) -> Result<RecordOutcome, TestError> {
         tracing::info!(?record, "testing");
+        if self.file.halted_depth.is_some()
+            && !matches!(
+                record,
+                Record::Control {
+                    control: Control::BeginInclude(_) | Control::EndInclude(_),
+                    ..
+                }
+            )
+        {
+            return Ok(RecordOutcome::Skipped);
+        }
+        let skipped = self.is_skipped(&record);
+        let xfail = match &record {
+            Record::Statement { xfail, .. } | Record::Query { xfail, .. } => xfail.clone(),
+            _ => None,
+        };
+        match (self.run_hooked(record).await, xfail) {
+            (Ok(()), _) if skipped => Ok(RecordOutcome::Skipped),
+            (Ok(()), None) => Ok(RecordOutcome::Passed),
+            (Ok(()), Some(reason)) => {
+                tracing::warn!(reason, "known failure passed");
+                Ok(RecordOutcome::UnexpectedPass)
+            }
+            (Err(e), Some(reason)) => {
+                tracing::info!(%e, reason, "known failure failed");
+                Ok(RecordOutcome::ExpectedFailure)
+            }
+            (Err(e), None) => Err(e),
+        }
+    }
+
+    /// Runs the record, and calls back the hook around it.
+    async fn run_hooked(&mut self, record: Record) -> Result<(), TestError> {
+        if self.hook.is_none() {
+            return self.run_retrying(record).await;
+        }
+        if let Some(hook) = &self.hook {
+            hook.lock().await.before_record(&record).await;
+        }
+        // The record is kept to call back the hook with.
+        let result = self.run_retrying(record.clone()).await;
+        if let Some(hook) = &self.hook {
+            hook.lock().await.after_record(&record, &result).await;
+        }
+        result
+    }
+
+    /// Runs the record, and retries it as many times as its `retry` allows if it fails.
+    async fn run_retrying(&mut self, record: Record) -> Result<(), TestError> {
+        let retry = match &record {
+            Record::Statement { retry, .. } | Record::Query { retry, .. } => *retry,
+            _ => None,
+        };
+        let retry = match retry {
+            Some(retry) => retry,
+            None => return self.run_record(record).await,
+        };
+        let mut attempt = 0;
+        loop {
+            match self.run_record(record.clone()).await {
+                Err(e) if attempt < retry.attempts => {
+                    attempt += 1;
+                    tracing::warn!(%e, attempt, "retrying");
+                    D::sleep(retry.backoff).await;
+                }
+                ret => return ret,
+            }
+        }
+    }
+
+    async fn run_record(&mut self, record: Record) -> Result<(), TestError> {
         match record {
             Record::Statement { conditions, .. } if self.should_skip(&conditions) => {}
             Record::Statement {
                 error,
+                expected_error,
                 sql,
                 loc,
                 expected_count,
                 ..
             } => {
                 let sql = self.replace_keywords(sql);
-                let ret = self.db.run(&sql).await;
-                match ret {
-                    Ok(_) if error => return Err(TestErrorKind::StatementOk { sql }.at(loc)),
-                    Ok(count_str) => {
-                        if let Some(expected_count) = expected_count {
-                            if expected_count.to_string() != count_str {
-                                return Err(TestErrorKind::StatementResultMismatch {
-                                    sql,
-                                    expected: expected_count,
-                                    actual: count_str,
-                                }
-                                .at(loc));
-                            }
-                        }
-                    }
-                    Err(e) if !error => {
-                        return Err(TestErrorKind::StatementFail {
-                            sql,
-                            err: Arc::new(e),
-                        }
-                        .at(loc));
-                    }
-                    _ => {}
-                }
-                if let Some(hook) = &mut self.hook {
-                    hook.on_stmt_complete(&sql).await;
-                }
+                let ret = self.db().run(&sql).await;
+                let reference = self.db().run_reference(&sql).await;
+                self.check_statement(
+                    sql,
+                    error,
+                    expected_error,
+                    expected_count,
+                    loc,
+                    ret,
+                    reference,
+                )
+                .await?;
             }
             Record::Query { conditions, .. } if self.should_skip(&conditions) => {}
             Record::Query {
                 loc,
                 sql,
                 expected_results,
+                results_loc,
                 sort_mode,
+                type_string,
+                label,
+                result_mode,
+                colnames,
+                float_tolerance,
+                error,
+                expected_error,
                 ..
             } => {
+                let sql_lines = sql.lines().count();
                 let sql = self.replace_keywords(sql);
-                let output = match self.db.run(&sql).await {
+                let ret = self.db().run(&sql).await;
+                let reference = self.db().run_reference(&sql).await;
+                let output = match ret {
+                    Ok(_) if error => return Err(TestErrorKind::QueryOk { sql }.at(loc)),
                     Ok(output) => output,
+                    Err(e) if error => {
+                        self.check_error(&sql, expected_error.clone(), &e)
+                            .and_then(|()| {
+                                self.check_reference(&sql, true, expected_error, reference)
+                            })
+                            .map_err(|kind| kind.at(loc))?;
+                        if let Some(hook) = &self.hook {
+                            hook.lock().await.on_query_complete(&sql).await;
+                        }
+                        return Ok(());
+                    }
                     Err(e) => {
                         return Err(TestErrorKind::QueryFail {
                             sql,
@@ -268,65 +1108,696 @@ impl<D: AsyncDB> Runner<D> {
                         .at(loc));
                     }
                 };
-                let mut output = split_lines_and_normalize(&output);
-                let mut expected_results = split_lines_and_normalize(&expected_results);
+                let reference = match self.check_reference(&sql, false, None, reference) {
+                    Ok(reference) => reference,
+                    Err(kind) => return Err(kind.at(loc)),
+                };
+                // The results of the reference database can not be located in the test file.
+                let compared = reference.is_some();
+                let results_loc = results_loc.filter(|_| !compared);
+                if let (true, DBOutput::Rows { types, .. }) = (self.type_check, &output) {
+                    // The types are empty if they are not known by the database.
+                    if !types.is_empty() && !types_match(&type_string, types) {
+                        return Err(TestErrorKind::QueryTypeMismatch {
+                            sql,
+                            expected: type_string,
+                            actual: types.iter().map(ColumnType::to_char).collect(),
+                        }
+                        .at(loc));
+                    }
+                }
+                if let Some(max) = self.max_output_bytes {
+                    let len = reference
+                        .iter()
+                        .chain([&output])
+                        .map(DBOutput::byte_len)
+                        .max();
+                    if let Some(len) = len.filter(|&len| len > max) {
+                        return Err(TestErrorKind::QueryOutputTooLarge { sql, len, max }.at(loc));
+                    }
+                }
+                // The names are empty if they are not known by the database.
+                let names = match &output {
+                    DBOutput::Rows { names, .. } if !names.is_empty() => {
+                        Some(normalize_string(&names.join(" ")))
+                    }
+                    _ => None,
+                };
+                let normalize = |output: &DBOutput| {
+                    output
+                        .lines()
+                        .iter()
+                        .map(|line| normalize_string(line))
+                        .filter(|line| !line.is_empty())
+                        .collect_vec()
+                };
+                let mut output = normalize(&output);
+                // The index of the line of each expected row in the expected results, to locate
+                // a mismatch. Left empty if the rows can not be located.
+                let raw_results = expected_results;
+                let (mut expected_results, mut expected_lines): (Vec<_>, Vec<_>) = match &reference
+                {
+                    Some(reference) => (normalize(reference), vec![]),
+                    None => split_lines_and_number(&self.replace_keywords(raw_results.clone()))
+                        .into_iter()
+                        .unzip(),
+                };
+                // Whether the expected results are rewritten if they do not match.
+                let rewrite = !compared
+                    && (self.override_results
+                        || (self.record_results && raw_results.trim().is_empty()));
+                // The line of column names to write, and whether it differs.
+                let mut names_line = None;
+                let mut names_mismatch = false;
+                if colnames && rewrite {
+                    names_line = names.clone().or_else(|| expected_results.first().cloned());
+                }
+                if colnames && !compared && !expected_results.is_empty() {
+                    let expected = expected_results.remove(0);
+                    let line = expected_lines.remove(0);
+                    if let Some(actual) = names {
+                        if actual != expected && rewrite {
+                            names_mismatch = true;
+                        } else if actual != expected {
+                            // Point at the line of the names, rather than the query.
+                            return Err(TestErrorKind::QueryColumnNamesMismatch {
+                                sql,
+                                expected,
+                                actual,
+                            }
+                            .at(results_loc.map_or(loc, |l| l.offset(line as u32, 1))));
+                        }
+                    }
+                }
+                if !self.normalizers.is_empty() || !self.file.normalizers.is_empty() {
+                    let normalizers = self.normalizers.iter().chain(&self.file.normalizers);
+                    output = apply_normalizers(normalizers.clone(), output);
+                    expected_results = apply_normalizers(normalizers, expected_results);
+                }
+                let expected_hashed = is_hashed(&expected_results);
+                let result_mode = result_mode.unwrap_or(self.file.result_mode);
+                // The results of the reference database are rows already.
+                if result_mode == ResultMode::ValueWise && !expected_hashed && !compared {
+                    let columns = type_string.len().max(1);
+                    expected_results = group_values(&expected_results, columns);
+                    expected_lines = expected_lines.into_iter().step_by(columns).collect();
+                }
                 match sort_mode.as_ref().or(self.sort_mode.as_ref()) {
                     None | Some(SortMode::NoSort) => {}
-                    Some(SortMode::RowSort) => {
+                    Some(SortMode::RowSort) if expected_lines.is_empty() => {
                         output.sort_unstable();
                         expected_results.sort_unstable();
                     }
-                    Some(SortMode::ValueSort) => todo!("value sort"),
+                    Some(SortMode::RowSort) => {
+                        output.sort_unstable();
+                        let mut rows = expected_results
+                            .into_iter()
+                            .zip(expected_lines)
+                            .collect_vec();
+                        rows.sort_unstable();
+                        (expected_results, expected_lines) = rows.into_iter().unzip();
+                    }
+                    Some(SortMode::ValueSort) => {
+                        output = sort_values(&output);
+                        if !expected_hashed {
+                            expected_results = sort_values(&expected_results);
+                            expected_lines.clear();
+                        }
+                    }
                 };
-                if !(self.validator)(&output, &expected_results) {
+                // Queries with the same label are compared by hash, like in the original
+                // sqllogictest.
+                let label_hash = label.map(|label| (label, hash_values(&output)));
+                // Labeled queries may leave out the results, and only be compared by label.
+                let only_label = label_hash.is_some() && expected_results.is_empty() && !compared;
+                // Large results are compared by hash if the expected results are hashed, or
+                // shown as hashes in a mismatch instead of listing all values.
+                let hashed = expected_hashed
+                    || (self.file.hash_threshold > 0
+                        && count_values(&output).max(count_values(&expected_results))
+                            > self.file.hash_threshold as usize);
+                // The actual results as they are written if rewritten, before they are hashed
+                // because the expected results are large.
+                let overridden = rewrite.then(|| {
+                    if expected_hashed
+                        || (self.file.hash_threshold > 0
+                            && count_values(&output) > self.file.hash_threshold as usize)
+                    {
+                        vec![hash_values(&output)]
+                    } else if result_mode == ResultMode::ValueWise {
+                        output
+                            .iter()
+                            .flat_map(|row| row.split(' '))
+                            .map(String::from)
+                            .collect()
+                    } else {
+                        output.clone()
+                    }
+                });
+                if hashed {
+                    output = vec![hash_values(&output)];
+                    if !expected_hashed {
+                        expected_results = vec![hash_values(&expected_results)];
+                        expected_lines.clear();
+                    }
+                }
+                // Hashes are compared textually.
+                let float_tolerance = match float_tolerance {
+                    Some(tolerance) => Some(tolerance.value()),
+                    None => self.float_tolerance,
+                }
+                .filter(|_| !hashed);
+                let matched = (self.validator)(&output, &expected_results)
+                    || float_tolerance.is_some_and(|tolerance| {
+                        rows_match_within(&output, &expected_results, &type_string, tolerance)
+                    });
+                if let Some(results) =
+                    overridden.filter(|_| (!only_label && !matched) || names_mismatch)
+                {
+                    self.overrides.push(ResultOverride {
+                        loc: loc.clone(),
+                        old_lines: raw_results.lines().count(),
+                        results_loc,
+                        sql_lines,
+                        results: names_line.into_iter().chain(results).collect(),
+                    });
+                } else if !only_label && !matched {
+                    // Point at the first row which differs, rather than the query.
+                    let loc = results_loc
+                        .as_ref()
+                        .and_then(|results_loc| {
+                            locate_mismatch(
+                                results_loc,
+                                &raw_results,
+                                &expected_results,
+                                &expected_lines,
+                                &output,
+                            )
+                        })
+                        .unwrap_or(loc);
                     return Err(TestErrorKind::QueryResultMismatch {
                         sql,
                         expected: expected_results.join("\n"),
                         actual: output.join("\n"),
+                        expected_at: results_loc
+                            .map(|loc| format!("{}:{}", loc.file(), loc.line()).into()),
+                        diff_style: self.diff_style,
+                        max_diff_lines: self.max_diff_lines,
                     }
                     .at(loc));
                 }
-                if let Some(hook) = &mut self.hook {
-                    hook.on_query_complete(&sql).await;
-                }
-            }
+                if let Some((label, hash)) = label_hash {
+                    match self.file.labels.get(&label) {
+                        Some((expected, first)) if *expected != hash => {
+                            return Err(TestErrorKind::LabelMismatch {
+                                sql,
+                                label,
+                                first: first.to_string(),
+                            }
+                            .at(loc));
+                        }
+                        Some(_) => {}
+                        None => {
+                            self.file.labels.insert(label, (hash, loc));
+                        }
+                    }
+                }
+                if let Some(hook) = &self.hook {
+                    hook.lock().await.on_query_complete(&sql).await;
+                }
+            }
             Record::Sleep { duration, .. } => D::sleep(duration).await,
-            Record::Halt { .. } => {}
-            Record::Subtest { .. } => {}
+            Record::Halt { conditions, .. } if self.should_skip(&conditions) => {}
+            Record::Halt { .. } => self.file.halted_depth = Some(self.file.include_depth),
+            Record::Require { capability, .. } => {
+                if !self.db().has_capability(&capability) {
+                    tracing::info!(capability, "skipped the rest of the file");
+                    self.file.halted_depth = Some(self.file.include_depth);
+                }
+            }
+            Record::Load {
+                loc,
+                path,
+                readonly,
+            } => {
+                let path = path.map(|path| self.replace_keywords(path));
+                self.close_connections();
+                let result = self.db.load(path.as_deref(), readonly).await;
+                check_reopen("load", result).map_err(|kind| kind.at(loc))?;
+            }
+            Record::Restart { loc } => {
+                self.close_connections();
+                let result = self.db.restart().await;
+                check_reopen("restart", result).map_err(|kind| kind.at(loc))?;
+            }
+            Record::Subtest { .. } | Record::Comment { .. } | Record::Newline { .. } => {}
+            Record::Invalid { error, .. } => tracing::warn!(%error, "skipped invalid record"),
+            Record::Let {
+                name,
+                value: LetValue::Literal(value),
+                ..
+            } => {
+                let value = self.replace_keywords(value);
+                self.file.variables.insert(name, value);
+            }
+            Record::Let {
+                loc,
+                name,
+                value: LetValue::Query(sql),
+            } => {
+                let sql = self.replace_keywords(sql);
+                let output = match self.db().run(&sql).await {
+                    Ok(output) => output,
+                    Err(e) => {
+                        return Err(TestErrorKind::QueryFail {
+                            sql,
+                            err: Arc::new(e),
+                        }
+                        .at(loc))
+                    }
+                };
+                let value = match &output.lines()[..] {
+                    [value] => value.clone(),
+                    _ => {
+                        return Err(TestErrorKind::LetResultMismatch {
+                            name,
+                            sql,
+                            actual: output.to_string(),
+                        }
+                        .at(loc))
+                    }
+                };
+                self.file.variables.insert(name, value);
+            }
+            Record::Connection { loc, name } => self
+                .switch_connection(name)
+                .await
+                .map_err(|kind| kind.at(loc))?,
+            Record::HashThreshold { threshold, .. } => self.file.hash_threshold = threshold,
+            Record::Custom { conditions, .. } if self.should_skip(&conditions) => {}
+            Record::Custom {
+                loc, name, text, ..
+            } => {
+                let text = self.replace_keywords(text);
+                // Cloned, since it borrows the connection of the runner while it runs.
+                let directive = match self.directives.get(&name) {
+                    Some(directive) => directive.clone(),
+                    None => return Err(TestErrorKind::UnknownDirective { name }.at(loc)),
+                };
+                let result = directive.lock().await.run(self.db(), &text).await;
+                result.map_err(|err| {
+                    TestErrorKind::DirectiveFail {
+                        text,
+                        err: err.into(),
+                    }
+                    .at(loc)
+                })?;
+            }
             Record::Include { loc, .. } => {
                 unreachable!("include should be rewritten during link: at {}", loc)
             }
-            Record::Control(control) => match control {
+            Record::Control { loc, control } => match control {
                 Control::SortMode(sort_mode) => {
                     self.sort_mode = Some(sort_mode);
                 }
-                Control::BeginInclude(_) | Control::EndInclude(_) => {}
+                Control::FloatPrecision(precision) => {
+                    self.apply_float_precision(Some(precision));
+                    self.check_float_precision(loc);
+                }
+                Control::Protocol(protocol) => {
+                    self.apply_protocol(protocol);
+                    self.check_float_precision(loc);
+                }
+                Control::ResultMode(mode) => self.file.result_mode = mode,
+                Control::Normalize(normalizer) => self.file.normalizers.push(normalizer),
+                // Applied by the parser.
+                Control::ResultTerminator(_) => {}
+                // Records are shuffled before they are run.
+                Control::Shuffle(_) => {}
+                Control::Substitution(on) => {
+                    if on && self.testdir.is_none() {
+                        self.enable_testdir();
+                    }
+                    self.file.substitution = Some(on);
+                }
+                Control::BeginInclude(_) => self.file.include_depth += 1,
+                Control::EndInclude(_) => {
+                    self.file.include_depth -= 1;
+                    // Records after the included file which halted are run.
+                    if self.file.halted_depth > Some(self.file.include_depth) {
+                        self.file.halted_depth = None;
+                    }
+                }
             },
         }
         Ok(())
     }
 
+    /// Returns the connection which records are run on.
+    fn db(&mut self) -> &mut D {
+        match &self.file.connection {
+            Some(name) => self.file.connections.get_mut(name).unwrap(),
+            None => &mut self.db,
+        }
+    }
+
+    /// Runs the following records on the connection named `name`, which is created if it does
+    /// not exist yet.
+    async fn switch_connection(&mut self, name: String) -> Result<(), TestErrorKind> {
+        if name == "default" {
+            self.file.connection = None;
+            return Ok(());
+        }
+        if !self.file.connections.contains_key(&name) {
+            let make_connection =
+                self.make_connection
+                    .as_ref()
+                    .ok_or_else(|| TestErrorKind::ConnectionFail {
+                        name: name.clone(),
+                        err: Arc::from(Box::<dyn std::error::Error + Send + Sync>::from(
+                            "the runner has no connection factory",
+                        )),
+                    })?;
+            // The factory is only locked while the future is created.
+            let connect = (make_connection.lock().unwrap())();
+            let mut db = connect.await.map_err(|err| TestErrorKind::ConnectionFail {
+                name: name.clone(),
+                err: Arc::from(err),
+            })?;
+            if self.float_precision.is_some() {
+                db.set_float_precision(self.float_precision);
+            }
+            if let Some(protocol) = self.protocol {
+                db.set_protocol(protocol);
+            }
+            self.file.connections.insert(name.clone(), db);
+        }
+        self.file.connection = Some(name);
+        Ok(())
+    }
+
+    /// Closes the connections created by [`Record::Connection`], e.g. at the end of the file or
+    /// when the database they are connected to is replaced by [`Record::Load`] or
+    /// [`Record::Restart`], and switches back to the default one.
+    fn close_connections(&mut self) {
+        self.file.connections.clear();
+        self.file.connection = None;
+    }
+
+    /// Checks the result of a statement, and calls the hook if it is as expected.
+    #[allow(clippy::too_many_arguments)]
+    async fn check_statement(
+        &mut self,
+        sql: String,
+        error: bool,
+        expected_error: Option<ExpectedError>,
+        expected_count: Option<u64>,
+        loc: Location,
+        ret: Result<DBOutput, D::Error>,
+        reference: Option<Result<DBOutput, D::Error>>,
+    ) -> Result<(), TestError> {
+        // The statement must behave the same on the reference database, if any.
+        for ret in std::iter::once(ret).chain(reference) {
+            self.check_statement_result(&sql, error, expected_error.clone(), expected_count, ret)
+                .map_err(|kind| kind.at(loc.clone()))?;
+        }
+        if let Some(hook) = &self.hook {
+            hook.lock().await.on_stmt_complete(&sql).await;
+        }
+        Ok(())
+    }
+
+    /// Checks the result of a statement against the expected error or count, if any.
+    fn check_statement_result(
+        &self,
+        sql: &str,
+        error: bool,
+        expected_error: Option<ExpectedError>,
+        expected_count: Option<u64>,
+        ret: Result<DBOutput, D::Error>,
+    ) -> Result<(), TestErrorKind> {
+        let sql = sql.to_string();
+        match ret {
+            Ok(_) if error => return Err(TestErrorKind::StatementOk { sql }),
+            Ok(output) => {
+                if let Some(expected_count) = expected_count {
+                    // Statements returning rows, e.g. `INSERT ... RETURNING`, affect as many rows.
+                    let count = match output {
+                        DBOutput::StatementComplete(Some(count)) => count,
+                        DBOutput::StatementComplete(None) => {
+                            return Err(TestErrorKind::StatementCountUnsupported { sql });
+                        }
+                        DBOutput::Rows { rows, .. } => rows.len() as u64,
+                    };
+                    if count != expected_count {
+                        return Err(TestErrorKind::StatementResultMismatch {
+                            sql,
+                            expected: expected_count,
+                            actual: count,
+                        });
+                    }
+                }
+            }
+            Err(e) if !error => {
+                return Err(TestErrorKind::StatementFail {
+                    sql,
+                    err: Arc::new(e),
+                });
+            }
+            Err(e) => self.check_error(&sql, expected_error, &e)?,
+        }
+        Ok(())
+    }
+
+    /// Checks the result of a query on the reference database, if any, like that of the query,
+    /// and returns its output if it is expected to succeed. See [`AsyncDB::run_reference`].
+    fn check_reference(
+        &self,
+        sql: &str,
+        error: bool,
+        expected_error: Option<ExpectedError>,
+        ret: Option<Result<DBOutput, D::Error>>,
+    ) -> Result<Option<DBOutput>, TestErrorKind> {
+        match ret {
+            None => Ok(None),
+            Some(Ok(_)) if error => Err(TestErrorKind::QueryOk {
+                sql: sql.to_string(),
+            }),
+            Some(Ok(output)) => Ok(Some(output)),
+            Some(Err(e)) if error => self.check_error(sql, expected_error, &e).map(|()| None),
+            Some(Err(e)) => Err(TestErrorKind::QueryFail {
+                sql: sql.to_string(),
+                err: Arc::new(e),
+            }),
+        }
+    }
+
+    /// Checks that an expected error has the expected message or SQLSTATE, if any.
+    fn check_error(
+        &self,
+        sql: &str,
+        expected_error: Option<ExpectedError>,
+        err: &D::Error,
+    ) -> Result<(), TestErrorKind> {
+        let messages = error_messages(err);
+        let (matched, expected, actual) = match expected_error {
+            Some(ExpectedError::Substring(expected)) => {
+                let expected = self.replace_keywords(expected);
+                let matched = messages[0].contains(&expected);
+                (matched, expected, messages[0].clone())
+            }
+            // Anchors like `^` can match the message of any source, e.g. that of the database.
+            Some(ExpectedError::Regex(regex)) => (
+                messages.iter().any(|message| regex.is_match(message)),
+                format!("regex: {regex}"),
+                messages[0].clone(),
+            ),
+            Some(ExpectedError::SqlState(expected)) => match self.db.error_code(err) {
+                Some(code) => (
+                    code == expected,
+                    format!("sqlstate {expected}"),
+                    format!("sqlstate {code}: {}", messages[0]),
+                ),
+                None => (false, format!("sqlstate {expected}"), messages[0].clone()),
+            },
+            None => return Ok(()),
+        };
+        if matched {
+            Ok(())
+        } else {
+            Err(TestErrorKind::ErrorMismatch {
+                sql: sql.to_string(),
+                expected,
+                actual,
+            })
+        }
+    }
+
+    /// Run consecutive statements together with [`AsyncDB::run_batch`], which is faster for
+    /// files of mostly statements if the database sends them in one round trip. The results are
+    /// checked in order like by [`Runner::run_async`], and the first failure is returned. Records
+    /// which can not be batched, see [`Record::can_batch`], are run one by one in between.
+    ///
+    /// The hook is called back before all of the statements of a batch are run, and after the
+    /// result of each of them is checked. The outcome of each record is added to
+    /// [`Runner::report`], where the statements of a batch share its time evenly.
+    pub async fn run_batch_async(&mut self, records: Vec<Record>) -> Result<(), TestError> {
+        let mut batch = vec![];
+        for record in records {
+            if record.can_batch() {
+                batch.push(record);
+                continue;
+            }
+            self.run_statements(std::mem::take(&mut batch)).await?;
+            self.run_reported(record).await?;
+        }
+        self.run_statements(batch).await
+    }
+
+    /// Runs statements which can be batched together. See [`Runner::run_batch_async`].
+    async fn run_statements(&mut self, records: Vec<Record>) -> Result<(), TestError> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        if self.file.halted_depth.is_some() {
+            for record in records {
+                self.run_reported(record).await?;
+            }
+            return Ok(());
+        }
+        let start = Instant::now();
+        let mut statements = vec![];
+        for record in records {
+            tracing::info!(?record, "testing");
+            if let Some(hook) = &self.hook {
+                hook.lock().await.before_record(&record).await;
+            }
+            match &record {
+                Record::Statement { conditions, .. } if self.should_skip(conditions) => {
+                    if let Some(hook) = &self.hook {
+                        hook.lock().await.after_record(&record, &Ok(())).await;
+                    }
+                    self.report.records.push(RecordReport {
+                        location: record.location().unwrap(),
+                        duration: Duration::ZERO,
+                        outcome: RecordOutcome::Skipped,
+                        error: None,
+                    });
+                }
+                Record::Statement {
+                    error,
+                    expected_error,
+                    sql,
+                    loc,
+                    ..
+                } => {
+                    let statement = (
+                        self.replace_keywords(sql.clone()),
+                        *error,
+                        expected_error.clone(),
+                        loc.clone(),
+                    );
+                    statements.push((statement, record));
+                }
+                _ => unreachable!("only statements can be batched"),
+            }
+        }
+        let sqls = statements
+            .iter()
+            .map(|((sql, ..), _)| sql.clone())
+            .collect_vec();
+        let results = self.db().run_batch(&sqls).await;
+        // The reference database runs the statements one by one.
+        let mut references = Vec::with_capacity(sqls.len());
+        for sql in &sqls {
+            references.push(self.db().run_reference(sql).await);
+        }
+        let duration = start.elapsed() / sqls.len().max(1) as u32;
+        // All of the results are checked, so that the hook is called back after each record
+        // which has run.
+        let mut first_failure = Ok(());
+        for ((((sql, error, expected_error, loc), record), ret), reference) in
+            statements.into_iter().zip_eq(results).zip_eq(references)
+        {
+            let result = self
+                .check_statement(sql, error, expected_error, None, loc, ret, reference)
+                .await;
+            if let Some(hook) = &self.hook {
+                hook.lock().await.after_record(&record, &result).await;
+            }
+            self.report.records.push(RecordReport {
+                location: record.location().unwrap(),
+                duration,
+                outcome: match &result {
+                    Ok(()) => RecordOutcome::Passed,
+                    Err(_) => RecordOutcome::Failed,
+                },
+                error: result.as_ref().err().map(|e| e.kind().to_string()),
+            });
+            if first_failure.is_ok() {
+                first_failure = result;
+            }
+        }
+        first_failure
+    }
+
     /// Run a single record.
     pub fn run(&mut self, record: Record) -> Result<(), TestError> {
         futures::executor::block_on(self.run_async(record))
     }
 
+    /// Run a single record, and return its outcome. See [`Runner::run_with_outcome_async`].
+    pub fn run_with_outcome(&mut self, record: Record) -> Result<RecordOutcome, TestError> {
+        block_on(self.run_with_outcome_async(record))
+    }
+
     /// Run multiple records.
     ///
-    /// The runner will stop early once a halt record is seen.
+    /// The runner will stop early once a halt record is seen. The outcome of each record is
+    /// kept in [`Runner::report`].
     pub async fn run_multi_async(
         &mut self,
         records: impl IntoIterator<Item = Record>,
     ) -> Result<(), TestError> {
+        self.report = RunReport::default();
         for record in records.into_iter() {
-            if let Record::Halt { .. } = record {
+            self.run_reported(record).await?;
+            if self.file.halted_depth == Some(0) {
                 break;
             }
-            self.run_async(record).await?;
         }
         Ok(())
     }
 
+    /// Runs a record, and adds its outcome to [`Runner::report`] unless it is a comment, an
+    /// empty line or a control record.
+    async fn run_reported(&mut self, record: Record) -> Result<RecordOutcome, TestError> {
+        let location = match &record {
+            Record::Comment { .. } | Record::Newline { .. } | Record::Control { .. } => None,
+            record => record.location(),
+        };
+        let start = Instant::now();
+        let result = self.run_with_outcome_async(record).await;
+        if let Some(location) = location {
+            self.report.records.push(RecordReport {
+                location,
+                duration: start.elapsed(),
+                outcome: match &result {
+                    Ok(outcome) => *outcome,
+                    Err(_) => RecordOutcome::Failed,
+                },
+                error: result.as_ref().err().map(|e| e.kind().to_string()),
+            });
+        }
+        result
+    }
+
+    /// Returns the report of the records run by the last [`Runner::run_multi_async`], or file
+    /// or script, including the failed one if any.
+    pub fn report(&self) -> &RunReport {
+        &self.report
+    }
+
     /// Run multiple records.
     ///
     /// The runner will stop early once a halt record is seen.
@@ -337,25 +1808,66 @@ impl<D: AsyncDB> Runner<D> {
         block_on(self.run_multi_async(records))
     }
 
-    /// Run a sqllogictest script.
-    pub async fn run_script_async(&mut self, script: &str) -> Result<(), TestError> {
-        let records = parse(script).expect("failed to parse sqllogictest");
-        self.run_multi_async(records).await
+    /// Run a sqllogictest script, and return the report of its records. See [`RunReport`].
+    pub async fn run_script_async(&mut self, script: &str) -> Result<RunReport, TestError> {
+        self.start_file();
+        let records =
+            parse_with_options(script, self.parse_options()).expect("failed to parse sqllogictest");
+        self.run_multi_async(records).await?;
+        Ok(self.report.clone())
     }
 
-    /// Run a sqllogictest file.
-    pub async fn run_file_async(&mut self, filename: impl AsRef<Path>) -> Result<(), TestError> {
-        let records = parse_file(filename)?;
-        self.run_multi_async(records).await
+    /// Run a sqllogictest file, and return the report of its records. See [`RunReport`].
+    pub async fn run_file_async(
+        &mut self,
+        filename: impl AsRef<Path>,
+    ) -> Result<RunReport, TestError> {
+        let filename = filename.as_ref();
+        if let Some(hook) = &self.hook {
+            hook.lock().await.before_file(filename).await;
+        }
+        self.start_file();
+        self.report = RunReport::default();
+        let mut result = match parse_file_with_options(filename, self.parse_options()) {
+            Ok(records) => self.run_multi_async(records).await,
+            Err(e) => Err(e.into()),
+        };
+        if result.is_ok() {
+            if let Err(e) = self.write_overrides() {
+                let loc = Location::new(filename.to_string_lossy().to_string(), 0);
+                result = Err(TestErrorKind::OverrideFail { err: Arc::new(e) }.at(loc));
+            }
+        } else {
+            self.overrides.clear();
+        }
+        if let Some(hook) = &self.hook {
+            hook.lock().await.after_file(filename, &result).await;
+        }
+        result?;
+        Ok(self.report.clone())
+    }
+
+    /// Resets the state set by the records of the last file or script, e.g. by `halt` or an
+    /// unmet `require`, which only apply to the file they are in.
+    fn start_file(&mut self) {
+        self.file = FileState::new(self.default_result_mode);
+        if self.float_precision != self.default_float_precision {
+            self.apply_float_precision(self.default_float_precision);
+        }
+        if let Some(protocol) = self.default_protocol {
+            if self.protocol != Some(protocol) {
+                self.apply_protocol(protocol);
+            }
+        }
     }
 
-    /// Run a sqllogictest script.
-    pub fn run_script(&mut self, script: &str) -> Result<(), TestError> {
+    /// Run a sqllogictest script, and return the report of its records.
+    pub fn run_script(&mut self, script: &str) -> Result<RunReport, TestError> {
         block_on(self.run_script_async(script))
     }
 
-    /// Run a sqllogictest file.
-    pub fn run_file(&mut self, filename: impl AsRef<Path>) -> Result<(), TestError> {
+    /// Run a sqllogictest file, and return the report of its records.
+    pub fn run_file(&mut self, filename: impl AsRef<Path>) -> Result<RunReport, TestError> {
         block_on(self.run_file_async(filename))
     }
 
@@ -372,6 +1884,7 @@ impl<D: AsyncDB> Runner<D> {
         Fut: Future<Output = D>,
     {
         let files = glob::glob(glob).expect("failed to read glob pattern");
+        let make_runner = &self.make_runner();
         let mut tasks = vec![];
         // let conn_builder = Arc::new(conn_builder);
 
@@ -391,8 +1904,9 @@ impl<D: AsyncDB> Runner<D> {
                 .expect("create db failed");
             let target = hosts[idx % hosts.len()].clone();
             tasks.push(async move {
-                let db = conn_builder(target, db_name).await;
-                let mut tester = Runner::new(db);
+                let db = conn_builder(target, db_name.clone()).await;
+                let mut tester = make_runner(db);
+                tester.set_database(db_name);
                 let filename = file.to_string_lossy().to_string();
                 tester.run_file_async(filename).await
             })
@@ -410,6 +1924,53 @@ impl<D: AsyncDB> Runner<D> {
         }
     }
 
+    /// Returns a function creating runners on other databases with the configuration of this
+    /// one, for the files run by [`Runner::run_parallel_async`]. The hook, the directives and the
+    /// connection factory are shared, and the state of the files run by this one is not copied.
+    fn make_runner(&self) -> impl Fn(D) -> Self + Send + Sync {
+        let make_connection = self.make_connection.clone();
+        let validator = self.validator;
+        let testdir = self.testdir.is_some();
+        let hook = self.hook.clone();
+        let directives = self.directives.clone();
+        let (parse_mode, dialect) = (self.parse_mode, self.dialect);
+        let (max_diff_lines, max_output_bytes) = (self.max_diff_lines, self.max_output_bytes);
+        let diff_style = self.diff_style;
+        let (type_check, float_tolerance) = (self.type_check, self.float_tolerance);
+        let normalizers = self.normalizers.clone();
+        let result_mode = self.default_result_mode;
+        let (float_precision, protocol) = (self.default_float_precision, self.default_protocol);
+        let (override_results, record_results) = (self.override_results, self.record_results);
+        move |db| {
+            let mut runner = Runner::new(db);
+            runner.make_connection = make_connection.clone();
+            runner.validator = validator;
+            if testdir {
+                runner.enable_testdir();
+            }
+            runner.hook = hook.clone();
+            runner.directives = directives.clone();
+            runner.parse_mode = parse_mode;
+            runner.dialect = dialect;
+            runner.max_diff_lines = max_diff_lines;
+            runner.diff_style = diff_style;
+            runner.max_output_bytes = max_output_bytes;
+            runner.type_check = type_check;
+            runner.float_tolerance = float_tolerance;
+            runner.normalizers = normalizers.clone();
+            runner.set_result_mode(result_mode);
+            if float_precision.is_some() {
+                runner.set_float_precision(float_precision);
+            }
+            if let Some(protocol) = protocol {
+                runner.set_protocol(protocol);
+            }
+            runner.override_results = override_results;
+            runner.record_results = record_results;
+            runner
+        }
+    }
+
     /// sync version of `run_parallel_async`
     pub fn run_parallel<Fut>(
         &mut self,
@@ -424,12 +1985,37 @@ impl<D: AsyncDB> Runner<D> {
         block_on(self.run_parallel_async(glob, hosts, conn_builder, jobs))
     }
 
-    /// Replace all keywords in the SQL.
-    fn replace_keywords(&self, sql: String) -> String {
+    /// Replace all keywords and the variables of [`Record::Let`] in the SQL, and environment
+    /// variables after `control substitution on`.
+    fn replace_keywords(&self, mut sql: String) -> String {
+        // The variables set by `let` take precedence over the environment variables.
+        match self.file.substitution {
+            Some(false) => return sql,
+            Some(true) => {
+                sql = substitute_variables(&sql, &self.file.variables, |name| {
+                    std::env::var(name).ok()
+                })
+                .replace("__NOW__", &self.now);
+            }
+            None => sql = substitute_variables(&sql, &self.file.variables, |_| None),
+        }
         if let Some(testdir) = &self.testdir {
-            sql.replace("__TEST_DIR__", testdir.path().to_str().unwrap())
-        } else {
-            sql
+            sql = sql.replace("__TEST_DIR__", testdir.path().to_str().unwrap());
+        }
+        if let Some(database) = &self.database {
+            sql = sql.replace("__DATABASE__", database);
+        }
+        sql
+    }
+
+    /// Returns whether the record is skipped by its conditions.
+    fn is_skipped(&self, record: &Record) -> bool {
+        match record {
+            Record::Statement { conditions, .. }
+            | Record::Query { conditions, .. }
+            | Record::Halt { conditions, .. }
+            | Record::Custom { conditions, .. } => self.should_skip(conditions),
+            _ => false,
         }
     }
 
@@ -437,23 +2023,1191 @@ impl<D: AsyncDB> Runner<D> {
     fn should_skip(&self, conditions: &[Condition]) -> bool {
         conditions
             .iter()
-            .any(|c| c.should_skip(self.db.engine_name()))
+            .any(|c| c.should_skip(self.db.engine_name(), |cap| self.db.has_capability(cap)))
     }
 
-    /// Set hook functions.
+    /// Set hook functions, which are called back around the files and records run by the
+    /// runner. See [`Hook`].
     pub fn set_hook(&mut self, hook: impl Hook + 'static) {
-        self.hook = Some(Box::new(hook));
+        self.hook = Some(Arc::new(Mutex::new(hook)));
+    }
+
+    /// Run lines starting with `name`, e.g. `flush`, with `directive`, instead of failing to
+    /// parse them. Scripts and files parsed by the runner recognize the directive, and those
+    /// parsed apart need it in [`ParseOptions::directives`].
+    pub fn register_directive(
+        &mut self,
+        name: impl Into<String>,
+        directive: impl Directive<D> + 'static,
+    ) {
+        self.directives
+            .insert(name.into(), Arc::new(Mutex::new(directive)));
+    }
+
+    /// Set how strictly the scripts and files run by the runner are parsed.
+    pub fn set_parse_mode(&mut self, mode: ParseMode) {
+        self.parse_mode = mode;
+    }
+
+    /// Set the dialect of the scripts and files run by the runner.
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+    }
+
+    /// Returns the options to parse scripts with the directives of the runner.
+    fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            directives: self.directives.keys().cloned().collect(),
+            mode: self.parse_mode,
+            dialect: self.dialect,
+            ..Default::default()
+        }
+    }
+}
+
+/// Checks the result of [`AsyncDB::load`] or [`AsyncDB::restart`], named `name`.
+fn check_reopen<E: std::error::Error + Send + Sync + 'static>(
+    name: &str,
+    result: Option<Result<(), E>>,
+) -> Result<(), TestErrorKind> {
+    match result {
+        Some(Ok(())) => Ok(()),
+        Some(Err(e)) => Err(TestErrorKind::DirectiveFail {
+            text: name.to_string(),
+            err: Arc::new(e),
+        }),
+        None => Err(TestErrorKind::UnsupportedDirective {
+            name: name.to_string(),
+        }),
     }
 }
 
+/// Groups values, one on each line, into rows of `columns` values.
+fn group_values(values: &[String], columns: usize) -> Vec<String> {
+    values
+        .chunks(columns.max(1))
+        .map(|row| row.join(" "))
+        .collect()
+}
+
 /// Trim and replace multiple whitespaces with one.
 fn normalize_string(s: &str) -> String {
     s.trim().split_ascii_whitespace().join(" ")
 }
 
-fn split_lines_and_normalize(s: &str) -> Vec<String> {
+/// Replaces `${name}` and `$name` with the values of the variables, where `$name` is not followed
+/// by other characters of an identifier, and `${NAME}` which is not a variable with the value of
+/// the environment variable `NAME` given by `env`. The string is scanned once, so values
+/// containing `$` are not substituted again. Other names are kept as they are.
+fn substitute_variables(
+    s: &str,
+    variables: &HashMap<String, String>,
+    env: impl Fn(&str) -> Option<String>,
+) -> String {
+    let mut output = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        output += &rest[..start];
+        let after = &rest[start + 1..];
+        let (name, end, braced) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(len) => (&braced[..len], len + 2, true),
+                None => ("", 0, false),
+            },
+            None => {
+                let len = after
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(after.len());
+                (&after[..len], len, false)
+            }
+        };
+        let value = match variables.get(name) {
+            Some(value) => Some(value.clone()),
+            None if braced => env(name),
+            None => None,
+        };
+        match value {
+            Some(value) if !name.is_empty() => {
+                output += &value;
+                rest = &after[end..];
+            }
+            _ => {
+                output.push('$');
+                rest = after;
+            }
+        }
+    }
+    output + rest
+}
+
+/// Returns the message of the error followed by those of its sources, then that of each source
+/// followed by those of the next ones, as errors like those of `tokio-postgres` have the message
+/// of the database in a source.
+fn error_messages(err: &dyn std::error::Error) -> Vec<String> {
+    let mut messages = vec![err.to_string()];
+    let mut source = err.source();
+    while let Some(err) = source {
+        for message in &mut messages {
+            *message += ": ";
+            *message += &err.to_string();
+        }
+        messages.push(err.to_string());
+        source = err.source();
+    }
+    messages
+}
+
+/// Returns whether the type string of a query matches the types of its columns.
+fn types_match(type_string: &str, types: &[ColumnType]) -> bool {
+    type_string.chars().count() == types.len()
+        && type_string
+            .chars()
+            .zip(types)
+            .all(|(c, actual)| match ColumnType::from_char(c) {
+                Some(expected) => *actual == ColumnType::Any || expected == *actual,
+                None => true,
+            })
+}
+
+/// Returns the values of the normalized rows in sorted order, one per line.
+fn sort_values(rows: &[String]) -> Vec<String> {
+    let mut values = rows
+        .iter()
+        .flat_map(|row| row.split(' '))
+        .map(String::from)
+        .collect_vec();
+    values.sort_unstable();
+    values
+}
+
+/// Returns whether the results are a hash, i.e. `N values hashing to X`.
+fn is_hashed(results: &[String]) -> bool {
+    match results {
+        [line] => matches!(
+            line.split(' ').collect::<Vec<_>>().as_slice(),
+            [count, "values", "hashing", "to", hash]
+                if count.parse::<usize>().is_ok() && hash.len() == 32
+        ),
+        _ => false,
+    }
+}
+
+/// Returns the number of values in the normalized rows.
+fn count_values(rows: &[String]) -> usize {
+    rows.iter().map(|row| row.split(' ').count()).sum()
+}
+
+/// Returns `N values hashing to X`, where `X` is the MD5 of the values each followed by a
+/// newline, like the original sqllogictest.
+fn hash_values(rows: &[String]) -> String {
+    use md5::{Digest, Md5};
+
+    let mut hasher = Md5::new();
+    for value in rows.iter().flat_map(|row| row.split(' ')) {
+        hasher.update(value);
+        hasher.update("\n");
+    }
+    let hash = hasher.finalize();
+    format!(
+        "{} values hashing to {}",
+        count_values(rows),
+        hash.iter().map(|b| format!("{b:02x}")).join("")
+    )
+}
+
+/// Joins the lines, keeping at most `max` of them followed by a line with the number left out.
+fn truncate_lines(lines: &[String], max: Option<usize>) -> String {
+    match max {
+        Some(max) if lines.len() > max => format!(
+            "{}\n... ({} more lines)",
+            lines[..max].join("\n"),
+            lines.len() - max
+        ),
+        _ => lines.join("\n"),
+    }
+}
+
+/// How the expected and the actual results of a query are shown when they mismatch. See
+/// [`Runner::set_diff_style`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum DiffStyle {
+    /// A unified diff, with the removed expected rows followed by the added actual rows.
+    #[default]
+    Unified,
+    /// The expected and the actual rows next to each other, with the values which differ
+    /// highlighted, which is easier to scan for wide rows.
+    SideBySide,
+}
+
+impl DiffStyle {
+    pub fn try_from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "unified" => Ok(Self::Unified),
+            "side-by-side" => Ok(Self::SideBySide),
+            _ => Err(format!("invalid diff style: {s:?}")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unified => "unified",
+            Self::SideBySide => "side-by-side",
+        }
+    }
+}
+
+/// The number of unchanged lines shown around the changed ones in a diff.
+const DIFF_CONTEXT: usize = 3;
+
+const RED: &str = "\x1b[91m";
+const GREEN: &str = "\x1b[92m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Wraps the text in the ANSI escape codes of the color if `colorize`.
+fn paint(text: &str, color: &str, colorize: bool) -> String {
+    if colorize {
+        format!("{color}{text}{RESET}")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Formats a diff from the expected to the actual results in the style, with the changes
+/// colored if `colorize` and [`DIFF_CONTEXT`] lines around them, so that a few differing rows stand out in a
+/// large result. The header shows the location of the expected results if known, and at most
+/// `max_lines` lines are shown after it.
+fn format_diff(
+    expected: &str,
+    actual: &str,
+    expected_at: Option<&str>,
+    style: DiffStyle,
+    max_lines: Option<usize>,
+    colorize: bool,
+) -> String {
+    // Results have no empty lines, but an empty result is diffed as one.
+    let lines = difference::Changeset::new(expected, actual, "\n")
+        .diffs
+        .into_iter()
+        .flat_map(|diff| {
+            let (tag, text) = match diff {
+                difference::Difference::Same(text) => (' ', text),
+                difference::Difference::Rem(text) => ('-', text),
+                difference::Difference::Add(text) => ('+', text),
+            };
+            text.split('\n')
+                .filter(|line| !line.is_empty())
+                .map(|line| (tag, line.to_string()))
+                .collect_vec()
+        })
+        .collect_vec();
+    let expected_at = expected_at.map_or(String::new(), |at| format!(" at {at}"));
+    let header = match style {
+        DiffStyle::Unified => format!("(-expected{expected_at}, +actual)"),
+        DiffStyle::SideBySide => format!("(expected{expected_at} | actual)"),
+    };
+    let mut out = String::new();
+    match style {
+        DiffStyle::Unified => {
+            write_hunks(
+                &mut out,
+                &lines,
+                |(tag, _)| *tag != ' ',
+                |(tag, _)| (*tag != '+', *tag != '-'),
+                colorize,
+                |out, hunk| {
+                    for (tag, line) in hunk {
+                        *out += &match tag {
+                            '-' => format!("\n{}", paint(&format!("-{line}"), RED, colorize)),
+                            '+' => format!("\n{}", paint(&format!("+{line}"), GREEN, colorize)),
+                            _ => format!("\n {line}"),
+                        };
+                    }
+                },
+            );
+        }
+        DiffStyle::SideBySide => {
+            let pairs = pair_lines(&lines);
+            let width = pairs
+                .iter()
+                .filter_map(|(expected, _)| expected.map(|line| line.chars().count()))
+                .max()
+                .unwrap_or(0);
+            write_hunks(
+                &mut out,
+                &pairs,
+                |(expected, actual)| expected != actual,
+                |(expected, actual)| (expected.is_some(), actual.is_some()),
+                colorize,
+                |out, hunk| {
+                    for &(expected, actual) in hunk {
+                        *out += &format_side_by_side(expected, actual, width, colorize);
+                    }
+                },
+            );
+        }
+    }
+    // Each line of the hunks starts with a newline.
+    let out = out.split('\n').skip(1).map(str::to_string).collect_vec();
+    if out.is_empty() {
+        return header;
+    }
+    format!("{header}\n{}", truncate_lines(&out, max_lines))
+}
+
+/// Writes the hunks of a diff of `items`, i.e. the runs of changed items and the items around
+/// them, each after a header with the line numbers in the expected and the actual results where
+/// it starts, and the number of its lines in them. `lines` returns whether an item takes a line
+/// in the expected and in the actual results. The headers are colored if `colorize`.
+fn write_hunks<T>(
+    out: &mut String,
+    items: &[T],
+    changed: impl Fn(&T) -> bool,
+    lines: impl Fn(&T) -> (bool, bool),
+    colorize: bool,
+    write: impl Fn(&mut String, &[T]),
+) {
+    // Whether each item is shown, i.e. it is changed or near a changed one.
+    let mut shown = vec![false; items.len()];
+    for (i, _) in items.iter().enumerate().filter(|(_, item)| changed(item)) {
+        let end = (i + DIFF_CONTEXT + 1).min(items.len());
+        shown[i.saturating_sub(DIFF_CONTEXT)..end].fill(true);
+    }
+    let count = |items: &[T]| {
+        items
+            .iter()
+            .map(&lines)
+            .fold((0, 0), |(expected, actual), (e, a)| {
+                (expected + e as usize, actual + a as usize)
+            })
+    };
+    let mut i = 0;
+    while i < items.len() {
+        if !shown[i] {
+            i += 1;
+            continue;
+        }
+        let hunk = &items[i..i + shown[i..].iter().take_while(|&&s| s).count()];
+        // Line numbers are counted from 1.
+        let (expected_line, actual_line) = count(&items[..i]);
+        let (expected_count, actual_count) = count(hunk);
+        let header = format!(
+            "@@ -{},{expected_count} +{},{actual_count} @@",
+            expected_line + 1,
+            actual_line + 1,
+        );
+        *out += &format!("\n{}", paint(&header, CYAN, colorize));
+        write(out, hunk);
+        i += hunk.len();
+    }
+}
+
+/// Pairs the lines of a diff side by side: each unchanged line with itself, and the removed
+/// lines of each change with the lines added in their place, in order.
+fn pair_lines(lines: &[(char, String)]) -> Vec<(Option<&str>, Option<&str>)> {
+    let mut pairs = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].0 == ' ' {
+            pairs.push((Some(lines[i].1.as_str()), Some(lines[i].1.as_str())));
+            i += 1;
+            continue;
+        }
+        let change = lines[i..].iter().take_while(|(tag, _)| *tag != ' ').count();
+        let change = &lines[i..i + change];
+        let removed = change.iter().filter(|(tag, _)| *tag == '-');
+        let added = change.iter().filter(|(tag, _)| *tag == '+');
+        pairs.extend(removed.zip_longest(added).map(|pair| match pair {
+            EitherOrBoth::Both((_, expected), (_, actual)) => {
+                (Some(expected.as_str()), Some(actual.as_str()))
+            }
+            EitherOrBoth::Left((_, expected)) => (Some(expected.as_str()), None),
+            EitherOrBoth::Right((_, actual)) => (None, Some(actual.as_str())),
+        }));
+        i += change.len();
+    }
+    pairs
+}
+
+/// Formats an expected and an actual row next to each other, with the expected one padded to
+/// `width`. The values of a changed row which differ from those of the other side are colored
+/// if `colorize`, and the row is marked with `!`.
+fn format_side_by_side<'a>(
+    expected: Option<&'a str>,
+    actual: Option<&'a str>,
+    width: usize,
+    colorize: bool,
+) -> String {
+    if expected == actual {
+        let line = expected.unwrap_or_default();
+        let pad = width - line.chars().count();
+        return format!("\n  {line}{:pad$} | {line}", "");
+    }
+    // Rows are normalized, so values are separated by single spaces.
+    let values = |row: Option<&'a str>| row.map_or(vec![], |row| row.split(' ').collect_vec());
+    let (expected_values, actual_values) = (values(expected), values(actual));
+    let highlight = |values: &[&str], others: &[&str], color: &str| {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| match others.get(i) {
+                Some(other) if other == value => value.to_string(),
+                _ => paint(value, color, colorize),
+            })
+            .join(" ")
+    };
+    let pad = width - expected.map_or(0, |line| line.chars().count());
+    format!(
+        "\n! {}{:pad$} | {}",
+        highlight(&expected_values, &actual_values, RED),
+        "",
+        highlight(&actual_values, &expected_values, GREEN),
+    )
+}
+
+/// Whether the rows are the same, except for the values of `R` columns which differ within the
+/// tolerance. See [`FloatTolerance`].
+fn rows_match_within(
+    actual: &[String],
+    expected: &[String],
+    type_string: &str,
+    tolerance: f64,
+) -> bool {
+    actual.len() == expected.len()
+        && actual.iter().zip(expected).all(|(actual, expected)| {
+            // Rows are normalized, so values are separated by single spaces.
+            let (actual_values, expected_values) = (actual.split(' '), expected.split(' '));
+            actual == expected
+                || (actual_values.clone().count() == type_string.len()
+                    && expected_values.clone().count() == type_string.len()
+                    && actual_values
+                        .zip(expected_values)
+                        .zip(type_string.chars())
+                        .all(|((actual, expected), ty)| {
+                            actual == expected
+                                || (ty == 'R' && floats_match(actual, expected, tolerance))
+                        }))
+        })
+}
+
+/// Whether the values are floats which differ by at most the tolerance, relative to the larger
+/// of them if it is larger than 1. See [`FloatTolerance`].
+fn floats_match(actual: &str, expected: &str, tolerance: f64) -> bool {
+    match (actual.parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(actual), Ok(expected)) => {
+            actual == expected
+                || (actual - expected).abs()
+                    <= tolerance * actual.abs().max(expected.abs()).max(1.0)
+        }
+        _ => false,
+    }
+}
+
+/// Rewrites the normalized lines with the normalizers in order, and normalizes them again.
+fn apply_normalizers<'a>(
+    normalizers: impl Iterator<Item = &'a Normalizer> + Clone,
+    lines: Vec<String>,
+) -> Vec<String> {
+    lines
+        .into_iter()
+        .map(|line| {
+            let line = normalizers
+                .clone()
+                .fold(line, |line, normalizer| normalizer.apply(&line));
+            normalize_string(&line)
+        })
+        .collect()
+}
+
+/// Splits `s` into normalized lines without the empty ones, with the index of each line in `s`.
+fn split_lines_and_number(s: &str) -> Vec<(String, usize)> {
     s.split('\n')
         .map(normalize_string)
-        .filter(|line| !line.is_empty())
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| (line, i))
         .collect()
 }
+
+/// Returns the location of the first expected row which differs from the output, at the
+/// column of its first differing value in `raw`, the expected results at `results_loc`, where
+/// `lines` are the indices of the rows in them. A missing row is located after the last one.
+/// Returns `None` if the rows can not be located.
+fn locate_mismatch(
+    results_loc: &Location,
+    raw: &str,
+    expected: &[String],
+    lines: &[usize],
+    output: &[String],
+) -> Option<Location> {
+    if lines.len() != expected.len() {
+        return None;
+    }
+    let row = (0..expected.len().max(output.len())).find(|&i| expected.get(i) != output.get(i))?;
+    let (Some(expected_row), Some(&line)) = (expected.get(row), lines.get(row)) else {
+        let next = lines.iter().max().map_or(0, |line| line + 1);
+        return Some(results_loc.offset(next as u32, 1));
+    };
+    // Rows are normalized, so values are separated by single spaces.
+    let value = match output.get(row) {
+        Some(output_row) => expected_row
+            .split(' ')
+            .zip(output_row.split(' '))
+            .position(|(expected, actual)| expected != actual),
+        None => Some(0),
+    };
+    // The values may be separated by any whitespace in the file, e.g. in aligned columns.
+    let raw_line = raw.split('\n').nth(line).unwrap_or_default();
+    let column = value
+        .and_then(|value| value_column(raw_line, value))
+        .unwrap_or(1);
+    Some(results_loc.offset(line as u32, column as u32))
+}
+
+/// Returns the column, counted in characters from 1, of the `n`th value in the line, where
+/// values are separated by whitespace.
+fn value_column(line: &str, n: usize) -> Option<usize> {
+    let mut prev = ' ';
+    line.chars()
+        .enumerate()
+        .filter(|&(_, c)| {
+            let start = !c.is_whitespace() && prev.is_whitespace();
+            prev = c;
+            start
+        })
+        .nth(n)
+        .map(|(i, _)| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(diff: &[(char, &str)]) -> Vec<(char, String)> {
+        diff.iter()
+            .map(|&(tag, line)| (tag, line.to_string()))
+            .collect()
+    }
+
+    fn strings(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_format_diff() {
+        assert_eq!(
+            format_diff("1\n2\n3", "1\n4\n3", Some("t.slt:5"), DiffStyle::Unified, None, true),
+            format!(
+                "(-expected at t.slt:5, +actual)\n{CYAN}@@ -1,3 +1,3 @@{RESET}\n 1\n{RED}-2{RESET}\n{GREEN}+4{RESET}\n 3"
+            )
+        );
+        assert_eq!(
+            format_diff("1\n22\n3", "1\n4\n3", None, DiffStyle::SideBySide, None, true),
+            format!(
+                "(expected | actual)\n{CYAN}@@ -1,3 +1,3 @@{RESET}\n  1  | 1\n! {RED}22{RESET} | {GREEN}4{RESET}\n  3  | 3"
+            )
+        );
+        // An empty result is diffed as one empty line, which is not shown.
+        assert_eq!(
+            format_diff("", "1", None, DiffStyle::Unified, None, true),
+            format!("(-expected, +actual)\n{CYAN}@@ -1,0 +1,1 @@{RESET}\n{GREEN}+1{RESET}")
+        );
+        // Without colors, e.g. for JUnit reports and logs.
+        assert_eq!(
+            format_diff(
+                "1\n22\n3",
+                "1\n4\n3",
+                None,
+                DiffStyle::SideBySide,
+                None,
+                false
+            ),
+            "(expected | actual)\n@@ -1,3 +1,3 @@\n  1  | 1\n! 22 | 4\n  3  | 3"
+        );
+    }
+
+    #[test]
+    fn test_write_hunks() {
+        // Changes further apart than twice the context are shown in separate hunks.
+        let mut diff = (1..=20).map(|i| (' ', i.to_string())).collect::<Vec<_>>();
+        diff.splice(1..2, lines(&[('-', "2"), ('+', "x")]));
+        diff.splice(18..19, lines(&[('-', "18"), ('+', "y")]));
+        let mut out = String::new();
+        write_hunks(
+            &mut out,
+            &diff,
+            |(tag, _)| *tag != ' ',
+            |(tag, _)| (*tag != '+', *tag != '-'),
+            true,
+            |out, hunk| {
+                for (tag, line) in hunk {
+                    *out += &format!(" {tag}{line}");
+                }
+            },
+        );
+        assert_eq!(
+            out,
+            format!(
+                "\n{CYAN}@@ -1,5 +1,5 @@{RESET}  1 -2 +x  3  4  5\
+                 \n{CYAN}@@ -15,6 +15,6 @@{RESET}  15  16  17 -18 +y  19  20"
+            )
+        );
+
+        let mut out = String::new();
+        write_hunks(
+            &mut out,
+            &diff[..1],
+            |_| false,
+            |_| (true, true),
+            true,
+            |_, _| {},
+        );
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn test_pair_lines() {
+        let diff = lines(&[
+            (' ', "a"),
+            ('-', "b"),
+            ('-', "c"),
+            ('+', "d"),
+            (' ', "e"),
+            ('+', "f"),
+        ]);
+        assert_eq!(
+            pair_lines(&diff),
+            vec![
+                (Some("a"), Some("a")),
+                (Some("b"), Some("d")),
+                (Some("c"), None),
+                (Some("e"), Some("e")),
+                (None, Some("f")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_side_by_side() {
+        assert_eq!(
+            format_side_by_side(Some("a"), Some("a"), 3, true),
+            "\n  a   | a"
+        );
+        assert_eq!(
+            format_side_by_side(Some("1 2"), Some("1 3"), 5, true),
+            format!("\n! 1 {RED}2{RESET}   | 1 {GREEN}3{RESET}")
+        );
+        assert_eq!(
+            format_side_by_side(None, Some("x"), 2, true),
+            format!("\n!    | {GREEN}x{RESET}")
+        );
+        assert_eq!(
+            format_side_by_side(Some("1 2"), None, 3, true),
+            format!("\n! {RED}1{RESET} {RED}2{RESET} | ")
+        );
+        assert_eq!(
+            format_side_by_side(Some("1 2"), None, 3, false),
+            "\n! 1 2 | "
+        );
+    }
+
+    #[test]
+    fn test_hash_values() {
+        // The MD5 of `1\n2\n3\n`.
+        assert_eq!(
+            hash_values(&strings(&["1 2", "3"])),
+            "3 values hashing to c0710d6b4f15dfa88f600b0e6b624077"
+        );
+        assert_eq!(
+            hash_values(&[]),
+            "0 values hashing to d41d8cd98f00b204e9800998ecf8427e"
+        );
+        assert!(is_hashed(&[hash_values(&strings(&["1 2", "3"]))]));
+    }
+
+    #[test]
+    fn test_floats_match() {
+        assert!(floats_match("1.0", "1.0000001", 1e-6));
+        assert!(!floats_match("1", "1.1", 1e-6));
+        // Relative to the larger value above 1, and absolute below.
+        assert!(floats_match("1000000", "1000000.5", 1e-6));
+        assert!(!floats_match("1000000", "1000002", 1e-6));
+        assert!(floats_match("0.0000001", "0", 1e-6));
+        assert!(floats_match("inf", "inf", 1e-6));
+        assert!(!floats_match("abc", "abc", 1e-6));
+        assert!(!floats_match("NaN", "NaN", 1e-6));
+    }
+
+    #[test]
+    fn test_rows_match_within() {
+        let within = |actual: &[&str], expected: &[&str], types| {
+            rows_match_within(&strings(actual), &strings(expected), types, 1e-6)
+        };
+        assert!(within(&["1 1.0000001", "2 x"], &["1 1.0", "2 x"], "IR"));
+        // Only `R` columns are compared within the tolerance.
+        assert!(!within(&["1.0000001 1"], &["1.0 1"], "IR"));
+        assert!(!within(&["1 1.0000001"], &["1 1.0", "2 2.0"], "IR"));
+        // Rows whose values do not match the types are compared textually.
+        assert!(!within(&["1 1.0000001"], &["1 1.0"], "R"));
+        assert!(within(&["a b c"], &["a b c"], "R"));
+    }
+
+    #[test]
+    fn test_locate_mismatch() {
+        let loc = Location::new("t.slt", 5);
+        let raw = "1  2\n\n3  4";
+        let expected = strings(&["1 2", "3 4"]);
+        let locate = |output: &[&str]| {
+            locate_mismatch(&loc, raw, &expected, &[0, 2], &strings(output))
+                .map(|loc| (loc.line(), loc.column()))
+        };
+        assert_eq!(locate(&["1 2", "3 5"]), Some((7, 4)));
+        assert_eq!(locate(&["1 2"]), Some((7, 1)));
+        assert_eq!(locate(&["1 2", "3 4", "5 6"]), Some((8, 1)));
+        assert_eq!(locate(&["1 2", "3 4"]), None);
+        assert_eq!(
+            locate_mismatch(&loc, raw, &expected, &[0], &strings(&["1 3"])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_value_column() {
+        assert_eq!(value_column("a", 0), Some(1));
+        assert_eq!(value_column("  a\tbb  c", 0), Some(3));
+        assert_eq!(value_column("  a\tbb  c", 1), Some(5));
+        assert_eq!(value_column("  a\tbb  c", 2), Some(9));
+        assert_eq!(value_column("  a\tbb  c", 3), None);
+        assert_eq!(value_column("é ü", 1), Some(3));
+    }
+
+    #[test]
+    fn test_substitute_variables() {
+        let variables = HashMap::from([
+            ("a".to_string(), "$b".to_string()),
+            ("b".to_string(), "${a}".to_string()),
+            ("id".to_string(), "1".to_string()),
+        ]);
+        let no_env = |_: &str| None;
+        assert_eq!(
+            substitute_variables("$a ${b} $b$a", &variables, no_env),
+            "$b ${a} ${a}$b"
+        );
+        assert_eq!(
+            substitute_variables(
+                "$id ${id} $ids $1 $$ ${id ${unterminated",
+                &variables,
+                no_env
+            ),
+            "1 1 $ids $1 $$ ${id ${unterminated"
+        );
+
+        // Only `${NAME}` is an environment variable, and the variables take precedence.
+        let env = HashMap::from([
+            ("HOME".to_string(), "/home/$id".to_string()),
+            ("id".to_string(), "env".to_string()),
+        ]);
+        let env = |name: &str| env.get(name).cloned();
+        assert_eq!(
+            substitute_variables("${HOME} $HOME ${id} ${UNSET}", &variables, env),
+            "/home/$id $HOME 1 ${UNSET}"
+        );
+    }
+
+    struct FakeDB;
+
+    #[derive(Debug)]
+    struct FakeDBError;
+
+    impl std::fmt::Display for FakeDBError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    impl std::error::Error for FakeDBError {}
+
+    impl DB for FakeDB {
+        type Error = FakeDBError;
+
+        fn run(&mut self, sql: &str) -> Result<DBOutput, FakeDBError> {
+            match sql {
+                "fail" => Err(FakeDBError),
+                "select 1, 2" => Ok(DBOutput::Rows {
+                    names: vec![],
+                    types: vec![ColumnType::Integer, ColumnType::Integer],
+                    rows: vec![vec!["1".to_string(), "2".to_string()]],
+                }),
+                _ => Ok(DBOutput::Rows {
+                    names: vec![],
+                    types: vec![ColumnType::Integer],
+                    rows: vec![vec!["2".to_string()]],
+                }),
+            }
+        }
+    }
+
+    /// Returns the same rows in reverse order from the reference database, which does not fail
+    /// `fail main`.
+    struct ReversedDB;
+
+    impl DB for ReversedDB {
+        type Error = FakeDBError;
+
+        fn run(&mut self, sql: &str) -> Result<DBOutput, FakeDBError> {
+            match sql {
+                "fail" | "fail main" => Err(FakeDBError),
+                _ => Ok(DBOutput::Rows {
+                    names: vec![],
+                    types: vec![ColumnType::Integer, ColumnType::FloatingPoint],
+                    rows: vec![
+                        vec!["2".to_string(), "0.5".to_string()],
+                        vec!["1".to_string(), "0.25".to_string()],
+                    ],
+                }),
+            }
+        }
+
+        fn run_reference(&mut self, sql: &str) -> Option<Result<DBOutput, FakeDBError>> {
+            if sql == "fail main" {
+                return Some(Ok(DBOutput::StatementComplete(Some(0))));
+            }
+            Some(DB::run(self, sql).map(|output| match output {
+                DBOutput::Rows { names, types, rows } => DBOutput::Rows {
+                    names,
+                    types,
+                    rows: rows.into_iter().rev().collect(),
+                },
+                output => output,
+            }))
+        }
+    }
+
+    #[test]
+    fn test_run_reference() {
+        let mut runner = Runner::new(ReversedDB);
+        // The expected results are ignored, and the rows are compared after they are sorted.
+        runner
+            .run_script("query IR rowsort\nselect\n----\n3 3\n")
+            .unwrap();
+        let err = runner.run_script("query IR\nselect\n").unwrap_err();
+        assert!(
+            matches!(err.kind(), TestErrorKind::QueryResultMismatch { .. }),
+            "{err}"
+        );
+        // Errors must match on both.
+        runner
+            .run_script("statement error FakeDBError\nfail\n")
+            .unwrap();
+        runner.run_script("query error FakeDB\nfail\n").unwrap();
+        let err = runner
+            .run_script("statement error\nfail main\n")
+            .unwrap_err();
+        assert!(
+            matches!(err.kind(), TestErrorKind::StatementOk { .. }),
+            "{err}"
+        );
+        let err = runner.run_script("query error\nfail main\n").unwrap_err();
+        assert!(matches!(err.kind(), TestErrorKind::QueryOk { .. }), "{err}");
+    }
+
+    /// Returns the numbers from 1 to 20 as rows.
+    struct NumbersDB;
+
+    impl DB for NumbersDB {
+        type Error = FakeDBError;
+
+        fn run(&mut self, _sql: &str) -> Result<DBOutput, FakeDBError> {
+            Ok(DBOutput::Rows {
+                names: vec![],
+                types: vec![ColumnType::Integer],
+                rows: (1..=20).map(|i| vec![i.to_string()]).collect(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_max_diff_lines() {
+        let mut runner = Runner::new(NumbersDB);
+        runner.set_max_diff_lines(5);
+        // The rows after the limit are still diffed, and the lines of the diff are cut.
+        let rows = (1..=20)
+            .map(|i| {
+                if i == 15 {
+                    "x".to_string()
+                } else {
+                    i.to_string()
+                }
+            })
+            .join("\n");
+        let err = runner
+            .run_script(&format!("query I\nselect\n----\n{rows}\n"))
+            .unwrap_err();
+        assert!(
+            matches!(err.kind(), TestErrorKind::QueryResultMismatch { .. }),
+            "{err}"
+        );
+        let plain = err.to_string();
+        assert!(
+            plain.contains("\n@@ -12,7 +12,7 @@\n 12\n 13\n 14\n-x\n... (4 more lines)\n"),
+            "{plain}"
+        );
+        let colored = err.display(true).to_string();
+        assert!(
+            colored.contains(&format!(
+                "\n{CYAN}@@ -12,7 +12,7 @@{RESET}\n 12\n 13\n 14\n{RED}-x{RESET}\n... (4 more lines)\n"
+            )),
+            "{colored}"
+        );
+    }
+
+    /// Does not know the number of rows affected by statements.
+    struct CountlessDB;
+
+    impl DB for CountlessDB {
+        type Error = FakeDBError;
+
+        fn run(&mut self, _sql: &str) -> Result<DBOutput, FakeDBError> {
+            Ok(DBOutput::StatementComplete(None))
+        }
+    }
+
+    #[test]
+    fn test_statement_count_unsupported() {
+        let mut runner = Runner::new(CountlessDB);
+        runner
+            .run_script(
+                "statement ok
+insert
+",
+            )
+            .unwrap();
+        let err = runner
+            .run_script(
+                "statement count 0
+insert
+",
+            )
+            .unwrap_err();
+        assert!(
+            matches!(err.kind(), TestErrorKind::StatementCountUnsupported { .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_run_batch() {
+        let mut runner = Runner::new(FakeDB);
+        let records = parse(
+            "statement ok
+select 1
+
+query I
+select 1
+----
+2
+
+statement ok
+fail
+
+statement ok
+select 2
+",
+        )
+        .unwrap();
+        // The query is run on its own, and the statements after the failure are still checked.
+        let err = block_on(runner.run_batch_async(records)).unwrap_err();
+        assert!(
+            matches!(err.kind(), TestErrorKind::StatementFail { .. }),
+            "{err}"
+        );
+        let outcomes = runner
+            .report()
+            .records
+            .iter()
+            .map(|record| (record.location.line(), record.outcome))
+            .collect_vec();
+        assert_eq!(
+            outcomes,
+            [
+                (1, RecordOutcome::Passed),
+                (4, RecordOutcome::Passed),
+                (9, RecordOutcome::Failed),
+                (12, RecordOutcome::Passed),
+            ]
+        );
+    }
+
+    /// Returns its name for `name`, the protocol set on it for `protocol` and the float precision
+    /// for `precision`, to tell the connections and their settings apart, and else behaves like
+    /// [`FakeDB`].
+    struct SessionDB {
+        name: &'static str,
+        precision: Option<usize>,
+        protocol: Option<Protocol>,
+    }
+
+    impl SessionDB {
+        fn new(name: &'static str) -> Self {
+            SessionDB {
+                name,
+                precision: None,
+                protocol: None,
+            }
+        }
+    }
+
+    impl DB for SessionDB {
+        type Error = FakeDBError;
+
+        fn run(&mut self, sql: &str) -> Result<DBOutput, FakeDBError> {
+            let value = match sql {
+                "name" => self.name.to_string(),
+                "protocol" => format!("{:?}", self.protocol),
+                "precision" => format!("{:?}", self.precision),
+                _ => return DB::run(&mut FakeDB, sql),
+            };
+            Ok(DBOutput::Rows {
+                names: vec![],
+                types: vec![ColumnType::Text],
+                rows: vec![vec![value]],
+            })
+        }
+
+        fn set_float_precision(&mut self, precision: Option<usize>) {
+            self.precision = precision;
+        }
+
+        fn set_protocol(&mut self, protocol: Protocol) {
+            self.protocol = Some(protocol);
+        }
+    }
+
+    #[test]
+    fn test_file_state() {
+        // Each record sets state which changes the outcome of the check after it, and which is
+        // reset in the next script, where the check has the same outcome as in a new runner.
+        let cases = [
+            ("halt\n", "statement ok\nfail\n"),
+            ("require json\n", "statement ok\nfail\n"),
+            ("control normalize 2 x\n", "query I\nselect\n----\nx\n"),
+            ("let $x = fail\n", "statement error\n$x\n"),
+            (
+                "control resultmode valuewise\n",
+                "query II\nselect 1, 2\n----\n1\n2\n",
+            ),
+            (
+                "control substitution off\n",
+                "let $x = fail\n\nstatement ok\n$x\n",
+            ),
+            ("hash-threshold 1\n", "query II\nselect 1, 2\n----\n1 3\n"),
+            ("connection con1\n", "query T\nname\n----\ncon1\n"),
+            (
+                "control float_precision 3\n",
+                "query T\nprecision\n----\nSome(3)\n",
+            ),
+            (
+                "control protocol simple\n",
+                "query T\nprotocol\n----\nSome(Simple)\n",
+            ),
+        ];
+        let new_runner = || {
+            let mut runner = Runner::new(SessionDB::new("default"));
+            runner.set_dialect(Dialect::DuckDb);
+            runner.set_float_precision(Some(5));
+            runner.set_protocol(Protocol::Extended);
+            runner.set_connection_factory(|| async { Ok(SessionDB::new("con1")) });
+            runner
+        };
+        // The error without its location, which differs between the scripts.
+        let outcome = |result: Result<RunReport, TestError>| {
+            result.map(|_| ()).map_err(|err| err.kind().to_string())
+        };
+        for (record, check) in cases {
+            let expected = outcome(new_runner().run_script(check));
+            let mut runner = new_runner();
+            let after_record = outcome(runner.run_script(&format!("{record}\n{check}")));
+            assert_ne!(after_record, expected, "{record}");
+            assert_eq!(outcome(runner.run_script(check)), expected, "{record}");
+        }
+    }
+
+    #[test]
+    fn test_float_precision_warnings() {
+        let mut runner = Runner::new(SessionDB::new("default"));
+        runner.set_protocol(Protocol::Extended);
+        // A float precision is not applied with the simple protocol.
+        let report = runner
+            .run_script(
+                "control float_precision 3\ncontrol protocol simple\ncontrol protocol extended\n",
+            )
+            .unwrap();
+        let warnings = report
+            .warnings
+            .iter()
+            .map(ToString::to_string)
+            .collect_vec();
+        assert_eq!(
+            warnings,
+            ["<unknown>:2:1: float precision is not applied with the simple protocol"]
+        );
+        // Restoring the defaults of the next script is not warned about.
+        runner.set_float_precision(Some(3));
+        let report = runner.run_script("control protocol simple\n").unwrap();
+        assert_eq!(report.warnings.len(), 1);
+        let report = runner.run_script("").unwrap();
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_write_overrides() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("test.slt");
+        let script = "\
+query I
+select 2
+----
+1
+
+query I
+select 2
+
+statement ok
+fail
+";
+        std::fs::write(&path, script).unwrap();
+        let mut runner = Runner::new(FakeDB);
+        runner.enable_override();
+        // Records run one by one keep their overrides until they are written, even on failure.
+        runner.run_multi(parse_file(&path).unwrap()).unwrap_err();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), script);
+        runner.write_overrides().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "\
+query I
+select 2
+----
+2
+
+query I
+select 2
+----
+2
+
+statement ok
+fail
+"
+        );
+
+        // The results are added after the last line, even without a newline.
+        std::fs::write(&path, "query I\nselect 2").unwrap();
+        let mut runner = Runner::new(FakeDB);
+        runner.enable_override();
+        runner.run_file(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "query I\nselect 2\n----\n2\n"
+        );
+    }
+}
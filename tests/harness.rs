@@ -1,3 +1,5 @@
+use sqllogictest::{ColumnType, DBOutput};
+
 sqllogictest::harness!(FakeDB::new, "slt/**/*.slt");
 
 pub struct FakeDB;
@@ -22,7 +24,11 @@ impl std::error::Error for FakeDBError {}
 impl sqllogictest::DB for FakeDB {
     type Error = FakeDBError;
 
-    fn run(&mut self, _sql: &str) -> Result<String, FakeDBError> {
-        Ok("I'm fake.".into())
+    fn run(&mut self, _sql: &str) -> Result<DBOutput, FakeDBError> {
+        Ok(DBOutput::Rows {
+            names: vec![],
+            types: vec![ColumnType::Text],
+            rows: vec![vec!["I'm fake.".to_string()]],
+        })
     }
 }